@@ -0,0 +1,391 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{Mutex, mpsc};
+use tokio::time::{Duration, timeout};
+
+use crate::engine::stderr::StderrLog;
+
+/// KataGo JSON 分析查询（analysis 模式，一行一条）；id 由客户端分配
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisQuery {
+    /// 着手序列，如 `[["B","Q16"],["W","D4"]]`
+    pub moves: Vec<(String, String)>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub initial_stones: Vec<(String, String)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_player: Option<String>,
+    pub rules: String,
+    pub komi: f32,
+    pub board_x_size: u32,
+    pub board_y_size: u32,
+    /// 需要分析的手数（0 表示初始局面）；为空时只分析最后局面
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analyze_turns: Option<Vec<u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_visits: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub include_ownership: bool,
+    /// 搜索过程中按该间隔（秒）推送中间结果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_during_search_every: Option<f32>,
+}
+
+impl AnalysisQuery {
+    fn expected_responses(&self) -> usize {
+        self.analyze_turns
+            .as_ref()
+            .map(|turns| turns.len().max(1))
+            .unwrap_or(1)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisResponse {
+    pub id: String,
+    pub turn_number: u32,
+    #[serde(default)]
+    pub is_during_search: bool,
+    #[serde(default)]
+    pub move_infos: Vec<MoveInfo>,
+    pub root_info: RootInfo,
+    #[serde(default)]
+    pub ownership: Option<Vec<f32>>,
+}
+
+impl AnalysisResponse {
+    /// 首选着手（order 最小者）
+    pub fn best_move(&self) -> Option<&MoveInfo> {
+        self.move_infos.iter().min_by_key(|m| m.order)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveInfo {
+    #[serde(rename = "move")]
+    pub mv: String,
+    pub visits: u32,
+    pub winrate: f32,
+    pub score_lead: f32,
+    #[serde(default)]
+    pub prior: f32,
+    #[serde(default)]
+    pub order: u32,
+    #[serde(default)]
+    pub pv: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootInfo {
+    pub winrate: f32,
+    pub score_lead: f32,
+    pub visits: u32,
+    #[serde(default)]
+    pub current_player: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AnalysisError {
+    #[error("katago rejected query: {0}")]
+    Rejected(String),
+    #[error("analysis engine exited")]
+    Closed,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+type Pending = Arc<StdMutex<HashMap<String, PendingQuery>>>;
+
+struct PendingQuery {
+    remaining: usize,
+    tx: mpsc::UnboundedSender<Result<AnalysisResponse, AnalysisError>>,
+}
+
+/// KataGo analysis 引擎客户端：单进程并发查询，按 id 分发响应
+pub struct AnalysisEngine {
+    pid: Option<u32>,
+    child: Mutex<Child>,
+    stdin: Mutex<Option<ChildStdin>>, // quit 时取走以关闭管道
+    pending: Pending,
+    next_id: AtomicU64,
+    stderr: Arc<StderrLog>,
+}
+
+impl std::fmt::Debug for AnalysisEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AnalysisEngine")
+            .field("pid", &self.pid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AnalysisEngine {
+    /// 启动 katago analysis 进程并开始后台读取响应
+    pub async fn start(cmd_path: &str, args: &[String]) -> Result<Arc<Self>> {
+        let mut cmd = Command::new(cmd_path);
+        cmd.args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut child = cmd.spawn().context("failed to spawn katago analysis")?;
+        let pid = child.id();
+        if let Some(id) = pid {
+            tracing::info!(pid=%id, "katago analysis spawned");
+        }
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("failed to open stdin"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("failed to open stdout"))?;
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| anyhow!("failed to open stderr"))?;
+        let stderr = StderrLog::capture(stderr, pid, "analysis");
+
+        let pending: Pending = Arc::new(StdMutex::new(HashMap::new()));
+        tokio::spawn(read_responses(stdout, pending.clone()));
+
+        Ok(Arc::new(Self {
+            pid,
+            child: Mutex::new(child),
+            stdin: Mutex::new(Some(stdin)),
+            pending,
+            next_id: AtomicU64::new(1),
+            stderr,
+        }))
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    pub fn stderr(&self) -> &Arc<StderrLog> {
+        &self.stderr
+    }
+
+    /// 提交查询，返回逐条响应（含搜索中间结果）；所有分析手数完成后通道关闭
+    pub async fn submit(&self, query: &AnalysisQuery) -> Result<AnalysisStream, AnalysisError> {
+        let id = format!("q{}", self.next_id.fetch_add(1, Ordering::SeqCst));
+        let mut value = serde_json::to_value(query).map_err(std::io::Error::other)?;
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert("id".to_string(), id.clone().into());
+        }
+        let mut line = value.to_string();
+        line.push('\n');
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        lock_pending(&self.pending).insert(
+            id.clone(),
+            PendingQuery {
+                remaining: query.expected_responses(),
+                tx,
+            },
+        );
+
+        let mut guard = self.stdin.lock().await;
+        let written = match guard.as_mut() {
+            Some(stdin) => {
+                async {
+                    stdin.write_all(line.as_bytes()).await?;
+                    stdin.flush().await
+                }
+                .await
+            }
+            None => Err(std::io::ErrorKind::BrokenPipe.into()),
+        };
+        if let Err(err) = written {
+            lock_pending(&self.pending).remove(&id);
+            return Err(if err.kind() == std::io::ErrorKind::BrokenPipe {
+                AnalysisError::Closed
+            } else {
+                err.into()
+            });
+        }
+        Ok(AnalysisStream { rx })
+    }
+
+    /// 提交查询并等待全部最终结果，按手数排序
+    pub async fn analyze(
+        &self,
+        query: &AnalysisQuery,
+    ) -> Result<Vec<AnalysisResponse>, AnalysisError> {
+        let mut stream = self.submit(query).await?;
+        let mut results = Vec::new();
+        while let Some(item) = stream.next().await {
+            let resp = item?;
+            if !resp.is_during_search {
+                results.push(resp);
+            }
+        }
+        results.sort_by_key(|r| r.turn_number);
+        Ok(results)
+    }
+
+    /// 关闭 stdin 让 KataGo 处理完后退出；超时则强杀
+    pub async fn quit(&self) -> Result<()> {
+        drop(self.stdin.lock().await.take());
+        let mut child = self.child.lock().await;
+        if timeout(Duration::from_secs(3), child.wait()).await.is_err() {
+            let _ = child.kill().await;
+            let _ = child.wait().await;
+            tracing::warn!(pid=?self.pid, "katago analysis killed after timeout");
+        }
+        Ok(())
+    }
+}
+
+/// 单个查询的响应流
+pub struct AnalysisStream {
+    rx: mpsc::UnboundedReceiver<Result<AnalysisResponse, AnalysisError>>,
+}
+
+impl AnalysisStream {
+    pub async fn next(&mut self) -> Option<Result<AnalysisResponse, AnalysisError>> {
+        self.rx.recv().await
+    }
+}
+
+fn lock_pending(pending: &Pending) -> std::sync::MutexGuard<'_, HashMap<String, PendingQuery>> {
+    pending.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+async fn read_responses(stdout: ChildStdout, pending: Pending) {
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!(?err, "failed to read katago analysis output");
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        dispatch_line(&line, &pending);
+    }
+    // 进程退出：所有未完成查询以 Closed 结束
+    for (_, query) in lock_pending(&pending).drain() {
+        let _ = query.tx.send(Err(AnalysisError::Closed));
+    }
+}
+
+fn dispatch_line(line: &str, pending: &Pending) {
+    let value: serde_json::Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(err) => {
+            tracing::warn!(?err, line, "unparseable katago analysis line");
+            return;
+        }
+    };
+    let Some(id) = value.get("id").and_then(|v| v.as_str()).map(str::to_string) else {
+        tracing::warn!(line, "katago analysis message without id");
+        return;
+    };
+    if let Some(warning) = value.get("warning").and_then(|v| v.as_str()) {
+        tracing::warn!(id, warning, "katago analysis warning");
+        return;
+    }
+
+    let mut map = lock_pending(pending);
+    if let Some(error) = value.get("error").and_then(|v| v.as_str()) {
+        match map.remove(&id) {
+            Some(query) => {
+                let _ = query
+                    .tx
+                    .send(Err(AnalysisError::Rejected(error.to_string())));
+            }
+            None => tracing::warn!(id, error, "katago analysis error"),
+        }
+        return;
+    }
+    // terminate 等动作的回执没有分析结果
+    if value.get("rootInfo").is_none() {
+        return;
+    }
+    let Some(query) = map.get_mut(&id) else {
+        return;
+    };
+    match serde_json::from_value::<AnalysisResponse>(value) {
+        Ok(resp) => {
+            let finished = !resp.is_during_search;
+            let _ = query.tx.send(Ok(resp));
+            if finished {
+                query.remaining = query.remaining.saturating_sub(1);
+                if query.remaining == 0 {
+                    map.remove(&id);
+                }
+            }
+        }
+        Err(err) => {
+            tracing::warn!(?err, id, "unexpected katago analysis response shape");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_serializes_in_katago_format() {
+        let query = AnalysisQuery {
+            moves: vec![("B".to_string(), "Q16".to_string())],
+            rules: "chinese".to_string(),
+            komi: 7.5,
+            board_x_size: 19,
+            board_y_size: 19,
+            analyze_turns: Some(vec![0, 1]),
+            ..Default::default()
+        };
+        let v = serde_json::to_value(&query).unwrap();
+        assert_eq!(v["moves"], serde_json::json!([["B", "Q16"]]));
+        assert_eq!(v["boardXSize"], 19);
+        assert_eq!(v["analyzeTurns"], serde_json::json!([0, 1]));
+        assert!(v.get("initialStones").is_none());
+        assert!(v.get("includeOwnership").is_none());
+    }
+
+    // 用 sh 模拟 analysis 引擎：对每条查询回显其 id，返回一条固定结果
+    #[tokio::test]
+    async fn routes_responses_by_id() {
+        let script = r#"while read line; do
+id=$(printf '%s' "$line" | sed 's/.*"id":"\([^"]*\)".*/\1/')
+printf '{"id":"%s","turnNumber":3,"isDuringSearch":false,"moveInfos":[{"move":"D4","visits":10,"winrate":0.6,"scoreLead":1.5,"order":0,"pv":["D4","Q16"]}],"rootInfo":{"winrate":0.55,"scoreLead":1.0,"visits":12,"currentPlayer":"B"}}\n' "$id"
+done"#;
+        let engine = AnalysisEngine::start("sh", &["-c".to_string(), script.to_string()])
+            .await
+            .expect("spawn fake analysis engine");
+        let query = AnalysisQuery {
+            rules: "chinese".to_string(),
+            komi: 7.5,
+            board_x_size: 19,
+            board_y_size: 19,
+            ..Default::default()
+        };
+        let (a, b) = tokio::join!(engine.analyze(&query), engine.analyze(&query));
+        let (a, b) = (a.expect("first"), b.expect("second"));
+        assert_ne!(a[0].id, b[0].id);
+        assert_eq!(a[0].turn_number, 3);
+        let best = a[0].best_move().expect("best move");
+        assert_eq!(best.mv, "D4");
+        assert_eq!(best.pv, vec!["D4", "Q16"]);
+        engine.quit().await.unwrap();
+    }
+}
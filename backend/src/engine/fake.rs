@@ -0,0 +1,182 @@
+use crate::engine::gtp::EngineError;
+use crate::engine::pool::BoardSetup;
+use crate::engine::protocol::{
+    AnalyzeInfo, Color, GenMove, Score, TimeControl, Vertex, fixed_handicap_vertices,
+};
+use crate::engine::{AnalyzeProgress, GoEngine};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// 进程内确定性假引擎：未配置 KataGo 时的兜底，也用于测试。
+/// 只记录落子与让子占位（不提子）；genmove 优先按脚本应答，否则取自左上起第一个空点
+#[derive(Debug)]
+pub struct FakeEngine {
+    board_size: u32,
+    komi: f32,
+    state: Mutex<FakeState>,
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    handicap: Vec<Vertex>,
+    history: Vec<(Color, Vertex)>,
+    script: VecDeque<GenMove>,
+}
+
+impl FakeState {
+    fn occupied(&self, vertex: Vertex) -> bool {
+        vertex != Vertex::Pass
+            && (self.handicap.contains(&vertex) || self.history.iter().any(|(_, v)| *v == vertex))
+    }
+
+    fn first_empty(&self, board_size: u32) -> Vertex {
+        (1..=board_size)
+            .rev()
+            .flat_map(|row| (0..board_size).map(move |col| Vertex::Point { col, row }))
+            .find(|v| !self.occupied(*v))
+            .unwrap_or(Vertex::Pass)
+    }
+
+    fn next_move(&self, board_size: u32) -> GenMove {
+        self.script
+            .front()
+            .copied()
+            .unwrap_or_else(|| GenMove::Play(self.first_empty(board_size)))
+    }
+}
+
+impl FakeEngine {
+    pub fn new(setup: BoardSetup) -> Self {
+        Self {
+            board_size: setup.board_size,
+            komi: setup.komi,
+            state: Mutex::new(FakeState::default()),
+        }
+    }
+
+    /// 预置 genmove 的应答，按顺序消耗；用完后回到默认策略
+    pub fn with_script(self, moves: impl IntoIterator<Item = GenMove>) -> Self {
+        self.lock().script.extend(moves);
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn on_board(&self, vertex: Vertex) -> bool {
+        match vertex {
+            Vertex::Pass => true,
+            Vertex::Point { col, row } => {
+                col < self.board_size && row >= 1 && row <= self.board_size
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl GoEngine for FakeEngine {
+    async fn play(&self, color: Color, vertex: Vertex) -> Result<(), EngineError> {
+        let mut state = self.lock();
+        if !self.on_board(vertex) || state.occupied(vertex) {
+            return Err(EngineError::Gtp("illegal move".to_string()));
+        }
+        state.history.push((color, vertex));
+        Ok(())
+    }
+
+    async fn genmove(&self, color: Color) -> Result<GenMove, EngineError> {
+        let mut state = self.lock();
+        let mv = state.next_move(self.board_size);
+        state.script.pop_front();
+        if let GenMove::Play(vertex) = mv {
+            state.history.push((color, vertex));
+        }
+        Ok(mv)
+    }
+
+    async fn undo(&self) -> Result<(), EngineError> {
+        match self.lock().history.pop() {
+            Some(_) => Ok(()),
+            None => Err(EngineError::Gtp("cannot undo".to_string())),
+        }
+    }
+
+    // 自由让子也按固定位置摆放
+    async fn place_handicap(&self, stones: u32, _free: bool) -> Result<Vec<Vertex>, EngineError> {
+        let vertices = fixed_handicap_vertices(self.board_size, stones)
+            .ok_or_else(|| EngineError::Gtp("invalid handicap".to_string()))?;
+        self.set_handicap(&vertices).await?;
+        Ok(vertices)
+    }
+
+    async fn set_handicap(&self, stones: &[Vertex]) -> Result<(), EngineError> {
+        self.lock().handicap = stones.to_vec();
+        Ok(())
+    }
+
+    // 不计时：用时由服务端的棋钟裁定
+    async fn time_settings(&self, _control: &TimeControl) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    async fn time_left(
+        &self,
+        _color: Color,
+        _seconds: u32,
+        _stones: u32,
+    ) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    // 只给出一个候选点（即 genmove 将下的点），访问数直接记为 max_visits
+    async fn analyze(
+        &self,
+        _color: Color,
+        max_visits: u32,
+        on_update: AnalyzeProgress<'_>,
+    ) -> Result<Vec<AnalyzeInfo>, EngineError> {
+        let mv = match self.lock().next_move(self.board_size) {
+            GenMove::Play(vertex) => vertex,
+            GenMove::Resign => Vertex::Pass,
+        };
+        let infos = vec![AnalyzeInfo {
+            mv,
+            visits: max_visits,
+            winrate: 0.5,
+            score_lead: 0.0,
+            pv: vec![mv],
+        }];
+        on_update(&infos);
+        Ok(infos)
+    }
+
+    // 数子：盘上黑子数 - 白子数 - 贴目
+    async fn final_score(&self) -> Result<Score, EngineError> {
+        let state = self.lock();
+        let count = |color: Color| {
+            state
+                .history
+                .iter()
+                .filter(|(c, v)| *c == color && *v != Vertex::Pass)
+                .count() as f32
+        };
+        let lead =
+            state.handicap.len() as f32 + count(Color::Black) - count(Color::White) - self.komi;
+        Ok(if lead > 0.0 {
+            Score::Black(lead)
+        } else if lead < 0.0 {
+            Score::White(-lead)
+        } else {
+            Score::Draw
+        })
+    }
+
+    async fn dead_stones(&self) -> Result<Vec<Vertex>, EngineError> {
+        Ok(Vec::new())
+    }
+
+    async fn quit(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::{Context, Result, anyhow};
+use std::future::Future;
 use std::process::Stdio;
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
@@ -6,25 +7,159 @@ use tokio::process::{Child, ChildStdin, ChildStdout, Command};
 use tokio::sync::Mutex;
 use tokio::time::{Duration, timeout};
 
+use crate::engine::protocol::{
+    self, AnalyzeInfo, Color, GenMove, GtpResponse, ParseError, Score, TimeControl, Vertex,
+};
+use crate::engine::stderr::StderrLog;
+use crate::engine::{AnalyzeProgress, GoEngine};
+
+/// kata-analyze 的输出间隔（厘秒）
+const ANALYZE_INTERVAL_CENTIS: u32 = 25;
+
+/// 启动失败或崩溃时附带的 stderr 行数
+const STDERR_TAIL_LINES: usize = 20;
+
+/// 引擎通信错误：区分进程退出/管道断开（需重启）与普通 GTP 失败
+#[derive(Debug, thiserror::Error)]
+pub enum EngineError {
+    #[error("engine process {pid:?} exited ({status})")]
+    Exited { pid: Option<u32>, status: String },
+    #[error("engine pipe broken: {0}")]
+    BrokenPipe(#[source] std::io::Error),
+    #[error("gtp error: {0}")]
+    Gtp(String),
+    #[error("gtp protocol out of sync: {0}")]
+    Protocol(String),
+    #[error("gtp command `{cmd}` timed out after {after:?}")]
+    Timeout { cmd: String, after: Duration },
+    #[error(transparent)]
+    Io(std::io::Error),
+}
+
+impl EngineError {
+    /// 进程已不可用，只能重启
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            EngineError::Exited { .. } | EngineError::BrokenPipe(_) | EngineError::Protocol(_)
+        )
+    }
+
+    fn from_write(err: std::io::Error) -> Self {
+        if err.kind() == std::io::ErrorKind::BrokenPipe {
+            EngineError::BrokenPipe(err)
+        } else {
+            EngineError::Io(err)
+        }
+    }
+}
+
+/// 各类命令的响应时限；超时后命令被放弃，残留响应在下一条命令前读掉
+#[derive(Clone, Debug)]
+pub struct CommandTimeouts {
+    pub default: Duration,
+    pub genmove: Duration, // genmove 等需要搜索的命令，也用作清理残留响应的时限
+    pub scoring: Duration, // final_score / final_status_list
+    pub load: Duration,    // loadsgf
+    pub startup: Duration, // 启动握手（含模型加载）
+}
+
+impl Default for CommandTimeouts {
+    fn default() -> Self {
+        Self {
+            default: Duration::from_secs(10),
+            genmove: Duration::from_secs(60),
+            scoring: Duration::from_secs(30),
+            load: Duration::from_secs(20),
+            startup: Duration::from_secs(120),
+        }
+    }
+}
+
+impl CommandTimeouts {
+    pub fn for_command(&self, cmd: &str) -> Duration {
+        let name = cmd.split_whitespace().next().unwrap_or("");
+        match name {
+            "genmove" | "reg_genmove" | "kata-genmove_analyze" | "kata-search" => self.genmove,
+            "final_score" | "final_status_list" => self.scoring,
+            "loadsgf" => self.load,
+            _ => self.default,
+        }
+    }
+}
+
+/// stdout 读取状态：读取被取消时已读字节留在 partial 中；
+/// outstanding 记录已发送但尚未读完响应的命令数，analyzing 表示 kata-analyze 仍在输出；
+/// next_id 为下一条命令的编号（命令与响应均经此锁串行，无需原子计数）
+#[derive(Debug)]
+struct ResponseReader {
+    inner: BufReader<ChildStdout>,
+    partial: Vec<u8>,
+    outstanding: usize,
+    analyzing: bool,
+    next_id: u32,
+}
+
+impl ResponseReader {
+    fn take_id(&mut self) -> u32 {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.next_id
+    }
+}
+
+impl ResponseReader {
+    /// 读取一整行（可安全取消）；EOF 时返回 None
+    async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        if self.inner.read_until(b'\n', &mut self.partial).await? == 0 {
+            return Ok(None);
+        }
+        let line = String::from_utf8_lossy(&self.partial).into_owned();
+        self.partial.clear();
+        Ok(Some(line))
+    }
+
+    /// 读取一条完整响应（直到空行）
+    async fn read_response(&mut self) -> std::io::Result<Option<String>> {
+        let mut acc = String::new();
+        loop {
+            match self.next_line().await? {
+                None => return Ok(None),
+                Some(line) if line.trim().is_empty() => break, // 响应结束
+                Some(line) => acc.push_str(&line),
+            }
+        }
+        self.outstanding = self.outstanding.saturating_sub(1);
+        Ok(Some(acc))
+    }
+}
+
 /// 简化的 GTP 引擎实例：提供最基本的命令往返
 #[derive(Debug)]
 pub struct GtpEngine {
+    pid: Option<u32>,
+    timeouts: CommandTimeouts,
     child: Mutex<Child>,
     stdin: Mutex<ChildStdin>,
-    stdout: Mutex<BufReader<ChildStdout>>, // 顺序读取响应
+    stdout: Mutex<ResponseReader>, // 顺序读取响应
+    stderr: Arc<StderrLog>,
 }
 
 impl GtpEngine {
-    /// 启动 kataGo gtp 进程
-    pub async fn start(cmd_path: &str, args: &[String]) -> Result<Arc<Self>> {
+    /// 启动 kataGo gtp 进程并完成握手；启动失败时错误中附带 stderr 末尾
+    pub async fn start(
+        cmd_path: &str,
+        args: &[String],
+        timeouts: CommandTimeouts,
+    ) -> Result<Arc<Self>> {
         let mut cmd = Command::new(cmd_path);
         cmd.args(args)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::inherit());
+            .stderr(Stdio::piped());
 
         let mut child = cmd.spawn().context("failed to spawn katago gtp")?;
-        if let Some(id) = child.id() {
+        let pid = child.id();
+        if let Some(id) = pid {
             tracing::info!(pid=%id, "katago spawned");
         }
         let stdin = child
@@ -35,49 +170,238 @@ impl GtpEngine {
             .stdout
             .take()
             .ok_or_else(|| anyhow!("failed to open stdout"))?;
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| anyhow!("failed to open stderr"))?;
+        let stderr = StderrLog::capture(stderr, pid, "gtp");
 
         let engine = Arc::new(Self {
+            pid,
+            timeouts,
             child: Mutex::new(child),
             stdin: Mutex::new(stdin),
-            stdout: Mutex::new(BufReader::new(stdout)),
+            stdout: Mutex::new(ResponseReader {
+                inner: BufReader::new(stdout),
+                partial: Vec::new(),
+                outstanding: 0,
+                analyzing: false,
+                next_id: 0,
+            }),
+            stderr,
         });
 
-        // 简单握手：version（可选）
-        // let _ = engine.send_command("version").await?;
+        // 握手：KataGo 加载完模型后才会应答
+        let limit = engine.timeouts.startup;
+        if let Err(err) = engine.request("protocol_version", limit).await {
+            let _ = engine.child.lock().await.kill().await;
+            engine.stderr.settle().await;
+            return Err(anyhow!(
+                "katago failed to start: {err}; stderr tail:\n{}",
+                engine.stderr.tail_text(STDERR_TAIL_LINES)
+            ));
+        }
         Ok(engine)
     }
 
-    /// 发送单条 GTP 命令并读取响应（以 \n\n 结束）
-    pub async fn send_command(self: &Arc<Self>, cmd: &str) -> Result<String> {
+    /// 子进程 pid（启动失败或平台不支持时为 None）
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    pub fn stderr(&self) -> &Arc<StderrLog> {
+        &self.stderr
+    }
+
+    /// 标记当前占用者（game:<id> / review:<id>），None 表示空闲
+    pub fn set_owner(&self, owner: Option<String>) {
+        self.stderr.set_owner(owner);
+    }
+
+    /// 子进程是否已退出（正忙时视为存活）
+    pub fn has_exited(&self) -> bool {
+        match self.child.try_lock() {
+            Ok(mut child) => !matches!(child.try_wait(), Ok(None)),
+            Err(_) => false,
+        }
+    }
+
+    /// 发送带编号的 GTP 命令（"12 genmove B"）并读取响应，校验回显编号；
+    /// 超过该类命令时限返回 Timeout。GTP 层面的失败以 GtpResponse::Failure 返回
+    pub async fn send_command(&self, cmd: &str) -> Result<GtpResponse, EngineError> {
+        self.request(cmd, self.timeouts.for_command(cmd)).await
+    }
+
+    async fn request(&self, cmd: &str, limit: Duration) -> Result<GtpResponse, EngineError> {
         let mut stdin = self.stdin.lock().await;
         let mut stdout = self.stdout.lock().await;
 
-        let line = format!("{}\n", cmd);
-        stdin.write_all(line.as_bytes()).await?;
-        stdin.flush().await?;
+        self.resync(&mut stdin, &mut stdout).await?;
+        let id = stdout.take_id();
+        self.write_line(&mut stdin, &format!("{id} {cmd}")).await?;
+        stdout.outstanding += 1;
 
-        // 读取直到空行
-        let mut buf = String::new();
-        let mut acc = String::new();
-        loop {
-            buf.clear();
-            let n = stdout.read_line(&mut buf).await?;
-            if n == 0 {
-                break; // EOF
+        let raw = match timeout(limit, stdout.read_response()).await {
+            Ok(Ok(Some(raw))) => raw,
+            Ok(Ok(None)) => return Err(self.exit_error().await), // EOF：进程已退出
+            Ok(Err(err)) => return Err(EngineError::Io(err)),
+            Err(_) => {
+                tracing::warn!(pid=?self.pid, cmd, ?limit, "gtp command timed out");
+                return Err(EngineError::Timeout {
+                    cmd: cmd.to_string(),
+                    after: limit,
+                });
             }
-            if buf.trim().is_empty() {
-                break; // 响应结束
+        };
+        match GtpResponse::parse(&raw) {
+            Ok((Some(echoed), resp)) if echoed == id => Ok(resp),
+            Ok((echoed, _)) => Err(EngineError::Protocol(format!(
+                "`{cmd}` sent as id {id}, response echoed {echoed:?}"
+            ))),
+            Err(msg) => Err(EngineError::Protocol(msg)),
+        }
+    }
+
+    /// 发送命令并要求成功，返回成功负载
+    pub async fn expect_success(&self, cmd: &str) -> Result<String, EngineError> {
+        self.send_command(cmd).await?.into_result()
+    }
+
+    /// 引擎是否支持某条命令（known_command）
+    pub async fn known_command(&self, name: &str) -> Result<bool, EngineError> {
+        let body = self
+            .expect_success(&format!("known_command {name}"))
+            .await?;
+        protocol::parse_bool(&body).map_err(unparseable)
+    }
+
+    /// 读掉此前超时/被取消命令的残留响应，使协议重新同步；引擎在时限内仍无响应则强杀
+    async fn resync(
+        &self,
+        stdin: &mut ChildStdin,
+        stdout: &mut ResponseReader,
+    ) -> Result<(), EngineError> {
+        if stdout.analyzing {
+            let id = stdout.take_id();
+            self.write_line(stdin, &format!("{id} protocol_version"))
+                .await?;
+            stdout.outstanding += 1;
+            stdout.analyzing = false;
+        }
+        while stdout.outstanding > 0 {
+            match timeout(self.timeouts.genmove, stdout.read_response()).await {
+                Ok(Ok(Some(_))) => {}
+                Ok(Ok(None)) => return Err(self.exit_error().await),
+                Ok(Err(err)) => return Err(EngineError::Io(err)),
+                Err(_) => return Err(self.kill_unresponsive().await),
             }
-            acc.push_str(&buf);
         }
-        if acc.starts_with('?') {
-            return Err(anyhow!("gtp error: {}", acc.trim()));
+        Ok(())
+    }
+
+    async fn write_line(&self, stdin: &mut ChildStdin, cmd: &str) -> Result<(), EngineError> {
+        let line = format!("{}\n", cmd);
+        let written = async {
+            stdin.write_all(line.as_bytes()).await?;
+            stdin.flush().await
+        }
+        .await;
+        match written {
+            Ok(()) => Ok(()),
+            Err(err) => match EngineError::from_write(err) {
+                EngineError::BrokenPipe(_) if self.has_exited() => Err(self.exit_error().await),
+                other => Err(other),
+            },
+        }
+    }
+
+    /// 读到 EOF 或写入失败后，等待片刻收集退出状态
+    async fn exit_error(&self) -> EngineError {
+        let mut child = self.child.lock().await;
+        let status = match timeout(Duration::from_millis(500), child.wait()).await {
+            Ok(Ok(status)) => status.to_string(),
+            _ => "unknown status".to_string(),
+        };
+        drop(child);
+        self.stderr.settle().await;
+        tracing::error!(
+            pid = ?self.pid,
+            owner = ?self.stderr.owner(),
+            status,
+            stderr = %self.stderr.tail_text(STDERR_TAIL_LINES),
+            "katago exited unexpectedly"
+        );
+        EngineError::Exited {
+            pid: self.pid,
+            status,
+        }
+    }
+
+    async fn kill_unresponsive(&self) -> EngineError {
+        let mut child = self.child.lock().await;
+        let _ = child.kill().await;
+        tracing::error!(pid=?self.pid, "katago unresponsive, killed");
+        EngineError::Exited {
+            pid: self.pid,
+            status: "killed after becoming unresponsive".to_string(),
+        }
+    }
+
+    /// 流式分析：发送 kata-analyze 类命令后逐行回调 info 输出。
+    /// on_info 返回 false 或 stop 完成时，发送一条无副作用命令打断分析，
+    /// 并读完分析响应与该命令响应，保证后续命令的协议同步。
+    pub async fn analyze_stream<F, S>(
+        &self,
+        cmd: &str,
+        mut on_info: F,
+        stop: S,
+    ) -> Result<(), EngineError>
+    where
+        F: FnMut(&str) -> bool,
+        S: Future<Output = ()>,
+    {
+        let mut stdin = self.stdin.lock().await;
+        let mut stdout = self.stdout.lock().await;
+
+        self.resync(&mut stdin, &mut stdout).await?;
+        let id = stdout.take_id();
+        self.write_line(&mut stdin, &format!("{id} {cmd}")).await?;
+        stdout.outstanding += 1;
+        stdout.analyzing = true;
+
+        tokio::pin!(stop);
+        loop {
+            tokio::select! {
+                _ = &mut stop => break,
+                line = stdout.next_line() => {
+                    let Some(line) = line.map_err(EngineError::Io)? else {
+                        return Err(self.exit_error().await);
+                    };
+                    let line = line.trim();
+                    if line.is_empty() {
+                        // 引擎自行结束了分析响应
+                        stdout.outstanding -= 1;
+                        stdout.analyzing = false;
+                        return Ok(());
+                    }
+                    if line.starts_with('?') {
+                        // 错误响应的结束空行留给下一次 resync 读掉
+                        stdout.analyzing = false;
+                        return Err(EngineError::Gtp(line.to_string()));
+                    }
+                    if line.starts_with("info") && !on_info(line) {
+                        break;
+                    }
+                }
+            }
         }
-        Ok(acc)
+
+        // 任意新命令都会打断分析；KataGo 先以空行结束分析响应，再回答该命令
+        self.resync(&mut stdin, &mut stdout).await
     }
 
     /// 优雅退出并等待子进程结束；超时则强杀
-    pub async fn quit(self: &Arc<Self>) -> Result<()> {
+    pub async fn quit(&self) -> Result<()> {
         // 尝试优雅退出
         let _ = self.send_command("quit").await;
 
@@ -88,7 +412,7 @@ impl GtpEngine {
                 if let Some(id) = child.id() {
                     tracing::info!(pid=%id, "katago exited");
                 }
-                return Ok(());
+                Ok(())
             }
             Err(_) => {
                 // 超时，强制杀死
@@ -97,8 +421,274 @@ impl GtpEngine {
                 if let Some(id) = child.id() {
                     tracing::warn!(pid=%id, "katago killed after timeout");
                 }
-                return Ok(());
+                Ok(())
             }
         }
     }
 }
+
+fn unparseable(err: ParseError) -> EngineError {
+    EngineError::Gtp(err.to_string())
+}
+
+#[async_trait::async_trait]
+impl GoEngine for GtpEngine {
+    async fn play(&self, color: Color, vertex: Vertex) -> Result<(), EngineError> {
+        self.expect_success(&format!("play {color} {vertex}"))
+            .await
+            .map(drop)
+    }
+
+    async fn genmove(&self, color: Color) -> Result<GenMove, EngineError> {
+        let body = self.expect_success(&format!("genmove {color}")).await?;
+        body.parse().map_err(unparseable)
+    }
+
+    async fn undo(&self) -> Result<(), EngineError> {
+        self.expect_success("undo").await.map(drop)
+    }
+
+    async fn place_handicap(&self, stones: u32, free: bool) -> Result<Vec<Vertex>, EngineError> {
+        let cmd = if free {
+            "place_free_handicap"
+        } else {
+            "fixed_handicap"
+        };
+        let body = self.expect_success(&format!("{cmd} {stones}")).await?;
+        protocol::parse_vertex_list(&body).map_err(unparseable)
+    }
+
+    async fn set_handicap(&self, stones: &[Vertex]) -> Result<(), EngineError> {
+        let list: Vec<String> = stones.iter().map(Vertex::to_string).collect();
+        self.expect_success(&format!("set_free_handicap {}", list.join(" ")))
+            .await
+            .map(drop)
+    }
+
+    // 加拿大读秒用标准 time_settings，其余用 KataGo 扩展
+    async fn time_settings(&self, control: &TimeControl) -> Result<(), EngineError> {
+        let cmd = match *control {
+            TimeControl::Byoyomi {
+                main_time,
+                period_time,
+                periods,
+            } => format!("kata-time_settings byoyomi {main_time} {period_time} {periods}"),
+            TimeControl::Canadian {
+                main_time,
+                period_time,
+                stones,
+            } => format!("time_settings {main_time} {period_time} {stones}"),
+            TimeControl::Fischer {
+                main_time,
+                increment,
+            } => format!("kata-time_settings fischer {main_time} {increment}"),
+        };
+        self.expect_success(&cmd).await.map(drop)
+    }
+
+    async fn time_left(&self, color: Color, seconds: u32, stones: u32) -> Result<(), EngineError> {
+        self.expect_success(&format!("time_left {color} {seconds} {stones}"))
+            .await
+            .map(drop)
+    }
+
+    // 分析时长以 genmove 时限为上限，防止引擎迟迟达不到访问数
+    async fn analyze(
+        &self,
+        color: Color,
+        max_visits: u32,
+        on_update: AnalyzeProgress<'_>,
+    ) -> Result<Vec<AnalyzeInfo>, EngineError> {
+        let cmd = format!("kata-analyze {color} {ANALYZE_INTERVAL_CENTIS}");
+        let mut latest = Vec::new();
+        self.analyze_stream(
+            &cmd,
+            |line| {
+                let infos = protocol::parse_analyze_infos(line);
+                let Some(best) = infos.first() else {
+                    return true;
+                };
+                let reached = best.visits >= max_visits;
+                let keep_going = on_update(&infos) && !reached;
+                latest = infos;
+                keep_going
+            },
+            tokio::time::sleep(self.timeouts.genmove),
+        )
+        .await?;
+        Ok(latest)
+    }
+
+    async fn final_score(&self) -> Result<Score, EngineError> {
+        let body = self.expect_success("final_score").await?;
+        body.parse().map_err(unparseable)
+    }
+
+    async fn dead_stones(&self) -> Result<Vec<Vertex>, EngineError> {
+        let body = self.expect_success("final_status_list dead").await?;
+        protocol::parse_vertex_list(&body).map_err(unparseable)
+    }
+
+    async fn quit(&self) -> Result<()> {
+        GtpEngine::quit(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 用 bash 模拟 kata-analyze：持续输出 info，直到收到下一条命令（命令带编号）
+    const FAKE_ANALYZER: &str = r#"while read id line; do
+case "$line" in
+kata-analyze*)
+  echo "=$id"
+  until read -t 0.02 nid next; do
+    echo "info move D4 visits 10 winrate 0.5 scoreLead 1.0 pv D4 Q16"
+  done
+  echo ""
+  printf '=%s %s\n\n' "$nid" "$next"
+  ;;
+quit) printf '=%s\n\n' "$id"; exit ;;
+*) printf '=%s %s\n\n' "$id" "$line" ;;
+esac
+done"#;
+
+    #[tokio::test]
+    async fn interrupted_analysis_leaves_protocol_in_sync() {
+        let engine = GtpEngine::start(
+            "bash",
+            &["-c".to_string(), FAKE_ANALYZER.to_string()],
+            CommandTimeouts::default(),
+        )
+        .await
+        .expect("spawn fake engine");
+        let mut seen = 0;
+        engine
+            .analyze_stream(
+                "kata-analyze B 10",
+                |line| {
+                    assert!(line.starts_with("info move D4"));
+                    seen += 1;
+                    seen < 3
+                },
+                std::future::pending(),
+            )
+            .await
+            .expect("stream");
+        assert_eq!(seen, 3);
+
+        let resp = engine.send_command("name").await.expect("name");
+        assert_eq!(resp, GtpResponse::Success("name".to_string()));
+        engine.quit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn known_command_reports_supported_commands() {
+        let engine = GtpEngine::start(
+            "sh",
+            &[
+                "-c".to_string(),
+                "while read id cmd arg; do case \"$cmd $arg\" in \
+                 'known_command kata-time_settings') printf '=%s true\\n\\n' \"$id\" ;; \
+                 'known_command odd') printf '=%s maybe\\n\\n' \"$id\" ;; \
+                 known_command*) printf '=%s false\\n\\n' \"$id\" ;; \
+                 quit*) printf '=%s\\n\\n' \"$id\"; exit ;; \
+                 *) printf '=%s\\n\\n' \"$id\" ;; esac; done"
+                    .to_string(),
+            ],
+            CommandTimeouts::default(),
+        )
+        .await
+        .expect("spawn");
+        assert!(engine.known_command("kata-time_settings").await.unwrap());
+        assert!(!engine.known_command("kata-search").await.unwrap());
+        // 答非布尔值视为 GTP 层面的失败，不是致命错误
+        let err = engine.known_command("odd").await.unwrap_err();
+        assert!(!err.is_fatal(), "{err:?}");
+        engine.quit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn mismatched_response_id_is_fatal() {
+        let engine = GtpEngine::start(
+            "sh",
+            &[
+                "-c".to_string(),
+                // 握手正常应答，之后回显错误编号
+                "read id line; printf '=%s 2\\n\\n' \"$id\"; \
+                 while read id line; do printf '=999\\n\\n'; done"
+                    .to_string(),
+            ],
+            CommandTimeouts::default(),
+        )
+        .await
+        .expect("spawn");
+        let err = engine.send_command("name").await.unwrap_err();
+        assert!(matches!(err, EngineError::Protocol(_)), "{err:?}");
+        assert!(err.is_fatal());
+    }
+
+    #[tokio::test]
+    async fn dead_process_is_reported_as_fatal() {
+        let engine = GtpEngine::start(
+            "sh",
+            &[
+                "-c".to_string(),
+                "read id line; printf '=%s 2\\n\\n' \"$id\"; echo 'cuda error' >&2; exit 3"
+                    .to_string(),
+            ],
+            CommandTimeouts::default(),
+        )
+        .await
+        .expect("spawn");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let err = engine.send_command("genmove B").await.unwrap_err();
+        assert!(err.is_fatal(), "unexpected error: {err:?}");
+        assert!(engine.has_exited());
+        assert_eq!(engine.stderr().tail_text(5), "cuda error");
+    }
+
+    #[tokio::test]
+    async fn failed_start_reports_stderr_tail() {
+        let err = GtpEngine::start(
+            "sh",
+            &[
+                "-c".to_string(),
+                "echo 'loading model' >&2; echo 'model file not found' >&2; exit 1".to_string(),
+            ],
+            CommandTimeouts::default(),
+        )
+        .await
+        .unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("loading model\nmodel file not found"), "{msg}");
+    }
+
+    // 第一条 genmove 迟迟不答：超时后下一条命令应先读掉迟到的响应再正常往返
+    #[tokio::test]
+    async fn timed_out_command_does_not_desync_protocol() {
+        let script = r#"while read id line; do
+case "$line" in
+genmove*) sleep 0.15; printf '=%s D4\n\n' "$id" ;;
+quit) printf '=%s\n\n' "$id"; exit ;;
+*) printf '=%s %s\n\n' "$id" "$line" ;;
+esac
+done"#;
+        let timeouts = CommandTimeouts {
+            genmove: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let engine = GtpEngine::start("bash", &["-c".to_string(), script.to_string()], timeouts)
+            .await
+            .expect("spawn fake engine");
+        let err = engine.send_command("genmove B").await.unwrap_err();
+        assert!(matches!(err, EngineError::Timeout { .. }), "{err:?}");
+        assert!(!err.is_fatal());
+
+        // 残留响应在 genmove 时限内到达，被 resync 丢弃
+        let resp = engine.send_command("name").await.expect("name");
+        assert_eq!(resp, GtpResponse::Success("name".to_string()));
+        engine.quit().await.unwrap();
+    }
+}
@@ -1 +1,52 @@
+pub mod analysis;
+pub mod fake;
 pub mod gtp;
+pub mod native;
+pub mod pool;
+pub mod profile;
+pub mod protocol;
+pub mod stderr;
+
+use gtp::EngineError;
+use protocol::{AnalyzeInfo, Color, GenMove, Score, TimeControl, Vertex};
+
+/// 分析进度回调：每次收到一批候选点时调用，返回 false 提前结束分析
+pub type AnalyzeProgress<'a> = &'a mut (dyn FnMut(&[AnalyzeInfo]) -> bool + Send);
+
+/// 对局与复盘所需的引擎能力；KataGo（GTP）与进程内假引擎各实现一份。
+/// 非法着法等引擎拒绝的命令以 EngineError::Gtp 返回
+#[async_trait::async_trait]
+pub trait GoEngine: Send + Sync + std::fmt::Debug {
+    async fn play(&self, color: Color, vertex: Vertex) -> Result<(), EngineError>;
+
+    async fn genmove(&self, color: Color) -> Result<GenMove, EngineError>;
+
+    async fn undo(&self) -> Result<(), EngineError>;
+
+    /// 摆放让子（黑子），返回实际位置；free 时由引擎自选（place_free_handicap）
+    async fn place_handicap(&self, stones: u32, free: bool) -> Result<Vec<Vertex>, EngineError>;
+
+    /// 按给定位置摆放让子（set_free_handicap），用于引擎重启后恢复
+    async fn set_handicap(&self, stones: &[Vertex]) -> Result<(), EngineError>;
+
+    /// 告知引擎本局用时规则
+    async fn time_settings(&self, control: &TimeControl) -> Result<(), EngineError>;
+
+    /// genmove 前告知 color 一方的剩余时间；读秒中 stones 为剩余次数（日式）或本周期剩余手数（加拿大）
+    async fn time_left(&self, color: Color, seconds: u32, stones: u32) -> Result<(), EngineError>;
+
+    /// 分析当前局面直到首选点达到 max_visits 或 on_update 返回 false，返回最后一批候选点
+    async fn analyze(
+        &self,
+        color: Color,
+        max_visits: u32,
+        on_update: AnalyzeProgress<'_>,
+    ) -> Result<Vec<AnalyzeInfo>, EngineError>;
+
+    async fn final_score(&self) -> Result<Score, EngineError>;
+
+    /// 终局判定的死子
+    async fn dead_stones(&self) -> Result<Vec<Vertex>, EngineError>;
+
+    async fn quit(&self) -> anyhow::Result<()>;
+}
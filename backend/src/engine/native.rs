@@ -0,0 +1,439 @@
+use crate::engine::gtp::EngineError;
+use crate::engine::pool::BoardSetup;
+use crate::engine::protocol::{
+    AnalyzeInfo, Color, GenMove, Score, TimeControl, Vertex, fixed_handicap_vertices,
+};
+use crate::engine::{AnalyzeProgress, GoEngine};
+use crate::review::StoneColor;
+use crate::review::parser::{apply_move, collect_group, index, neighbors};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use std::sync::Mutex;
+
+type Board = Vec<Option<StoneColor>>;
+
+/// 进程内轻量引擎：未配置 KataGo 时使用。
+/// 按"提子 > 逃出叫吃 > 随机合法点（不填己眼、不自紧气）"选点，数子法估分决定停着与认输
+#[derive(Debug)]
+pub struct NativeEngine {
+    size: usize,
+    komi: f32,
+    state: Mutex<NativeState>,
+}
+
+#[derive(Debug)]
+struct NativeState {
+    board: Board,
+    history: Vec<(Color, Vertex, Board)>, // 着法及落子前的盘面，用于悔棋与打劫判定
+    rng: StdRng,
+}
+
+impl NativeEngine {
+    pub fn new(setup: BoardSetup) -> Self {
+        Self::with_rng(setup, StdRng::from_os_rng())
+    }
+
+    /// 固定随机种子，便于测试复现
+    #[cfg(test)]
+    pub fn with_seed(setup: BoardSetup, seed: u64) -> Self {
+        Self::with_rng(setup, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(setup: BoardSetup, rng: StdRng) -> Self {
+        let size = setup.board_size as usize;
+        Self {
+            size,
+            komi: setup.komi,
+            state: Mutex::new(NativeState {
+                board: vec![None; size * size],
+                history: Vec::new(),
+                rng,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, NativeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn point(&self, vertex: Vertex) -> Option<(usize, usize)> {
+        match vertex {
+            Vertex::Pass => None,
+            Vertex::Point { col, row } => {
+                let (col, row) = (col as usize, row as usize);
+                (col < self.size && row >= 1 && row <= self.size).then(|| (col, self.size - row))
+            }
+        }
+    }
+
+    fn vertex(&self, x: usize, y: usize) -> Vertex {
+        Vertex::Point {
+            col: x as u32,
+            row: (self.size - y) as u32,
+        }
+    }
+
+    /// 落子后的盘面；占位、自杀或劫争立即提回时返回 None
+    fn try_play(
+        &self,
+        state: &NativeState,
+        x: usize,
+        y: usize,
+        color: StoneColor,
+    ) -> Option<Board> {
+        let idx = index(self.size, x, y);
+        if state.board[idx].is_some() {
+            return None;
+        }
+        let mut next = state.board.clone();
+        apply_move(&mut next, self.size, x, y, color);
+        // 自杀时落下的子已被 apply_move 移除；盘面回到上一手之前即为劫争提回
+        let retakes_ko = state
+            .history
+            .last()
+            .is_some_and(|(_, _, before)| *before == next);
+        if next[idx].is_none() || retakes_ko {
+            return None;
+        }
+        Some(next)
+    }
+
+    fn stones(&self, board: &Board, color: StoneColor) -> usize {
+        board.iter().filter(|s| **s == Some(color)).count()
+    }
+
+    /// 数子法：盘上子数 + 只与一方相邻的空域，返回黑 - 白 - 贴目
+    fn area_lead(&self, board: &Board) -> f32 {
+        let size = self.size;
+        let mut black = self.stones(board, StoneColor::Black) as f32;
+        let mut white = self.stones(board, StoneColor::White) as f32;
+        let mut seen = vec![false; board.len()];
+        for start in 0..board.len() {
+            if board[start].is_some() || seen[start] {
+                continue;
+            }
+            let mut region = 0;
+            let mut borders = (false, false);
+            let mut stack = vec![(start % size, start / size)];
+            seen[start] = true;
+            while let Some((x, y)) = stack.pop() {
+                region += 1;
+                for (nx, ny) in neighbors(x, y, size) {
+                    let n = index(size, nx, ny);
+                    match board[n] {
+                        Some(StoneColor::Black) => borders.0 = true,
+                        Some(StoneColor::White) => borders.1 = true,
+                        None if !seen[n] => {
+                            seen[n] = true;
+                            stack.push((nx, ny));
+                        }
+                        None => {}
+                    }
+                }
+            }
+            match borders {
+                (true, false) => black += region as f32,
+                (false, true) => white += region as f32,
+                _ => {}
+            }
+        }
+        black - white - self.komi
+    }
+
+    fn lead_for(&self, board: &Board, color: Color) -> f32 {
+        let lead = self.area_lead(board);
+        if color == Color::Black { lead } else { -lead }
+    }
+
+    /// 四邻均为己方棋子的空点视为己眼
+    fn is_own_eye(&self, board: &Board, x: usize, y: usize, color: StoneColor) -> bool {
+        neighbors(x, y, self.size)
+            .into_iter()
+            .all(|(nx, ny)| board[index(self.size, nx, ny)] == Some(color))
+    }
+
+    fn liberties_after(&self, board: &Board, x: usize, y: usize, color: StoneColor) -> usize {
+        collect_group(board, self.size, x, y, color).1
+    }
+
+    fn choose(&self, state: &mut NativeState, color: Color) -> GenMove {
+        let stone = StoneColor::from(color);
+        let area = (self.size * self.size) as f32;
+        let played = state.history.len();
+
+        // 对方停着且己方不落后时跟着停，尽快终局
+        let opponent_passed = matches!(state.history.last(), Some((_, Vertex::Pass, _)));
+        if opponent_passed && self.lead_for(&state.board, color) > 0.0 {
+            return GenMove::Play(Vertex::Pass);
+        }
+        // 下过半盘后仍大幅落后则认输
+        if played as f32 > area / 2.0 && self.lead_for(&state.board, color) < -area / 4.0 {
+            return GenMove::Resign;
+        }
+
+        let mut captures = Vec::new();
+        let mut escapes = Vec::new();
+        let mut quiet = Vec::new();
+        let mut edge = Vec::new();
+        let opp_before = self.stones(&state.board, stone.opponent());
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let Some(next) = self.try_play(state, x, y, stone) else {
+                    continue;
+                };
+                let captured = opp_before - self.stones(&next, stone.opponent());
+                let liberties = self.liberties_after(&next, x, y, stone);
+                if captured > 0 {
+                    captures.push((captured, (x, y)));
+                    continue;
+                }
+                if self.is_own_eye(&state.board, x, y, stone) || liberties < 2 {
+                    continue;
+                }
+                // 落子后连成的棋块里有原本只剩一气的己方棋子
+                let saved = neighbors(x, y, self.size)
+                    .into_iter()
+                    .filter(|&(nx, ny)| state.board[index(self.size, nx, ny)] == Some(stone))
+                    .map(|(nx, ny)| collect_group(&state.board, self.size, nx, ny, stone))
+                    .filter(|(_, libs)| *libs == 1)
+                    .map(|(group, _)| group.len())
+                    .sum::<usize>();
+                if saved > 0 {
+                    escapes.push((saved, (x, y)));
+                } else if x == 0 || y == 0 || x + 1 == self.size || y + 1 == self.size {
+                    edge.push((x, y));
+                } else {
+                    quiet.push((x, y));
+                }
+            }
+        }
+
+        let best = |moves: &[(usize, (usize, usize))]| {
+            moves.iter().max_by_key(|(n, _)| *n).map(|(_, p)| *p)
+        };
+        let pick = best(&captures)
+            .or_else(|| best(&escapes))
+            .or_else(|| quiet.choose(&mut state.rng).copied())
+            .or_else(|| edge.choose(&mut state.rng).copied());
+        match pick {
+            Some((x, y)) => GenMove::Play(self.vertex(x, y)),
+            None => GenMove::Play(Vertex::Pass),
+        }
+    }
+
+    fn place(
+        &self,
+        state: &mut NativeState,
+        color: Color,
+        vertex: Vertex,
+    ) -> Result<(), EngineError> {
+        let before = state.board.clone();
+        if vertex != Vertex::Pass {
+            let (x, y) = self
+                .point(vertex)
+                .ok_or_else(|| EngineError::Gtp("illegal move".to_string()))?;
+            state.board = self
+                .try_play(state, x, y, color.into())
+                .ok_or_else(|| EngineError::Gtp("illegal move".to_string()))?;
+        }
+        state.history.push((color, vertex, before));
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl GoEngine for NativeEngine {
+    async fn play(&self, color: Color, vertex: Vertex) -> Result<(), EngineError> {
+        let mut state = self.lock();
+        self.place(&mut state, color, vertex)
+    }
+
+    async fn genmove(&self, color: Color) -> Result<GenMove, EngineError> {
+        let mut state = self.lock();
+        let mv = self.choose(&mut state, color);
+        if let GenMove::Play(vertex) = mv {
+            self.place(&mut state, color, vertex)?;
+        }
+        Ok(mv)
+    }
+
+    // 自由让子也按固定位置摆放
+    async fn place_handicap(&self, stones: u32, _free: bool) -> Result<Vec<Vertex>, EngineError> {
+        let vertices = fixed_handicap_vertices(self.size as u32, stones)
+            .ok_or_else(|| EngineError::Gtp("invalid handicap".to_string()))?;
+        self.set_handicap(&vertices).await?;
+        Ok(vertices)
+    }
+
+    // 让子直接摆上棋盘，不进入着法历史，悔棋不会撤掉
+    async fn set_handicap(&self, stones: &[Vertex]) -> Result<(), EngineError> {
+        let mut state = self.lock();
+        for vertex in stones {
+            let (x, y) = self
+                .point(*vertex)
+                .ok_or_else(|| EngineError::Gtp("invalid handicap vertex".to_string()))?;
+            let idx = index(self.size, x, y);
+            state.board[idx] = Some(StoneColor::Black);
+        }
+        Ok(())
+    }
+
+    // 选点不耗时，忽略用时设置
+    async fn time_settings(&self, _control: &TimeControl) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    async fn time_left(
+        &self,
+        _color: Color,
+        _seconds: u32,
+        _stones: u32,
+    ) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    async fn undo(&self) -> Result<(), EngineError> {
+        let mut state = self.lock();
+        let (_, _, before) = state
+            .history
+            .pop()
+            .ok_or_else(|| EngineError::Gtp("cannot undo".to_string()))?;
+        state.board = before;
+        Ok(())
+    }
+
+    // 只给出 genmove 会选的一个点；胜率由数子差粗略换算，均为行棋方视角
+    async fn analyze(
+        &self,
+        color: Color,
+        max_visits: u32,
+        on_update: AnalyzeProgress<'_>,
+    ) -> Result<Vec<AnalyzeInfo>, EngineError> {
+        let infos = {
+            let mut state = self.lock();
+            let mv = match self.choose(&mut state, color) {
+                GenMove::Play(vertex) => vertex,
+                GenMove::Resign => Vertex::Pass,
+            };
+            let lead = self.lead_for(&state.board, color);
+            let area = (self.size * self.size) as f32;
+            vec![AnalyzeInfo {
+                mv,
+                visits: max_visits,
+                winrate: (0.5 + lead / area).clamp(0.0, 1.0),
+                score_lead: lead,
+                pv: vec![mv],
+            }]
+        };
+        on_update(&infos);
+        Ok(infos)
+    }
+
+    async fn final_score(&self) -> Result<Score, EngineError> {
+        let lead = self.area_lead(&self.lock().board);
+        Ok(if lead > 0.0 {
+            Score::Black(lead)
+        } else if lead < 0.0 {
+            Score::White(-lead)
+        } else {
+            Score::Draw
+        })
+    }
+
+    // 不做死活判断，盘上棋子都按活棋计
+    async fn dead_stones(&self) -> Result<Vec<Vertex>, EngineError> {
+        Ok(Vec::new())
+    }
+
+    async fn quit(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Vertex {
+        s.parse().unwrap()
+    }
+
+    fn engine(board_size: u32) -> NativeEngine {
+        NativeEngine::with_seed(
+            BoardSetup {
+                board_size,
+                komi: 0.5,
+            },
+            7,
+        )
+    }
+
+    #[tokio::test]
+    async fn escapes_atari_and_rejects_illegal_moves() {
+        let e = engine(9);
+        // 白 E5 被黑三面包围，只剩 E6 一气
+        for (c, m) in [
+            (Color::Black, "D5"),
+            (Color::White, "E5"),
+            (Color::Black, "F5"),
+            (Color::White, "A9"),
+            (Color::Black, "E4"),
+        ] {
+            e.play(c, v(m)).await.unwrap();
+        }
+        assert_eq!(
+            e.genmove(Color::White).await.unwrap(),
+            GenMove::Play(v("E6"))
+        );
+        assert!(e.play(Color::Black, v("E5")).await.is_err()); // 占位
+        assert!(e.play(Color::White, v("J10")).await.is_err()); // 盘外
+    }
+
+    #[tokio::test]
+    async fn captures_stone_in_atari_and_respects_ko() {
+        let e = engine(9);
+        // 构造劫：黑提 E5 后白不能立即在 D5 提回
+        for (c, m) in [
+            (Color::Black, "D4"),
+            (Color::White, "E4"),
+            (Color::Black, "C5"),
+            (Color::White, "F5"),
+            (Color::Black, "D6"),
+            (Color::White, "E6"),
+            (Color::Black, "J9"),
+            (Color::White, "D5"),
+        ] {
+            e.play(c, v(m)).await.unwrap();
+        }
+        assert_eq!(
+            e.genmove(Color::Black).await.unwrap(),
+            GenMove::Play(v("E5"))
+        );
+        assert!(e.play(Color::White, v("D5")).await.is_err());
+        e.undo().await.unwrap();
+        assert!(e.play(Color::Black, v("E5")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn self_play_ends_with_passes_or_resignation() {
+        let e = engine(5);
+        let mut color = Color::Black;
+        let mut passes = 0;
+        let mut ended = false;
+        // genmove 内部按规则落子，非法着法会直接报错
+        for _ in 0..200 {
+            match e.genmove(color).await.unwrap() {
+                GenMove::Play(Vertex::Pass) => passes += 1,
+                GenMove::Play(_) => passes = 0,
+                GenMove::Resign => passes = 2,
+            }
+            if passes == 2 {
+                ended = true;
+                break;
+            }
+            color = color.opponent();
+        }
+        assert!(ended, "self-play should finish on a small board");
+    }
+}
@@ -0,0 +1,446 @@
+use crate::engine::gtp::{CommandTimeouts, EngineError, GtpEngine};
+use crate::engine::protocol::{AnalyzeInfo, Color, GenMove, Score, TimeControl, Vertex};
+use crate::engine::stderr::StderrLog;
+use crate::engine::{AnalyzeProgress, GoEngine};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Duration, timeout};
+
+/// 引擎启动参数：可执行文件 + 命令行；参数完全相同的进程才能互相复用
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EngineSpec {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+/// 租借时需要恢复的棋盘参数
+#[derive(Clone, Copy, Debug)]
+pub struct BoardSetup {
+    pub board_size: u32,
+    pub komi: f32,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PoolError {
+    #[error("engine pool busy: waited {0:?} for a free engine")]
+    Busy(Duration),
+    #[error(transparent)]
+    Engine(#[from] anyhow::Error),
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolStats {
+    pub max_size: usize,
+    pub live: usize,
+    pub idle: usize,
+    pub leased: usize,
+    pub queued: usize,
+}
+
+/// 已退出引擎保留的 stderr 缓冲个数，供排查崩溃
+const RETIRED_LOGS_KEPT: usize = 16;
+
+#[derive(Debug, Default)]
+struct EngineLogs {
+    live: Vec<Arc<StderrLog>>,
+    retired: VecDeque<Arc<StderrLog>>,
+}
+
+/// KataGo 进程池：限制进程总数，空闲进程保持常驻，满载时排队等待
+#[derive(Debug)]
+pub struct EnginePool {
+    max_size: usize,
+    wait_limit: Duration,
+    timeouts: CommandTimeouts,
+    permits: Arc<Semaphore>,
+    idle: Mutex<Vec<(EngineSpec, Arc<GtpEngine>)>>,
+    live: AtomicUsize,
+    queued: AtomicUsize,
+    logs: Mutex<EngineLogs>,
+}
+
+impl EnginePool {
+    pub fn new(max_size: usize, wait_limit: Duration, timeouts: CommandTimeouts) -> Arc<Self> {
+        let max_size = max_size.max(1);
+        Arc::new(Self {
+            max_size,
+            wait_limit,
+            timeouts,
+            permits: Arc::new(Semaphore::new(max_size)),
+            idle: Mutex::new(Vec::new()),
+            live: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            logs: Mutex::new(EngineLogs::default()),
+        })
+    }
+
+    /// 预热：启动 count 个指定参数的空闲进程（不超过池容量）。启动期间占用一个名额，
+    /// 与同时到来的租借一起计数；进程放入空闲列表后归还
+    pub async fn warm(&self, spec: &EngineSpec, count: usize) -> anyhow::Result<()> {
+        for _ in 0..count {
+            let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+                break;
+            };
+            if self.live.load(Ordering::SeqCst) >= self.max_size {
+                break;
+            }
+            let engine = GtpEngine::start(&spec.program, &spec.args, self.timeouts.clone()).await?;
+            self.register(&engine);
+            self.lock_idle().push((spec.clone(), engine));
+            drop(permit);
+        }
+        Ok(())
+    }
+
+    /// 租借一个引擎：优先复用同参数空闲进程；池满时排队，超过等待上限返回 Busy
+    pub async fn acquire(
+        self: &Arc<Self>,
+        spec: &EngineSpec,
+        setup: BoardSetup,
+    ) -> Result<EngineLease, PoolError> {
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        let waited = timeout(self.wait_limit, self.permits.clone().acquire_owned()).await;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        let permit = match waited {
+            Ok(Ok(permit)) => permit,
+            Ok(Err(_)) | Err(_) => return Err(PoolError::Busy(self.wait_limit)),
+        };
+
+        let (reused, evicted) = {
+            let mut idle = self.lock_idle();
+            if let Some(pos) = idle.iter().position(|(s, _)| s == spec) {
+                (Some(idle.swap_remove(pos).1), None)
+            } else if self.live.load(Ordering::SeqCst) >= self.max_size && !idle.is_empty() {
+                // 池已满但有其他参数的空闲进程：腾出一个位置
+                (None, Some(idle.remove(0).1))
+            } else {
+                (None, None)
+            }
+        };
+        if let Some(old) = evicted {
+            self.retire(&old);
+            tokio::spawn(async move {
+                let _ = old.quit().await;
+            });
+        }
+
+        let engine = match reused {
+            Some(engine) => match reset_engine(&engine, setup).await {
+                Ok(()) => engine,
+                Err(err) => {
+                    tracing::warn!(
+                        ?err,
+                        pid = ?engine.pid(),
+                        "pooled engine failed to reset, respawning"
+                    );
+                    self.retire(&engine);
+                    tokio::spawn(async move {
+                        let _ = engine.quit().await;
+                    });
+                    self.spawn_fresh(spec, setup).await?
+                }
+            },
+            None => self.spawn_fresh(spec, setup).await?,
+        };
+
+        let stats = self.stats();
+        tracing::debug!(
+            pid = ?engine.pid(),
+            live = stats.live,
+            idle = stats.idle,
+            queued = stats.queued,
+            "engine leased"
+        );
+        Ok(EngineLease {
+            engine: Some(engine),
+            spec: spec.clone(),
+            pool: self.clone(),
+            _permit: permit,
+        })
+    }
+
+    async fn spawn_fresh(
+        &self,
+        spec: &EngineSpec,
+        setup: BoardSetup,
+    ) -> Result<Arc<GtpEngine>, PoolError> {
+        let engine = GtpEngine::start(&spec.program, &spec.args, self.timeouts.clone()).await?;
+        self.register(&engine);
+        if let Err(err) = reset_engine(&engine, setup).await {
+            self.retire(&engine);
+            let _ = engine.quit().await;
+            return Err(err.into());
+        }
+        Ok(engine)
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let idle = self.lock_idle().len();
+        let live = self.live.load(Ordering::SeqCst);
+        PoolStats {
+            max_size: self.max_size,
+            live,
+            idle,
+            leased: live.saturating_sub(idle),
+            queued: self.queued.load(Ordering::SeqCst),
+        }
+    }
+
+    /// 退出所有空闲进程；仍在租借中的进程归还后不再复用
+    pub async fn shutdown(&self) {
+        self.permits.close();
+        let engines: Vec<Arc<GtpEngine>> = self.lock_idle().drain(..).map(|(_, e)| e).collect();
+        for engine in engines {
+            self.retire(&engine);
+            let _ = engine.quit().await;
+        }
+    }
+
+    /// 池内所有引擎（含最近退出的）的 stderr 缓冲
+    pub fn stderr_logs(&self) -> Vec<Arc<StderrLog>> {
+        let logs = self.lock_logs();
+        logs.live
+            .iter()
+            .chain(logs.retired.iter())
+            .cloned()
+            .collect()
+    }
+
+    fn register(&self, engine: &GtpEngine) {
+        self.live.fetch_add(1, Ordering::SeqCst);
+        self.lock_logs().live.push(engine.stderr().clone());
+    }
+
+    /// 引擎离开进程池：释放计数，stderr 缓冲转入退出列表
+    fn retire(&self, engine: &GtpEngine) {
+        self.live.fetch_sub(1, Ordering::SeqCst);
+        let mut logs = self.lock_logs();
+        if let Some(pos) = logs
+            .live
+            .iter()
+            .position(|l| Arc::ptr_eq(l, engine.stderr()))
+        {
+            let log = logs.live.swap_remove(pos);
+            if logs.retired.len() == RETIRED_LOGS_KEPT {
+                logs.retired.pop_front();
+            }
+            logs.retired.push_back(log);
+        }
+    }
+
+    fn release(&self, spec: EngineSpec, engine: Arc<GtpEngine>) {
+        engine.set_owner(None);
+        if engine.has_exited() {
+            // 已崩溃的进程不再放回空闲列表
+            self.retire(&engine);
+            return;
+        }
+        if self.permits.is_closed() {
+            self.retire(&engine);
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                handle.spawn(async move {
+                    let _ = engine.quit().await;
+                });
+            }
+            return;
+        }
+        self.lock_idle().push((spec, engine));
+    }
+
+    fn lock_logs(&self) -> std::sync::MutexGuard<'_, EngineLogs> {
+        self.logs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_idle(&self) -> std::sync::MutexGuard<'_, Vec<(EngineSpec, Arc<GtpEngine>)>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 租借凭证：Drop 时把进程放回空闲列表并释放名额
+#[derive(Debug)]
+pub struct EngineLease {
+    engine: Option<Arc<GtpEngine>>,
+    spec: EngineSpec,
+    pool: Arc<EnginePool>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Deref for EngineLease {
+    type Target = GtpEngine;
+
+    fn deref(&self) -> &GtpEngine {
+        self.engine
+            .as_deref()
+            .expect("lease holds engine until drop")
+    }
+}
+
+// 租借的进程由进程池管理生命周期：quit 为空操作，Drop 时归还
+#[async_trait::async_trait]
+impl GoEngine for EngineLease {
+    async fn play(&self, color: Color, vertex: Vertex) -> Result<(), EngineError> {
+        GoEngine::play(&**self, color, vertex).await
+    }
+
+    async fn genmove(&self, color: Color) -> Result<GenMove, EngineError> {
+        GoEngine::genmove(&**self, color).await
+    }
+
+    async fn undo(&self) -> Result<(), EngineError> {
+        GoEngine::undo(&**self).await
+    }
+
+    async fn place_handicap(&self, stones: u32, free: bool) -> Result<Vec<Vertex>, EngineError> {
+        GoEngine::place_handicap(&**self, stones, free).await
+    }
+
+    async fn set_handicap(&self, stones: &[Vertex]) -> Result<(), EngineError> {
+        GoEngine::set_handicap(&**self, stones).await
+    }
+
+    async fn time_settings(&self, control: &TimeControl) -> Result<(), EngineError> {
+        GoEngine::time_settings(&**self, control).await
+    }
+
+    async fn time_left(&self, color: Color, seconds: u32, stones: u32) -> Result<(), EngineError> {
+        GoEngine::time_left(&**self, color, seconds, stones).await
+    }
+
+    async fn analyze(
+        &self,
+        color: Color,
+        max_visits: u32,
+        on_update: AnalyzeProgress<'_>,
+    ) -> Result<Vec<AnalyzeInfo>, EngineError> {
+        GoEngine::analyze(&**self, color, max_visits, on_update).await
+    }
+
+    async fn final_score(&self) -> Result<Score, EngineError> {
+        GoEngine::final_score(&**self).await
+    }
+
+    async fn dead_stones(&self) -> Result<Vec<Vertex>, EngineError> {
+        GoEngine::dead_stones(&**self).await
+    }
+
+    async fn quit(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EngineLease {
+    fn drop(&mut self) {
+        if let Some(engine) = self.engine.take() {
+            self.pool.release(self.spec.clone(), engine);
+        }
+    }
+}
+
+/// 清盘并恢复棋盘大小与贴目（顺序同新开对局：clear_board → boardsize → komi），
+/// 并清除上一局的用时设置；不支持 KataGo 用时扩展的引擎没有可清的设置
+async fn reset_engine(engine: &GtpEngine, setup: BoardSetup) -> anyhow::Result<()> {
+    engine.expect_success("clear_board").await?;
+    engine
+        .expect_success(&format!("boardsize {}", setup.board_size))
+        .await?;
+    engine
+        .expect_success(&format!("komi {}", setup.komi))
+        .await?;
+    match engine.known_command("kata-time_settings").await {
+        Ok(true) => {
+            engine.expect_success("kata-time_settings none").await?;
+        }
+        Ok(false) | Err(EngineError::Gtp(_)) => {}
+        Err(err) => return Err(err.into()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 用 sh 模拟一个对任何命令都回答 "=<id>" 的 GTP 引擎，收到 quit 后退出
+    fn echo_spec() -> EngineSpec {
+        EngineSpec {
+            program: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                "while read id line; do printf '=%s\\n\\n' \"$id\"; [ \"$line\" = quit ] && exit; done"
+                    .to_string(),
+            ],
+        }
+    }
+
+    const SETUP: BoardSetup = BoardSetup {
+        board_size: 19,
+        komi: 7.5,
+    };
+
+    #[tokio::test]
+    async fn released_engine_is_reused() {
+        let pool = EnginePool::new(2, Duration::from_secs(5), CommandTimeouts::default());
+        let lease = pool.acquire(&echo_spec(), SETUP).await.expect("lease");
+        let pid = lease.pid();
+        assert_eq!(pool.stats().leased, 1);
+        drop(lease);
+        assert_eq!(pool.stats().idle, 1);
+
+        let again = pool.acquire(&echo_spec(), SETUP).await.expect("lease");
+        assert_eq!(again.pid(), pid);
+        assert_eq!(pool.stats().live, 1);
+        drop(again);
+        pool.shutdown().await;
+        assert_eq!(pool.stats().live, 0);
+    }
+
+    #[tokio::test]
+    async fn full_pool_queues_then_times_out() {
+        let pool = EnginePool::new(1, Duration::from_millis(200), CommandTimeouts::default());
+        let lease = pool.acquire(&echo_spec(), SETUP).await.expect("lease");
+
+        let waiter = {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.acquire(&echo_spec(), SETUP).await.map(|l| l.pid()) })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(pool.stats().queued, 1);
+        assert!(matches!(waiter.await.unwrap(), Err(PoolError::Busy(_))));
+
+        let waiter = {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.acquire(&echo_spec(), SETUP).await.map(|l| l.pid()) })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let pid = lease.pid();
+        drop(lease);
+        assert_eq!(waiter.await.unwrap().expect("lease after release"), pid);
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn warming_counts_against_engines_being_leased() {
+        let pool = EnginePool::new(1, Duration::from_secs(5), CommandTimeouts::default());
+        // 租借中的进程启动较慢：其名额已占用，预热不能再起一个
+        let mut slow = echo_spec();
+        slow.args[1] = format!("sleep 0.3; {}", slow.args[1]);
+        let leasing = {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.acquire(&slow, SETUP).await.map(|l| l.pid()) })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        pool.warm(&echo_spec(), 1).await.expect("warm");
+        assert_eq!(pool.stats().live, 0);
+
+        leasing.await.unwrap().expect("lease");
+        assert_eq!(pool.stats().live, 1);
+        pool.warm(&echo_spec(), 1).await.expect("warm");
+        assert_eq!(pool.stats().live, 1);
+        pool.shutdown().await;
+    }
+}
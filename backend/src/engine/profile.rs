@@ -0,0 +1,227 @@
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// 随程序发布的默认档位，未配置 ENGINE_PROFILES_PATH 时使用
+const BUNDLED_PROFILES: &str = include_str!("../../engine_profiles.toml");
+
+/// 由 katago_spec 按对局设置的覆盖项，档位中不得出现
+const RESERVED_OVERRIDES: &[&str] = &["rules", "playoutDoublingAdvantage"];
+
+/// 一个难度档位：KataGo -override-config 覆盖项，可选换用其他模型/可执行文件
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EngineProfile {
+    pub name: String,
+    pub label: String,
+    pub undos: Option<u32>, // 每局悔棋次数上限，None 为不限
+    #[serde(default)]
+    overrides: BTreeMap<String, serde_json::Value>,
+    pub model: Option<String>,
+    pub engine: Option<String>,
+}
+
+impl EngineProfile {
+    /// 覆盖项的 "key=value" 形式，按 key 排序
+    pub fn override_args(&self) -> Vec<String> {
+        self.overrides
+            .iter()
+            .map(|(k, v)| match v {
+                serde_json::Value::String(s) => format!("{k}={s}"),
+                other => format!("{k}={other}"),
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    default: Option<String>,
+    review: Option<String>,
+    #[serde(rename = "profile")]
+    profiles: Vec<EngineProfile>,
+}
+
+/// 档位列表中的一项，供前端构造难度选择
+#[derive(Clone, Debug, Serialize)]
+pub struct ProfileSummary {
+    pub name: String,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub undos: Option<u32>,
+}
+
+/// 启动时加载并校验过的全部档位；default / review 缺省分别取第一档与最后一档
+#[derive(Debug)]
+pub struct ProfileSet {
+    profiles: Vec<EngineProfile>,
+    default: String,
+    review: String,
+}
+
+impl ProfileSet {
+    /// 按扩展名解析 TOML 或 JSON 文件
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read engine profiles {}", path.display()))?;
+        let json = path.extension().is_some_and(|ext| ext == "json");
+        Self::parse(&text, json)
+            .with_context(|| format!("invalid engine profiles {}", path.display()))
+    }
+
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_PROFILES, false).expect("bundled engine profiles should be valid")
+    }
+
+    fn parse(text: &str, json: bool) -> anyhow::Result<Self> {
+        let file: ProfileFile = if json {
+            serde_json::from_str(text)?
+        } else {
+            toml::from_str(text)?
+        };
+        let Some(first) = file.profiles.first() else {
+            bail!("no profile defined");
+        };
+        let last = file.profiles.last().unwrap_or(first);
+        let set = Self {
+            default: file.default.unwrap_or_else(|| first.name.clone()),
+            review: file.review.unwrap_or_else(|| last.name.clone()),
+            profiles: file.profiles,
+        };
+        set.validate()?;
+        Ok(set)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let mut names = HashSet::new();
+        for profile in &self.profiles {
+            let name = &profile.name;
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                bail!("profile name {name:?} must be non-empty ASCII letters, digits, '-' or '_'");
+            }
+            if !names.insert(name.as_str()) {
+                bail!("duplicate profile {name:?}");
+            }
+            if profile.label.trim().is_empty() {
+                bail!("profile {name:?} has an empty label");
+            }
+            for (key, value) in &profile.overrides {
+                if key.is_empty() || key.contains(['=', ',']) || key.contains(char::is_whitespace) {
+                    bail!("profile {name:?}: invalid override key {key:?}");
+                }
+                if RESERVED_OVERRIDES.contains(&key.as_str()) {
+                    bail!("profile {name:?}: override {key:?} is set per game");
+                }
+                if value.is_array() || value.is_object() || value.is_null() {
+                    bail!("profile {name:?}: override {key:?} must be a string, number or bool");
+                }
+            }
+            for path in [&profile.model, &profile.engine].into_iter().flatten() {
+                if !Path::new(path).is_file() {
+                    bail!("profile {name:?}: file {path:?} does not exist");
+                }
+            }
+        }
+        for (what, name) in [("default", &self.default), ("review", &self.review)] {
+            if self.get(name).is_none() {
+                bail!("{what} profile {name:?} is not defined");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&EngineProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// 旧接口的 engineLevel：从 1 开始的档位序号
+    pub fn by_level(&self, level: u8) -> Option<&EngineProfile> {
+        self.profiles.get((level as usize).checked_sub(1)?)
+    }
+
+    /// 档位的序号（从 1 开始），与 by_level 相对
+    pub fn level_of(&self, name: &str) -> Option<usize> {
+        self.profiles
+            .iter()
+            .position(|p| p.name == name)
+            .map(|i| i + 1)
+    }
+
+    pub fn default_profile(&self) -> &EngineProfile {
+        self.get(&self.default).expect("validated default profile")
+    }
+
+    pub fn review_profile(&self) -> &EngineProfile {
+        self.get(&self.review).expect("validated review profile")
+    }
+
+    /// 全部档位名，按配置顺序
+    pub fn names(&self) -> Vec<String> {
+        self.profiles.iter().map(|p| p.name.clone()).collect()
+    }
+
+    pub fn summaries(&self) -> Vec<ProfileSummary> {
+        self.profiles
+            .iter()
+            .map(|p| ProfileSummary {
+                name: p.name.clone(),
+                label: p.label.clone(),
+                undos: p.undos,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_profiles_match_levels() {
+        let set = ProfileSet::bundled();
+        assert_eq!(set.summaries().len(), 5);
+        assert_eq!(set.default_profile().name, "3star");
+        assert_eq!(set.review_profile().name, "5star");
+        assert_eq!(set.get("1star").unwrap().undos, None);
+        assert_eq!(set.get("3star").unwrap().undos, Some(3));
+        let level1 = set.by_level(1).unwrap();
+        assert!(level1.override_args().contains(&"maxVisits=80".to_string()));
+        assert!(
+            level1
+                .override_args()
+                .contains(&"allowResignation=false".to_string())
+        );
+        assert_eq!(set.level_of("3star"), Some(3));
+        assert!(set.by_level(0).is_none());
+        assert!(set.by_level(6).is_none());
+    }
+
+    #[test]
+    fn invalid_profiles_are_rejected() {
+        let dup = r#"
+            [[profile]]
+            name = "a"
+            label = "A"
+            [[profile]]
+            name = "a"
+            label = "B"
+        "#;
+        assert!(ProfileSet::parse(dup, false).is_err());
+
+        let reserved = r#"{"profile":[{"name":"a","label":"A","overrides":{"rules":"japanese"}}]}"#;
+        assert!(ProfileSet::parse(reserved, true).is_err());
+
+        let unknown_default = r#"{"default":"b","profile":[{"name":"a","label":"A"}]}"#;
+        assert!(ProfileSet::parse(unknown_default, true).is_err());
+
+        let ok = r#"{"profile":[{"name":"a","label":"A","overrides":{"maxVisits":5}}]}"#;
+        let set = ProfileSet::parse(ok, true).unwrap();
+        assert_eq!(set.review_profile().override_args(), vec!["maxVisits=5"]);
+    }
+}
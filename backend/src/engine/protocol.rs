@@ -0,0 +1,472 @@
+use crate::engine::gtp::EngineError;
+use std::fmt;
+use std::str::FromStr;
+
+/// 一条 GTP 响应："=" 成功负载 / "?" 失败信息（均已去掉标记、回显 id 与首尾空白）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GtpResponse {
+    Success(String),
+    Failure(String),
+}
+
+impl GtpResponse {
+    /// 解析一条完整响应（不含结束空行），返回回显的命令 id
+    pub fn parse(raw: &str) -> Result<(Option<u32>, GtpResponse), String> {
+        let raw = raw.trim_start();
+        let (success, rest) = match raw.as_bytes().first() {
+            Some(b'=') => (true, &raw[1..]),
+            Some(b'?') => (false, &raw[1..]),
+            _ => return Err(format!("not a gtp response: {raw:?}")),
+        };
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let id = rest[..digits].parse().ok();
+        let payload = rest[digits..].trim().to_string();
+        let resp = if success {
+            GtpResponse::Success(payload)
+        } else {
+            GtpResponse::Failure(payload)
+        };
+        Ok((id, resp))
+    }
+
+    /// 失败响应转为 EngineError::Gtp，便于用 ? 传播
+    pub fn into_result(self) -> Result<String, EngineError> {
+        match self {
+            GtpResponse::Success(payload) => Ok(payload),
+            GtpResponse::Failure(msg) => Err(EngineError::Gtp(msg)),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("cannot parse {what} from {text:?}")]
+pub struct ParseError {
+    what: &'static str,
+    text: String,
+}
+
+impl ParseError {
+    fn new(what: &'static str, text: &str) -> Self {
+        Self {
+            what,
+            text: text.to_string(),
+        }
+    }
+}
+
+/// GTP 颜色：命令中写作 "B" / "W"；JSON 中为 "black" / "white"
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    Black,
+    White,
+}
+
+impl Color {
+    pub fn opponent(self) -> Self {
+        match self {
+            Color::Black => Color::White,
+            Color::White => Color::Black,
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "b" | "black" => Ok(Color::Black),
+            "w" | "white" => Ok(Color::White),
+            _ => Err(ParseError::new("color", s)),
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Color::Black => "B",
+            Color::White => "W",
+        })
+    }
+}
+
+const GTP_COLUMNS: &[u8] = b"ABCDEFGHJKLMNOPQRSTUVWXYZ";
+
+/// GTP 棋盘点：列从左起 0 开始（跳过 I），行号自下而上从 1 开始
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Vertex {
+    Pass,
+    Point { col: u32, row: u32 },
+}
+
+impl Vertex {
+    /// SGF 坐标（"pd"，左上为原点）→ Vertex；空串与 19 路以内的 "tt" 视为 pass
+    pub fn from_sgf(coord: &str, board_size: u32) -> Option<Self> {
+        if coord.is_empty() || (coord == "tt" && board_size <= 19) {
+            return Some(Vertex::Pass);
+        }
+        let bytes = coord.as_bytes();
+        if bytes.len() != 2 {
+            return None;
+        }
+        let x = bytes[0].checked_sub(b'a')? as u32;
+        let y = bytes[1].checked_sub(b'a')? as u32;
+        if x >= board_size || y >= board_size || x as usize >= GTP_COLUMNS.len() {
+            return None;
+        }
+        Some(Vertex::Point {
+            col: x,
+            row: board_size - y,
+        })
+    }
+
+    /// Vertex → SGF 坐标；pass 为空串，超出棋盘返回 None
+    pub fn to_sgf(self, board_size: u32) -> Option<String> {
+        match self {
+            Vertex::Pass => Some(String::new()),
+            Vertex::Point { col, row } => {
+                if col >= board_size || row == 0 || row > board_size {
+                    return None;
+                }
+                let x = (b'a' + col as u8) as char;
+                let y = (b'a' + (board_size - row) as u8) as char;
+                Some(format!("{x}{y}"))
+            }
+        }
+    }
+}
+
+impl FromStr for Vertex {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        if text.eq_ignore_ascii_case("pass") {
+            return Ok(Vertex::Pass);
+        }
+        let err = || ParseError::new("vertex", s);
+        let letter = text.bytes().next().ok_or_else(err)?.to_ascii_uppercase();
+        let col = GTP_COLUMNS
+            .iter()
+            .position(|&c| c == letter)
+            .ok_or_else(err)? as u32;
+        let row: u32 = text[1..].parse().map_err(|_| err())?;
+        if row == 0 {
+            return Err(err());
+        }
+        Ok(Vertex::Point { col, row })
+    }
+}
+
+impl fmt::Display for Vertex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Vertex::Pass => f.write_str("pass"),
+            Vertex::Point { col, row } => {
+                write!(f, "{}{}", GTP_COLUMNS[*col as usize] as char, row)
+            }
+        }
+    }
+}
+
+/// SGF 坐标 → GTP 坐标（"Q16"）；pass 与非法坐标返回 None
+pub fn sgf_to_gtp(coord: &str, board_size: u32) -> Option<String> {
+    match Vertex::from_sgf(coord, board_size)? {
+        Vertex::Pass => None,
+        point => Some(point.to_string()),
+    }
+}
+
+/// genmove 的结果：落子（含 pass）或认输
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenMove {
+    Play(Vertex),
+    Resign,
+}
+
+impl FromStr for GenMove {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("resign") {
+            return Ok(GenMove::Resign);
+        }
+        s.parse().map(GenMove::Play)
+    }
+}
+
+impl fmt::Display for GenMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenMove::Play(vertex) => vertex.fmt(f),
+            GenMove::Resign => f.write_str("resign"),
+        }
+    }
+}
+
+/// GTP fixed_handicap 规定的让子位置；让子数对该棋盘无效时返回 None
+/// （2~9 子；偶数路与 7 路最多 4 子，小于 7 路不支持）
+pub fn fixed_handicap_vertices(board_size: u32, stones: u32) -> Option<Vec<Vertex>> {
+    let max = if board_size.is_multiple_of(2) || board_size == 7 {
+        4
+    } else {
+        9
+    };
+    if board_size < 7 || !(2..=max).contains(&stones) {
+        return None;
+    }
+    let edge = if board_size >= 13 { 3 } else { 2 };
+    let (lo, hi, mid) = (edge, board_size - 1 - edge, board_size / 2);
+    let pt = |col: u32, row_from_top: u32| Vertex::Point {
+        col,
+        row: board_size - row_from_top,
+    };
+    // 对角 → 另一对角 → 天元（奇数子）→ 左右边 → 上下边
+    let mut vertices = vec![pt(lo, hi), pt(hi, lo), pt(lo, lo), pt(hi, hi)];
+    vertices.truncate(stones.min(4) as usize);
+    if stones >= 6 {
+        vertices.extend([pt(lo, mid), pt(hi, mid)]);
+    }
+    if stones >= 8 {
+        vertices.extend([pt(mid, hi), pt(mid, lo)]);
+    }
+    if stones % 2 == 1 && stones >= 5 {
+        vertices.push(pt(mid, mid));
+    }
+    Some(vertices)
+}
+
+/// 对局用时规则（秒），分类与 KataGo kata-time_settings 一致
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TimeControl {
+    /// 日式读秒：每手在一个读秒周期内下完不扣次数
+    #[serde(rename_all = "camelCase")]
+    Byoyomi {
+        main_time: u32,
+        period_time: u32,
+        periods: u32,
+    },
+    /// 加拿大读秒：每个周期内须下完 stones 手
+    #[serde(rename_all = "camelCase")]
+    Canadian {
+        main_time: u32,
+        period_time: u32,
+        stones: u32,
+    },
+    /// 费舍尔：每手加秒
+    #[serde(rename_all = "camelCase")]
+    Fischer { main_time: u32, increment: u32 },
+}
+
+impl TimeControl {
+    /// 单项上限 24 小时；读秒周期、次数与手数至少为 1
+    pub fn is_valid(&self) -> bool {
+        const MAX_SECONDS: u32 = 24 * 3600;
+        match *self {
+            TimeControl::Byoyomi {
+                main_time,
+                period_time,
+                periods,
+            } => {
+                main_time <= MAX_SECONDS
+                    && (1..=MAX_SECONDS).contains(&period_time)
+                    && (1..=100).contains(&periods)
+            }
+            TimeControl::Canadian {
+                main_time,
+                period_time,
+                stones,
+            } => {
+                main_time <= MAX_SECONDS
+                    && (1..=MAX_SECONDS).contains(&period_time)
+                    && (1..=100).contains(&stones)
+            }
+            TimeControl::Fischer {
+                main_time,
+                increment,
+            } => (1..=MAX_SECONDS).contains(&main_time) && increment <= MAX_SECONDS,
+        }
+    }
+}
+
+/// 空白（含换行）分隔的点列表，如 final_status_list 的结果
+pub fn parse_vertex_list(s: &str) -> Result<Vec<Vertex>, ParseError> {
+    s.split_whitespace().map(str::parse).collect()
+}
+
+/// final_score 结果："B+2.5" / "W+7.5" / "0"（和棋）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Score {
+    Black(f32),
+    White(f32),
+    Draw,
+}
+
+impl FromStr for Score {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let err = || ParseError::new("score", s);
+        if text == "0" {
+            return Ok(Score::Draw);
+        }
+        let (winner, margin) = text.split_once('+').ok_or_else(err)?;
+        let margin: f32 = margin.parse().map_err(|_| err())?;
+        match winner {
+            "B" | "b" => Ok(Score::Black(margin)),
+            "W" | "w" => Ok(Score::White(margin)),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Score::Black(margin) => write!(f, "B+{margin}"),
+            Score::White(margin) => write!(f, "W+{margin}"),
+            Score::Draw => f.write_str("0"),
+        }
+    }
+}
+
+/// known_command 等命令的布尔响应
+pub fn parse_bool(s: &str) -> Result<bool, ParseError> {
+    match s.trim() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(ParseError::new("bool", s)),
+    }
+}
+
+/// kata-analyze 输出中的一个候选点
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalyzeInfo {
+    pub mv: Vertex,
+    pub visits: u32,
+    pub winrate: f32,
+    pub score_lead: f32,
+    pub pv: Vec<Vertex>,
+}
+
+/// 解析一行 kata-analyze 输出（多个 "info ..." 段按候选顺序排列）；无法识别的段被跳过
+pub fn parse_analyze_infos(line: &str) -> Vec<AnalyzeInfo> {
+    let mut infos = Vec::new();
+    let mut tokens = line.split_whitespace().peekable();
+    while tokens.peek().is_some() {
+        if tokens.next() != Some("info") {
+            continue;
+        }
+        let mut mv = None;
+        let mut visits = 0;
+        let mut winrate = 0.5;
+        let mut score_lead = 0.0;
+        let mut pv = Vec::new();
+        while let Some(&token) = tokens.peek() {
+            if token == "info" {
+                break;
+            }
+            tokens.next();
+            match token {
+                "move" => mv = tokens.next().and_then(|v| v.parse().ok()),
+                "visits" => visits = tokens.next().and_then(|v| v.parse().ok()).unwrap_or(0),
+                "winrate" => winrate = tokens.next().and_then(|v| v.parse().ok()).unwrap_or(0.5),
+                "scoreLead" | "scorelead" => {
+                    score_lead = tokens.next().and_then(|v| v.parse().ok()).unwrap_or(0.0)
+                }
+                "pv" => {
+                    while let Some(v) = tokens.next_if(|t| *t != "info") {
+                        if let Ok(vertex) = v.parse() {
+                            pv.push(vertex);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(mv) = mv {
+            infos.push(AnalyzeInfo {
+                mv,
+                visits,
+                winrate,
+                score_lead,
+                pv,
+            });
+        }
+    }
+    infos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_carry_echoed_id() {
+        assert_eq!(
+            GtpResponse::parse("=12 D4"),
+            Ok((Some(12), GtpResponse::Success("D4".to_string())))
+        );
+        assert_eq!(
+            GtpResponse::parse("? illegal move"),
+            Ok((None, GtpResponse::Failure("illegal move".to_string())))
+        );
+        assert!(GtpResponse::parse("info move D4").is_err());
+    }
+
+    #[test]
+    fn typed_payloads_round_trip() {
+        let q16: Vertex = "q16".parse().unwrap();
+        assert_eq!(q16, Vertex::Point { col: 15, row: 16 });
+        assert_eq!(q16.to_string(), "Q16");
+        assert_eq!(q16.to_sgf(19).as_deref(), Some("pd"));
+        assert_eq!(Vertex::from_sgf("pd", 19), Some(q16));
+        assert!("I5".parse::<Vertex>().is_err());
+
+        assert_eq!("resign".parse::<GenMove>().unwrap(), GenMove::Resign);
+        assert_eq!(
+            parse_vertex_list("D4 Q16\nPASS").unwrap(),
+            vec![Vertex::Point { col: 3, row: 4 }, q16, Vertex::Pass]
+        );
+        assert_eq!("W+7.5".parse::<Score>().unwrap(), Score::White(7.5));
+        assert_eq!(Score::Black(2.5).to_string(), "B+2.5");
+        assert!(parse_bool("true").unwrap());
+
+        let nine: Vec<String> = fixed_handicap_vertices(19, 9)
+            .unwrap()
+            .iter()
+            .map(Vertex::to_string)
+            .collect();
+        assert_eq!(
+            nine,
+            ["D4", "Q16", "D16", "Q4", "D10", "Q10", "K4", "K16", "K10"]
+        );
+        let three = fixed_handicap_vertices(9, 3).unwrap();
+        assert_eq!(
+            three,
+            vec![
+                "C3".parse().unwrap(),
+                "G7".parse().unwrap(),
+                "C7".parse().unwrap()
+            ]
+        );
+        assert!(fixed_handicap_vertices(19, 10).is_none());
+        assert!(fixed_handicap_vertices(8, 5).is_none());
+    }
+
+    #[test]
+    fn analyze_line_splits_candidates() {
+        let line = "info move D4 visits 10 winrate 0.55 scoreLead 1.5 pv D4 Q16 \
+                    info move Q16 visits 3 winrate 0.4 pv Q16";
+        let infos = parse_analyze_infos(line);
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[0].visits, 10);
+        assert_eq!(infos[0].pv.len(), 2);
+        assert_eq!(infos[1].mv.to_string(), "Q16");
+        assert_eq!(infos[1].score_lead, 0.0);
+    }
+}
@@ -0,0 +1,138 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::ChildStderr;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, timeout};
+
+/// 每个引擎保留的 stderr 行数
+const STDERR_RING_LINES: usize = 200;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StderrLine {
+    pub at: i64, // unix 秒
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>, // 写入时的占用者（game:<id> / review:<id>）
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StderrSnapshot {
+    pub pid: Option<u32>,
+    pub kind: &'static str,
+    pub owner: Option<String>,
+    pub exited: bool,
+    pub lines: Vec<StderrLine>,
+}
+
+/// 引擎 stderr 环形缓冲：后台任务逐行读入，超出容量丢弃最旧的行
+#[derive(Debug)]
+pub struct StderrLog {
+    pid: Option<u32>,
+    kind: &'static str,
+    owner: Mutex<Option<String>>,
+    lines: Mutex<VecDeque<StderrLine>>,
+    exited: AtomicBool,
+    reader: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl StderrLog {
+    /// 开始读取子进程 stderr；kind 区分 gtp / analysis 引擎
+    pub fn capture(stderr: ChildStderr, pid: Option<u32>, kind: &'static str) -> Arc<Self> {
+        let log = Arc::new(Self {
+            pid,
+            kind,
+            owner: Mutex::new(None),
+            lines: Mutex::new(VecDeque::with_capacity(STDERR_RING_LINES)),
+            exited: AtomicBool::new(false),
+            reader: Mutex::new(None),
+        });
+        let reader = tokio::spawn(read_stderr(stderr, log.clone()));
+        *lock(&log.reader) = Some(reader);
+        log
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// 设置当前占用者，之后读入的行以此标记
+    pub fn set_owner(&self, owner: Option<String>) {
+        *lock(&self.owner) = owner;
+    }
+
+    pub fn owner(&self) -> Option<String> {
+        lock(&self.owner).clone()
+    }
+
+    /// stderr 已读到 EOF（进程退出）
+    pub fn exited(&self) -> bool {
+        self.exited.load(Ordering::SeqCst)
+    }
+
+    /// 最近 n 行，旧的在前
+    pub fn tail(&self, n: usize) -> Vec<StderrLine> {
+        let lines = lock(&self.lines);
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+
+    /// 最近 n 行的纯文本，用于错误信息与日志
+    pub fn tail_text(&self, n: usize) -> String {
+        self.tail(n)
+            .into_iter()
+            .map(|l| l.text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn snapshot(&self, n: usize) -> StderrSnapshot {
+        StderrSnapshot {
+            pid: self.pid,
+            kind: self.kind,
+            owner: self.owner(),
+            exited: self.exited(),
+            lines: self.tail(n),
+        }
+    }
+
+    /// 进程退出后等待读取任务收完剩余输出（最多 500ms）
+    pub async fn settle(&self) {
+        let reader = lock(&self.reader).take();
+        if let Some(reader) = reader {
+            let _ = timeout(Duration::from_millis(500), reader).await;
+        }
+    }
+
+    fn push(&self, text: String) {
+        let line = StderrLine {
+            at: time::OffsetDateTime::now_utc().unix_timestamp(),
+            owner: self.owner(),
+            text,
+        };
+        let mut lines = lock(&self.lines);
+        if lines.len() == STDERR_RING_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+async fn read_stderr(stderr: ChildStderr, log: Arc<StderrLog>) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        tracing::debug!(pid = ?log.pid, kind = log.kind, "{}", line);
+        log.push(line);
+    }
+    log.exited.store(true, Ordering::SeqCst);
+}
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
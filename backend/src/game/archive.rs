@@ -0,0 +1,366 @@
+use crate::engine::protocol::{Color, TimeControl, Vertex};
+use crate::game::{EndReason, GameEnd};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// 一局棋的完整记录：用于导出 SGF，结束后写入归档
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameRecord {
+    pub game_id: String,
+    pub sid: String, // 开局一方
+    #[serde(default)]
+    pub opponent: Opponent,
+    pub profile: String,
+    pub level: Option<usize>, // 档位序号（从 1 开始）
+    pub rules: String,
+    pub board_size: u32,
+    pub komi: f32,
+    pub human_color: Color, // 开局一方的执子
+    #[serde(default)]
+    pub handicap: Vec<String>,
+    pub time_control: Option<TimeControl>,
+    pub moves: Vec<RecordedMove>,
+    #[serde(default)]
+    pub evals: BTreeMap<usize, String>, // 第 N 手后的形势判断
+    pub end: Option<GameEnd>,
+    pub started_at: i64,
+}
+
+/// 对手：KataGo，或凭邀请加入的另一位玩家（guest_sid 在加入前为空）
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Opponent {
+    #[default]
+    Engine,
+    #[serde(rename_all = "camelCase")]
+    Human { guest_sid: Option<String> },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedMove {
+    pub color: Color,
+    pub vertex: String, // GTP 坐标或 "pass"
+}
+
+impl GameRecord {
+    /// 着法序列；无法解析的坐标按 pass 处理
+    pub fn move_list(&self) -> Vec<(Color, Vertex)> {
+        self.moves
+            .iter()
+            .map(|m| (m.color, m.vertex.parse().unwrap_or(Vertex::Pass)))
+            .collect()
+    }
+
+    pub fn handicap_vertices(&self) -> Vec<Vertex> {
+        self.handicap
+            .iter()
+            .filter_map(|v| v.parse().ok())
+            .collect()
+    }
+
+    /// SGF 中对手一方的名字
+    pub fn opponent_name(&self) -> String {
+        match self.opponent {
+            Opponent::Human { .. } => "Human".to_string(),
+            Opponent::Engine => engine_name(&self.profile, self.level),
+        }
+    }
+
+    /// 对局中的玩家（sid 与执子）；人人对局含已加入的另一方
+    pub fn players(&self) -> Vec<(String, Color)> {
+        let mut players = vec![(self.sid.clone(), self.human_color)];
+        if let Opponent::Human {
+            guest_sid: Some(guest),
+        } = &self.opponent
+        {
+            players.push((guest.clone(), self.human_color.opponent()));
+        }
+        players
+    }
+
+    fn outcome(&self, color: Color) -> Outcome {
+        match self.end.as_ref().and_then(|e| e.winner) {
+            Some(winner) if winner == color => Outcome::Win,
+            Some(_) => Outcome::Loss,
+            None => Outcome::Draw,
+        }
+    }
+}
+
+/// 引擎在 SGF 中的名字：有档位序号时写作 "KataGo N-star"，否则用档位名
+pub fn engine_name(profile: &str, level: Option<usize>) -> String {
+    match level {
+        Some(level) => format!("KataGo {level}-star"),
+        None => format!("KataGo {profile}"),
+    }
+}
+
+/// 玩家一方的胜负
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw, // 含和棋与数子失败
+}
+
+/// 归档列表中的一项
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveSummary {
+    pub game_id: String,
+    pub profile: String,
+    pub level: Option<usize>,
+    pub rules: String,
+    pub board_size: u32,
+    pub komi: f32,
+    pub human_color: Color, // 本人执子
+    pub human_opponent: bool,
+    pub handicap: usize,
+    pub move_count: usize,
+    pub result: String,
+    pub reason: Option<EndReason>,
+    pub outcome: Outcome,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+}
+
+impl ArchiveSummary {
+    // color 一方（列表所属玩家）看到的摘要
+    fn of(record: &GameRecord, color: Color) -> Self {
+        Self {
+            game_id: record.game_id.clone(),
+            profile: record.profile.clone(),
+            level: record.level,
+            rules: record.rules.clone(),
+            board_size: record.board_size,
+            komi: record.komi,
+            human_color: color,
+            human_opponent: matches!(record.opponent, Opponent::Human { .. }),
+            handicap: record.handicap.len(),
+            move_count: record.moves.len(),
+            result: record
+                .end
+                .as_ref()
+                .map_or_else(|| "?".to_string(), |e| e.result.clone()),
+            reason: record.end.as_ref().map(|e| e.reason),
+            outcome: record.outcome(color),
+            started_at: record.started_at,
+            ended_at: record.end.as_ref().map(|e| e.ended_at),
+        }
+    }
+}
+
+/// 列表筛选条件；均为可选
+#[derive(Clone, Debug, Default)]
+pub struct ArchiveFilter {
+    pub outcome: Option<Outcome>,
+    pub level: Option<usize>,
+    pub profile: Option<String>,
+    pub board_size: Option<u32>,
+}
+
+impl ArchiveFilter {
+    fn matches(&self, s: &ArchiveSummary) -> bool {
+        self.outcome.is_none_or(|o| o == s.outcome)
+            && self.level.is_none_or(|l| s.level == Some(l))
+            && self.profile.as_ref().is_none_or(|p| *p == s.profile)
+            && self.board_size.is_none_or(|b| b == s.board_size)
+    }
+}
+
+/// 已结束对局的磁盘归档：每局一个 JSON 文件，启动时扫描目录建立内存索引
+#[derive(Debug)]
+pub struct GameArchive {
+    dir: PathBuf,
+    index: Mutex<Vec<(String, ArchiveSummary)>>, // (sid, 摘要)，按结束时间先后；人人对局每位玩家一项
+}
+
+impl GameArchive {
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("create game archive {}", dir.display()))?;
+        let mut index = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let parsed = std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Ok(serde_json::from_slice::<GameRecord>(&bytes)?));
+            match parsed {
+                Ok(record) => index.extend(summaries(&record)),
+                Err(err) => {
+                    tracing::warn!(?err, path = %path.display(), "skipping unreadable archived game")
+                }
+            }
+        }
+        index.sort_by_key(|(_, s)| (s.ended_at, s.started_at));
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            index: Mutex::new(index),
+        })
+    }
+
+    /// 写入（或覆盖）一局；先写临时文件再改名，避免留下半个文件
+    pub async fn save(&self, record: &GameRecord) -> anyhow::Result<()> {
+        let path = self
+            .path_of(&record.game_id)
+            .with_context(|| format!("invalid game id {:?}", record.game_id))?;
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(record)?).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        let mut index = self.lock();
+        index.retain(|(_, s)| s.game_id != record.game_id);
+        index.extend(summaries(record));
+        Ok(())
+    }
+
+    /// sid 名下符合条件的对局，最近结束的在前；page 从 1 开始。返回 (总数, 本页)
+    pub fn list(
+        &self,
+        sid: &str,
+        filter: &ArchiveFilter,
+        page: usize,
+        page_size: usize,
+    ) -> (usize, Vec<ArchiveSummary>) {
+        let index = self.lock();
+        let matching: Vec<&ArchiveSummary> = index
+            .iter()
+            .rev()
+            .filter(|(owner, s)| owner == sid && filter.matches(s))
+            .map(|(_, s)| s)
+            .collect();
+        let skip = page.saturating_sub(1).saturating_mul(page_size);
+        let games = matching
+            .iter()
+            .skip(skip)
+            .take(page_size)
+            .map(|s| (*s).clone())
+            .collect();
+        (matching.len(), games)
+    }
+
+    pub async fn get(&self, game_id: &str) -> anyhow::Result<Option<GameRecord>> {
+        let Some(path) = self.path_of(game_id) else {
+            return Ok(None);
+        };
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // gameId 只允许字母、数字与 '-'，防止路径穿越
+    fn path_of(&self, game_id: &str) -> Option<PathBuf> {
+        (!game_id.is_empty()
+            && game_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        .then(|| self.dir.join(format!("{game_id}.json")))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(String, ArchiveSummary)>> {
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn summaries(record: &GameRecord) -> Vec<(String, ArchiveSummary)> {
+    record
+        .players()
+        .into_iter()
+        .map(|(sid, color)| (sid, ArchiveSummary::of(record, color)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, sid: &str, winner: Option<Color>, ended_at: i64) -> GameRecord {
+        GameRecord {
+            game_id: id.to_string(),
+            sid: sid.to_string(),
+            opponent: Opponent::Engine,
+            profile: "3star".to_string(),
+            level: Some(3),
+            rules: "chinese".to_string(),
+            board_size: 19,
+            komi: 7.5,
+            human_color: Color::Black,
+            handicap: Vec::new(),
+            time_control: None,
+            moves: vec![RecordedMove {
+                color: Color::Black,
+                vertex: "D4".to_string(),
+            }],
+            evals: BTreeMap::new(),
+            end: Some(GameEnd {
+                reason: EndReason::Resignation,
+                result: "?".to_string(),
+                winner,
+                dead: Vec::new(),
+                ended_at,
+            }),
+            started_at: ended_at - 60,
+        }
+    }
+
+    #[tokio::test]
+    async fn saved_games_are_listed_per_sid_and_reloaded() {
+        let dir = std::env::temp_dir().join(format!("game-archive-{}", uuid::Uuid::new_v4()));
+        let archive = GameArchive::open(&dir).unwrap();
+        archive
+            .save(&record("g-1", "a", Some(Color::Black), 100))
+            .await
+            .unwrap();
+        archive
+            .save(&record("g-2", "a", Some(Color::White), 200))
+            .await
+            .unwrap();
+        archive.save(&record("g-3", "b", None, 300)).await.unwrap();
+
+        let (total, games) = archive.list("a", &ArchiveFilter::default(), 1, 1);
+        assert_eq!(total, 2);
+        assert_eq!(games[0].game_id, "g-2");
+        assert_eq!(games[0].outcome, Outcome::Loss);
+        let wins = ArchiveFilter {
+            outcome: Some(Outcome::Win),
+            ..Default::default()
+        };
+        let (total, games) = archive.list("a", &wins, 1, 10);
+        assert_eq!((total, games[0].game_id.as_str()), (1, "g-1"));
+
+        let reopened = GameArchive::open(&dir).unwrap();
+        let (total, _) = reopened.list("b", &ArchiveFilter::default(), 1, 10);
+        assert_eq!(total, 1);
+
+        // 人人对局双方各自看到自己的胜负
+        let mut pvp = record("g-4", "a", Some(Color::White), 400);
+        pvp.opponent = Opponent::Human {
+            guest_sid: Some("b".to_string()),
+        };
+        reopened.save(&pvp).await.unwrap();
+        let (_, games) = reopened.list("b", &ArchiveFilter::default(), 1, 10);
+        assert_eq!(
+            (games[0].game_id.as_str(), games[0].outcome),
+            ("g-4", Outcome::Win)
+        );
+        let (_, games) = reopened.list("a", &ArchiveFilter::default(), 1, 10);
+        assert_eq!(games[0].outcome, Outcome::Loss);
+
+        let loaded = reopened.get("g-3").await.unwrap().unwrap();
+        assert_eq!(
+            loaded.move_list(),
+            vec![(Color::Black, "D4".parse().unwrap())]
+        );
+        assert!(reopened.get("../g-3").await.unwrap().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
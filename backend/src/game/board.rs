@@ -0,0 +1,327 @@
+use crate::engine::protocol::{Color, Vertex};
+use crate::review::StoneColor;
+use crate::review::parser::{apply_move, collect_group, index};
+
+/// 着法被棋盘拒绝的原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum IllegalMove {
+    #[error("it is {0}'s turn")]
+    WrongTurn(Color),
+    #[error("point is off the board")]
+    OffBoard,
+    #[error("point is occupied")]
+    Occupied,
+    #[error("suicide is not allowed")]
+    Suicide,
+    #[error("ko: cannot retake immediately")]
+    Ko,
+}
+
+impl IllegalMove {
+    /// 响应体中的 reason 字段
+    pub fn code(&self) -> &'static str {
+        match self {
+            IllegalMove::WrongTurn(_) => "WRONG_TURN",
+            IllegalMove::OffBoard => "OFF_BOARD",
+            IllegalMove::Occupied => "OCCUPIED",
+            IllegalMove::Suicide => "SUICIDE",
+            IllegalMove::Ko => "KO",
+        }
+    }
+}
+
+/// 支持的棋盘路数，与 SGF 导入的限制一致；开局前校验，避免按任意大小分配棋盘
+pub const BOARD_SIZES: std::ops::RangeInclusive<u32> = 5..=25;
+
+/// 一手已落的棋及其提掉的子
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlayedMove {
+    pub color: Color,
+    pub vertex: Vertex,
+    pub captured: Vec<Vertex>,
+    ko_before: Option<Vertex>, // 落子前的劫点，悔棋时恢复
+}
+
+/// 对局的权威棋盘：盘面、着法历史、提子数、劫点与轮到哪方
+#[derive(Clone, Debug)]
+pub struct GameBoard {
+    size: u32,
+    grid: Vec<Option<StoneColor>>,
+    to_move: Color,
+    ko: Option<Vertex>, // 轮到的一方此手不能落的点（刚被提的单子）
+    handicap: Vec<Vertex>,
+    history: Vec<PlayedMove>,
+}
+
+impl GameBoard {
+    pub fn new(size: u32) -> Self {
+        Self {
+            size,
+            grid: vec![None; (size * size) as usize],
+            to_move: Color::Black,
+            ko: None,
+            handicap: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// 摆好让子（黑子）的棋盘，白先行；让子不进入着法历史
+    pub fn with_handicap(size: u32, stones: &[Vertex]) -> Self {
+        let mut board = Self::new(size);
+        for vertex in stones {
+            if let Some((x, y)) = board.point(*vertex) {
+                let idx = board.idx(x, y);
+                board.grid[idx] = Some(StoneColor::Black);
+                board.handicap.push(*vertex);
+            }
+        }
+        if !board.handicap.is_empty() {
+            board.to_move = Color::White;
+        }
+        board
+    }
+
+    pub fn handicap(&self) -> &[Vertex] {
+        &self.handicap
+    }
+
+    pub fn to_move(&self) -> Color {
+        self.to_move
+    }
+
+    pub fn ko(&self) -> Option<Vertex> {
+        self.ko
+    }
+
+    pub fn moves(&self) -> &[PlayedMove] {
+        &self.history
+    }
+
+    /// 引擎重放用的着法序列
+    pub fn move_list(&self) -> Vec<(Color, Vertex)> {
+        self.history.iter().map(|m| (m.color, m.vertex)).collect()
+    }
+
+    /// 最后两手均为 pass
+    pub fn ended_by_passes(&self) -> bool {
+        matches!(
+            self.history.as_slice(),
+            [.., a, b] if a.vertex == Vertex::Pass && b.vertex == Vertex::Pass
+        )
+    }
+
+    /// color 一方累计提掉的对方棋子数
+    pub fn prisoners(&self, color: Color) -> usize {
+        self.history
+            .iter()
+            .filter(|m| m.color == color)
+            .map(|m| m.captured.len())
+            .sum()
+    }
+
+    /// color 一方盘上所有棋子，自上而下、自左而右
+    pub fn stones(&self, color: Color) -> Vec<Vertex> {
+        let size = self.size as usize;
+        let stone = StoneColor::from(color);
+        (0..self.grid.len())
+            .filter(|&i| self.grid[i] == Some(stone))
+            .map(|i| self.vertex(i % size, i / size))
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub fn stone_at(&self, vertex: Vertex) -> Option<Color> {
+        let (x, y) = self.point(vertex)?;
+        self.grid[self.idx(x, y)].map(Color::from)
+    }
+
+    /// 只检查合法性，不改变棋盘
+    pub fn check(&self, color: Color, vertex: Vertex) -> Result<(), IllegalMove> {
+        self.resolve(color, vertex).map(|_| ())
+    }
+
+    /// 落子（含 pass）；返回提掉的子
+    pub fn play(&mut self, color: Color, vertex: Vertex) -> Result<&PlayedMove, IllegalMove> {
+        let (grid, captured, ko) = self.resolve(color, vertex)?;
+        self.grid = grid;
+        self.history.push(PlayedMove {
+            color,
+            vertex,
+            captured,
+            ko_before: self.ko,
+        });
+        self.ko = ko;
+        self.to_move = color.opponent();
+        Ok(self.history.last().expect("just pushed"))
+    }
+
+    /// 撤销最后一手，恢复被提的子与劫点
+    pub fn undo(&mut self) -> Option<PlayedMove> {
+        let last = self.history.pop()?;
+        if let Some((x, y)) = self.point(last.vertex) {
+            let idx = self.idx(x, y);
+            self.grid[idx] = None;
+        }
+        let opponent = StoneColor::from(last.color.opponent());
+        for v in &last.captured {
+            if let Some((x, y)) = self.point(*v) {
+                let idx = self.idx(x, y);
+                self.grid[idx] = Some(opponent);
+            }
+        }
+        self.ko = last.ko_before;
+        self.to_move = last.color;
+        Some(last)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn resolve(
+        &self,
+        color: Color,
+        vertex: Vertex,
+    ) -> Result<(Vec<Option<StoneColor>>, Vec<Vertex>, Option<Vertex>), IllegalMove> {
+        if color != self.to_move {
+            return Err(IllegalMove::WrongTurn(self.to_move));
+        }
+        if vertex == Vertex::Pass {
+            return Ok((self.grid.clone(), Vec::new(), None));
+        }
+        let (x, y) = self.point(vertex).ok_or(IllegalMove::OffBoard)?;
+        let idx = self.idx(x, y);
+        if self.grid[idx].is_some() {
+            return Err(IllegalMove::Occupied);
+        }
+        if self.ko == Some(vertex) {
+            return Err(IllegalMove::Ko);
+        }
+        let stone = StoneColor::from(color);
+        let size = self.size as usize;
+        let mut grid = self.grid.clone();
+        apply_move(&mut grid, size, x, y, stone);
+        // apply_move 会把无气且未提子的己方棋块移除
+        if grid[idx].is_none() {
+            return Err(IllegalMove::Suicide);
+        }
+        let captured: Vec<Vertex> = (0..grid.len())
+            .filter(|&i| self.grid[i].is_some() && grid[i].is_none())
+            .map(|i| self.vertex(i % size, i / size))
+            .collect();
+        // 单子提单子且落下的子只剩被提点一气时形成劫
+        let (group, liberties) = collect_group(&grid, size, x, y, stone);
+        let ko = match captured.as_slice() {
+            [single] if group.len() == 1 && liberties == 1 => Some(*single),
+            _ => None,
+        };
+        Ok((grid, captured, ko))
+    }
+
+    fn idx(&self, x: usize, y: usize) -> usize {
+        index(self.size as usize, x, y)
+    }
+
+    // (x, y) 以左上为原点，与 review::parser 一致
+    fn point(&self, vertex: Vertex) -> Option<(usize, usize)> {
+        match vertex {
+            Vertex::Pass => None,
+            Vertex::Point { col, row } => (col < self.size && row >= 1 && row <= self.size)
+                .then(|| (col as usize, (self.size - row) as usize)),
+        }
+    }
+
+    fn vertex(&self, x: usize, y: usize) -> Vertex {
+        Vertex::Point {
+            col: x as u32,
+            row: self.size - y as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Vertex {
+        s.parse().unwrap()
+    }
+
+    fn play_all(board: &mut GameBoard, moves: &[&str]) {
+        for m in moves {
+            let color = board.to_move();
+            board.play(color, v(m)).unwrap();
+        }
+    }
+
+    #[test]
+    fn captures_are_reported_and_undone() {
+        let mut board = GameBoard::new(9);
+        play_all(&mut board, &["D5", "E5", "F5", "A9", "E4", "A8"]);
+        let played = board.play(Color::Black, v("E6")).unwrap();
+        assert_eq!(played.captured, vec![v("E5")]);
+        assert_eq!(board.prisoners(Color::Black), 1);
+        assert_eq!(board.stone_at(v("E5")), None);
+        assert_eq!(board.to_move(), Color::White);
+
+        let undone = board.undo().unwrap();
+        assert_eq!(undone.vertex, v("E6"));
+        assert_eq!(board.stone_at(v("E5")), Some(Color::White));
+        assert_eq!(board.stones(Color::White), vec![v("A9"), v("A8"), v("E5")]);
+        assert_eq!(board.prisoners(Color::Black), 0);
+        assert_eq!(board.to_move(), Color::Black);
+    }
+
+    #[test]
+    fn rejects_illegal_moves() {
+        let mut board = GameBoard::new(9);
+        assert_eq!(
+            board.check(Color::White, v("D4")),
+            Err(IllegalMove::WrongTurn(Color::Black))
+        );
+        assert_eq!(
+            board.check(Color::Black, v("K5")),
+            Err(IllegalMove::OffBoard)
+        );
+        play_all(&mut board, &["B9", "D4", "A8"]);
+        assert_eq!(
+            board.check(Color::White, v("D4")),
+            Err(IllegalMove::Occupied)
+        );
+        assert_eq!(
+            board.check(Color::White, v("A9")),
+            Err(IllegalMove::Suicide)
+        );
+    }
+
+    #[test]
+    fn ko_forbids_immediate_retake() {
+        let mut board = GameBoard::new(9);
+        play_all(
+            &mut board,
+            &["D4", "E4", "C5", "F5", "D6", "E6", "J9", "D5"],
+        );
+        let played = board.play(Color::Black, v("E5")).unwrap();
+        assert_eq!(played.captured, vec![v("D5")]);
+        assert_eq!(board.ko(), Some(v("D5")));
+        assert_eq!(board.check(Color::White, v("D5")), Err(IllegalMove::Ko));
+
+        // 劫材交换后可以提回
+        play_all(&mut board, &["A1", "B1"]);
+        assert_eq!(board.ko(), None);
+        assert!(board.check(Color::White, v("D5")).is_ok());
+    }
+
+    #[test]
+    fn handicap_stones_are_not_moves() {
+        let mut board = GameBoard::with_handicap(9, &[v("C3"), v("G7")]);
+        assert_eq!(board.to_move(), Color::White);
+        assert_eq!(board.stones(Color::Black), vec![v("G7"), v("C3")]);
+        assert_eq!(
+            board.check(Color::White, v("C3")),
+            Err(IllegalMove::Occupied)
+        );
+        play_all(&mut board, &["E5"]);
+        assert!(board.undo().is_some());
+        assert!(board.undo().is_none());
+        assert_eq!(board.handicap(), &[v("C3"), v("G7")]);
+        assert_eq!(board.to_move(), Color::White);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::engine::protocol::Color;
+use crate::game::EndReason;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+
+// 每对档位之间预置一局虚拟和棋：全胜/全负时估计值不至于发散
+const PRIOR_DRAWS: f64 = 1.0;
+// 95% 置信区间
+const Z_95: f64 = 1.96;
+
+/// 一局校准对局的结果，逐行追加到 results.jsonl
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibrationGame {
+    pub run_id: String,
+    pub black: String, // 档位名
+    pub white: String,
+    pub board_size: u32,
+    pub result: String, // SGF RE 形式
+    pub winner: Option<Color>,
+    #[serde(default)]
+    pub reason: Option<EndReason>, // 双方连续 pass、认输或达到手数上限；早期记录没有此项
+    pub moves: usize,
+    pub ended_at: i64,
+}
+
+/// 一轮校准的进度
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibrationProgress {
+    pub run_id: String,
+    pub profiles: Vec<String>,
+    pub games_per_pair: u32,
+    pub total: usize,
+    pub played: usize,
+    pub failed: usize, // 引擎出错或无法开局而未计入的局数
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+/// 某一档位的 Elo 估计：以参与统计的第一个档位为 0 分
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rating {
+    pub profile: String,
+    pub elo: f64,
+    pub low: f64, // 95% 置信区间
+    pub high: f64,
+    pub games: usize,
+    pub score: f64, // 胜 1 分，和 0.5 分
+}
+
+/// 校准结果目录：results.jsonl 记录历次校准的每一局；同一时间只进行一轮校准
+#[derive(Debug)]
+pub struct CalibrationStore {
+    path: PathBuf,
+    progress: Mutex<Option<CalibrationProgress>>, // 最近一轮
+}
+
+impl CalibrationStore {
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("create calibration dir {}", dir.display()))?;
+        Ok(Self {
+            path: dir.join("results.jsonl"),
+            progress: Mutex::new(None),
+        })
+    }
+
+    /// 登记新一轮校准；上一轮尚未结束时返回 false
+    pub fn try_start(&self, progress: CalibrationProgress) -> bool {
+        let mut current = self.lock();
+        if current.as_ref().is_some_and(|p| p.finished_at.is_none()) {
+            return false;
+        }
+        *current = Some(progress);
+        true
+    }
+
+    pub fn progress(&self) -> Option<CalibrationProgress> {
+        self.lock().clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut CalibrationProgress)) {
+        if let Some(progress) = self.lock().as_mut() {
+            f(progress);
+        }
+    }
+
+    pub async fn append(&self, game: &CalibrationGame) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(game)?;
+        line.push(b'\n');
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// 读取全部（或指定一轮的）结果；无法解析的行跳过
+    pub async fn load(&self, run_id: Option<&str>) -> anyhow::Result<Vec<CalibrationGame>> {
+        let text = match tokio::fs::read_to_string(&self.path).await {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(text
+            .lines()
+            .filter_map(|line| serde_json::from_str::<CalibrationGame>(line).ok())
+            .filter(|game| run_id.is_none_or(|id| game.run_id == id))
+            .collect())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<CalibrationProgress>> {
+        self.progress.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 按 Bradley-Terry 模型求各档位 Elo 的极大似然估计（牛顿法），置信区间取自 Fisher 信息矩阵。
+/// profiles 决定顺序，其中没有对局的档位不参与统计
+pub fn elo_ratings(profiles: &[String], games: &[CalibrationGame]) -> Vec<Rating> {
+    let index = |name: &str| profiles.iter().position(|p| p == name);
+    let n = profiles.len();
+    let mut played = vec![vec![0.0; n]; n]; // played[i][j]：i 与 j 的对局数
+    let mut scored = vec![vec![0.0; n]; n]; // scored[i][j]：i 对 j 的得分
+    for game in games {
+        let (Some(b), Some(w)) = (index(&game.black), index(&game.white)) else {
+            continue;
+        };
+        if b == w {
+            continue;
+        }
+        let black_score = match game.winner {
+            Some(Color::Black) => 1.0,
+            Some(Color::White) => 0.0,
+            None => 0.5,
+        };
+        played[b][w] += 1.0;
+        played[w][b] += 1.0;
+        scored[b][w] += black_score;
+        scored[w][b] += 1.0 - black_score;
+    }
+    let active: Vec<usize> = (0..n)
+        .filter(|&i| played[i].iter().any(|&g| g > 0.0))
+        .collect();
+    let k = active.len();
+    if k < 2 {
+        return Vec::new();
+    }
+    let mut games_between = vec![vec![0.0; k]; k];
+    let mut score_between = vec![vec![0.0; k]; k];
+    for (a, &i) in active.iter().enumerate() {
+        for (b, &j) in active.iter().enumerate() {
+            if a != b {
+                games_between[a][b] = played[i][j] + PRIOR_DRAWS;
+                score_between[a][b] = scored[i][j] + PRIOR_DRAWS / 2.0;
+            }
+        }
+    }
+
+    // theta 为自然对数尺度的实力，第一个档位固定为 0；牛顿法只在其余 k-1 个参数上进行
+    let mut theta = vec![0.0; k];
+    let mut covariance = vec![vec![0.0; k - 1]; k - 1];
+    for _ in 0..100 {
+        let mut gradient = vec![0.0; k];
+        let mut information = vec![vec![0.0; k]; k];
+        for a in 0..k {
+            for b in 0..k {
+                if a == b {
+                    continue;
+                }
+                let p = logistic(theta[a] - theta[b]);
+                let w = games_between[a][b] * p * (1.0 - p);
+                gradient[a] += score_between[a][b] - games_between[a][b] * p;
+                information[a][a] += w;
+                information[a][b] -= w;
+            }
+        }
+        let reduced: Vec<Vec<f64>> = information[1..]
+            .iter()
+            .map(|row| row[1..].to_vec())
+            .collect();
+        let Some(inverse) = invert(reduced) else {
+            break;
+        };
+        let step: Vec<f64> = inverse
+            .iter()
+            .map(|row| row.iter().zip(&gradient[1..]).map(|(x, g)| x * g).sum())
+            .collect();
+        for (t, d) in theta[1..].iter_mut().zip(&step) {
+            *t += d;
+        }
+        covariance = inverse;
+        if step.iter().all(|d| d.abs() < 1e-10) {
+            break;
+        }
+    }
+
+    let scale = 400.0 / std::f64::consts::LN_10;
+    active
+        .iter()
+        .enumerate()
+        .map(|(a, &i)| {
+            let se = if a == 0 {
+                0.0
+            } else {
+                covariance[a - 1][a - 1].max(0.0).sqrt()
+            };
+            let elo = theta[a] * scale;
+            Rating {
+                profile: profiles[i].clone(),
+                elo,
+                low: elo - Z_95 * se * scale,
+                high: elo + Z_95 * se * scale,
+                games: played[i].iter().sum::<f64>() as usize,
+                score: scored[i].iter().sum(),
+            }
+        })
+        .collect()
+}
+
+fn logistic(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+// 高斯-约当消元求逆；奇异时返回 None
+fn invert(mut m: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = m.len();
+    let mut inv: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        inv.swap(col, pivot);
+        let d = m[col][col];
+        for j in 0..n {
+            m[col][j] /= d;
+            inv[col][j] /= d;
+        }
+        for row in 0..n {
+            if row != col {
+                let f = m[row][col];
+                for j in 0..n {
+                    m[row][j] -= f * m[col][j];
+                    inv[row][j] -= f * inv[col][j];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(black: &str, white: &str, winner: Option<Color>) -> CalibrationGame {
+        CalibrationGame {
+            run_id: "r".to_string(),
+            black: black.to_string(),
+            white: white.to_string(),
+            board_size: 9,
+            result: String::new(),
+            winner,
+            reason: None,
+            moves: 0,
+            ended_at: 0,
+        }
+    }
+
+    #[test]
+    fn elo_matches_closed_form_for_two_levels() {
+        let profiles = vec![
+            "1star".to_string(),
+            "2star".to_string(),
+            "3star".to_string(),
+        ];
+        // 二星对一星 10 局 7 胜 1 和 2 负；三星没有对局，不参与统计
+        let mut games = Vec::new();
+        games.extend((0..7).map(|_| game("2star", "1star", Some(Color::Black))));
+        games.push(game("1star", "2star", None));
+        games.extend((0..2).map(|_| game("1star", "2star", Some(Color::Black))));
+        let ratings = elo_ratings(&profiles, &games);
+        assert_eq!(ratings.len(), 2);
+        assert_eq!(ratings[0].profile, "1star");
+        assert_eq!(ratings[0].elo, 0.0);
+        assert_eq!(ratings[1].games, 10);
+        assert_eq!(ratings[1].score, 7.5);
+        // 加上一局虚拟和棋后得分率为 8/11，Elo 差为 400·log10(8/3)
+        let expected = 400.0 * (8.0f64 / 3.0).log10();
+        assert!((ratings[1].elo - expected).abs() < 1e-6);
+        // 标准误为 1/sqrt(n·p·(1-p))，10 局还不足以排除两档实力相同
+        let half_width = Z_95 * 400.0 / std::f64::consts::LN_10 / (24.0f64 / 11.0).sqrt();
+        assert!((ratings[1].high - expected - half_width).abs() < 1e-6);
+        assert!(ratings[1].low < 0.0);
+    }
+
+    #[test]
+    fn levels_are_ordered_by_results() {
+        let profiles: Vec<String> = ["a", "b", "c"].map(String::from).to_vec();
+        let mut games = Vec::new();
+        for _ in 0..6 {
+            games.push(game("b", "a", Some(Color::Black)));
+            games.push(game("c", "b", Some(Color::Black)));
+            games.push(game("a", "c", Some(Color::White)));
+        }
+        let ratings = elo_ratings(&profiles, &games);
+        assert!(ratings[0].elo < ratings[1].elo && ratings[1].elo < ratings[2].elo);
+    }
+}
@@ -0,0 +1,328 @@
+use crate::engine::protocol::{Color, TimeControl};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// 一方的剩余用时
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SideClock {
+    main: Duration,
+    period: Duration, // 当前读秒周期剩余；费舍尔不用
+    periods: u32,     // 日式读秒剩余次数
+    stones: u32,      // 加拿大读秒本周期还需下的手数
+}
+
+impl SideClock {
+    fn new(control: TimeControl) -> Self {
+        let (main, period, periods, stones) = match control {
+            TimeControl::Byoyomi {
+                main_time,
+                period_time,
+                periods,
+            } => (main_time, period_time, periods, 0),
+            TimeControl::Canadian {
+                main_time,
+                period_time,
+                stones,
+            } => (main_time, period_time, 0, stones),
+            TimeControl::Fischer { main_time, .. } => (main_time, 0, 0, 0),
+        };
+        Self {
+            main: Duration::from_secs(main.into()),
+            period: Duration::from_secs(period.into()),
+            periods,
+            stones,
+        }
+    }
+
+    /// 扣除用时：先扣基本时间，再进入读秒；超时返回 false
+    fn consume(&mut self, control: TimeControl, elapsed: Duration) -> bool {
+        let used = elapsed.min(self.main);
+        self.main -= used;
+        let mut over = elapsed - used;
+        if over.is_zero() {
+            return true;
+        }
+        match control {
+            TimeControl::Byoyomi { period_time, .. } => {
+                // 每用满一个周期扣一次读秒
+                while over >= self.period {
+                    over -= self.period;
+                    self.periods = self.periods.saturating_sub(1);
+                    self.period = Duration::from_secs(period_time.into());
+                    if self.periods == 0 {
+                        self.period = Duration::ZERO;
+                        return false;
+                    }
+                }
+                self.period -= over;
+                true
+            }
+            TimeControl::Canadian { .. } => {
+                if over >= self.period {
+                    self.period = Duration::ZERO;
+                    return false;
+                }
+                self.period -= over;
+                true
+            }
+            TimeControl::Fischer { .. } => false,
+        }
+    }
+
+    /// 一手下完：日式读秒周期复原，加拿大读秒计手数，费舍尔加秒
+    fn finish_move(&mut self, control: TimeControl) {
+        match control {
+            TimeControl::Byoyomi { period_time, .. } if self.main.is_zero() => {
+                self.period = Duration::from_secs(period_time.into());
+            }
+            TimeControl::Canadian {
+                period_time,
+                stones,
+                ..
+            } if self.main.is_zero() => {
+                self.stones = self.stones.saturating_sub(1);
+                if self.stones == 0 {
+                    self.stones = stones;
+                    self.period = Duration::from_secs(period_time.into());
+                }
+            }
+            TimeControl::Fischer { increment, .. } => {
+                self.main += Duration::from_secs(increment.into());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 服务端棋钟：只有轮到的一方在走；落子时按钟切换
+#[derive(Clone, Debug)]
+pub struct GameClock {
+    control: TimeControl,
+    black: SideClock,
+    white: SideClock,
+    running: Option<(Color, Instant)>,
+}
+
+/// 响应体中一方的剩余用时（毫秒）
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SideSnapshot {
+    pub main_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub periods: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stones: Option<u32>,
+}
+
+/// 响应体中的 clock 字段；也用于对局快照
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockSnapshot {
+    pub control: TimeControl,
+    pub running: Option<Color>,
+    pub black: SideSnapshot,
+    pub white: SideSnapshot,
+}
+
+impl GameClock {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            black: SideClock::new(control),
+            white: SideClock::new(control),
+            running: None,
+        }
+    }
+
+    /// 从快照恢复：剩余用时按快照，原先在走的一方从 now 起继续计时
+    pub fn restore(snapshot: &ClockSnapshot, now: Instant) -> Self {
+        let side = |s: &SideSnapshot| SideClock {
+            main: Duration::from_millis(s.main_ms),
+            period: Duration::from_millis(s.period_ms.unwrap_or(0)),
+            periods: s.periods.unwrap_or(0),
+            stones: s.stones.unwrap_or(0),
+        };
+        Self {
+            control: snapshot.control,
+            black: side(&snapshot.black),
+            white: side(&snapshot.white),
+            running: snapshot.running.map(|color| (color, now)),
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    pub fn start(&mut self, color: Color, now: Instant) {
+        self.running = Some((color, now));
+    }
+
+    /// color 一方落子后按钟：扣除其用时并开始对方计时；已超时返回 false（棋钟停止）
+    pub fn press(&mut self, color: Color, now: Instant) -> bool {
+        let control = self.control;
+        let elapsed = match self.running {
+            Some((running, since)) if running == color => now.saturating_duration_since(since),
+            _ => Duration::ZERO,
+        };
+        let side = self.side_mut(color);
+        if !side.consume(control, elapsed) {
+            self.running = None;
+            return false;
+        }
+        side.finish_move(control);
+        self.running = Some((color.opponent(), now));
+        true
+    }
+
+    /// 停表（终局）：扣除正在走的一方的用时
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((color, since)) = self.running.take() {
+            let control = self.control;
+            self.side_mut(color)
+                .consume(control, now.saturating_duration_since(since));
+        }
+    }
+
+    /// 正在走的一方是否已超时
+    pub fn flagged(&self, now: Instant) -> Option<Color> {
+        let (color, since) = self.running?;
+        let mut side = *self.side(color);
+        (!side.consume(self.control, now.saturating_duration_since(since))).then_some(color)
+    }
+
+    /// 引擎 time_left 的参数：剩余秒数与读秒次数/手数（基本时间内为 0）
+    pub fn time_left(&self, color: Color, now: Instant) -> (u32, u32) {
+        let side = self.current(color, now);
+        if !side.main.is_zero() || matches!(self.control, TimeControl::Fischer { .. }) {
+            return (side.main.as_secs() as u32, 0);
+        }
+        match self.control {
+            TimeControl::Byoyomi { .. } => (side.period.as_secs() as u32, side.periods),
+            _ => (side.period.as_secs() as u32, side.stones),
+        }
+    }
+
+    pub fn snapshot(&self, now: Instant) -> ClockSnapshot {
+        let snap = |color| {
+            let side = self.current(color, now);
+            let ms = |d: Duration| d.as_millis() as u64;
+            let (period_ms, periods, stones) = match self.control {
+                TimeControl::Byoyomi { .. } => (Some(ms(side.period)), Some(side.periods), None),
+                TimeControl::Canadian { .. } => (Some(ms(side.period)), None, Some(side.stones)),
+                TimeControl::Fischer { .. } => (None, None, None),
+            };
+            SideSnapshot {
+                main_ms: ms(side.main),
+                period_ms,
+                periods,
+                stones,
+            }
+        };
+        ClockSnapshot {
+            control: self.control,
+            running: self.running.map(|(color, _)| color),
+            black: snap(Color::Black),
+            white: snap(Color::White),
+        }
+    }
+
+    // 计入正在走的时间后的一方用时
+    fn current(&self, color: Color, now: Instant) -> SideClock {
+        let mut side = *self.side(color);
+        if let Some((running, since)) = self.running
+            && running == color
+        {
+            side.consume(self.control, now.saturating_duration_since(since));
+        }
+        side
+    }
+
+    fn side(&self, color: Color) -> &SideClock {
+        match color {
+            Color::Black => &self.black,
+            Color::White => &self.white,
+        }
+    }
+
+    fn side_mut(&mut self, color: Color) -> &mut SideClock {
+        match color {
+            Color::Black => &mut self.black,
+            Color::White => &mut self.white,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn byoyomi_periods_reset_per_move_and_run_out() {
+        let t0 = Instant::now();
+        let mut clock = GameClock::new(TimeControl::Byoyomi {
+            main_time: 10,
+            period_time: 5,
+            periods: 2,
+        });
+        clock.start(Color::Black, t0);
+        // 用完基本时间并进入读秒 3 秒：不扣次数，下一手周期复原
+        assert!(clock.press(Color::Black, t0 + secs(13)));
+        let snap = clock.snapshot(t0 + secs(13));
+        assert_eq!(snap.black.main_ms, 0);
+        assert_eq!(snap.black.period_ms, Some(5000));
+        assert_eq!(snap.black.periods, Some(2));
+        assert_eq!(snap.running, Some(Color::White));
+
+        clock.start(Color::Black, t0);
+        assert_eq!(clock.time_left(Color::Black, t0 + secs(6)), (4, 1));
+        assert_eq!(clock.flagged(t0 + secs(9)), None);
+        assert_eq!(clock.flagged(t0 + secs(10)), Some(Color::Black));
+        assert!(!clock.press(Color::Black, t0 + secs(10)));
+    }
+
+    #[test]
+    fn canadian_needs_stones_within_period() {
+        let t0 = Instant::now();
+        let mut clock = GameClock::new(TimeControl::Canadian {
+            main_time: 0,
+            period_time: 10,
+            stones: 2,
+        });
+        clock.start(Color::White, t0);
+        assert!(clock.press(Color::White, t0 + secs(6)));
+        assert_eq!(clock.time_left(Color::White, t0 + secs(6)), (4, 1));
+        clock.start(Color::White, t0);
+        // 本周期第二手下完后周期复原
+        assert!(clock.press(Color::White, t0 + secs(3)));
+        assert_eq!(clock.time_left(Color::White, t0), (10, 2));
+        clock.start(Color::White, t0);
+        assert!(!clock.press(Color::White, t0 + secs(10)));
+    }
+
+    #[test]
+    fn fischer_adds_increment() {
+        let t0 = Instant::now();
+        let mut clock = GameClock::new(TimeControl::Fischer {
+            main_time: 10,
+            increment: 5,
+        });
+        clock.start(Color::Black, t0);
+        assert!(clock.press(Color::Black, t0 + secs(8)));
+        assert_eq!(clock.snapshot(t0 + secs(8)).black.main_ms, 7000);
+        assert_eq!(clock.flagged(t0 + secs(8) + secs(9)), None);
+        assert_eq!(clock.flagged(t0 + secs(8) + secs(11)), Some(Color::White));
+        let restored = GameClock::restore(&clock.snapshot(t0 + secs(10)), t0 + secs(100));
+        assert_eq!(restored.snapshot(t0 + secs(100)).white.main_ms, 8000);
+        assert_eq!(restored.flagged(t0 + secs(107)), None);
+        clock.stop(t0 + secs(8) + secs(4));
+        assert_eq!(clock.snapshot(t0).white.main_ms, 6000);
+        assert_eq!(clock.snapshot(t0).running, None);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::engine::protocol::{Color, Vertex};
+use crate::game::GameEnd;
+use crate::game::archive::engine_name;
+use crate::game::board::{GameBoard, IllegalMove, PlayedMove};
+use crate::game::sgf::{SgfGame, write_game};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// 观众落后超过这么多条事件时改为重发整盘状态
+const EVENT_BUFFER: usize = 64;
+
+/// 表演赛的一方：难度档位
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entrant {
+    pub profile: String,
+    pub level: Option<usize>, // 档位序号（从 1 开始）
+    pub name: String,         // 棋谱中的名字
+}
+
+impl Entrant {
+    pub fn new(profile: &str, level: Option<usize>) -> Self {
+        Self {
+            profile: profile.to_string(),
+            level,
+            name: engine_name(profile, level),
+        }
+    }
+}
+
+/// 推送给观众的一手棋
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveEvent {
+    pub move_number: usize,
+    pub color: Color,
+    pub vertex: String,        // GTP 坐标或 "pass"
+    pub captured: Vec<String>, // 本手提掉的子
+}
+
+impl MoveEvent {
+    fn of(number: usize, played: &PlayedMove) -> Self {
+        Self {
+            move_number: number,
+            color: played.color,
+            vertex: played.vertex.to_string(),
+            captured: played.captured.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+}
+
+/// 观众订阅的事件：逐手落子，最后一条为终局或中止
+#[derive(Clone, Debug)]
+pub enum ExhibitionEvent {
+    Move(MoveEvent),
+    End(ExhibitionOutcome),
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExhibitionOutcome {
+    pub end: Option<GameEnd>,
+    pub error: Option<String>, // 引擎出错中止时的错误码
+}
+
+/// 整盘状态：观众进场或落后太多时先收到它，再接着收逐手事件
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExhibitionView {
+    pub exhibition_id: String,
+    pub black: Entrant,
+    pub white: Entrant,
+    pub rules: String,
+    pub board_size: u32,
+    pub komi: f32,
+    pub moves: Vec<MoveEvent>,
+    pub outcome: Option<ExhibitionOutcome>, // 进行中为空
+    pub started_at: i64,
+}
+
+/// 列表中的一场表演赛
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExhibitionSummary {
+    pub exhibition_id: String,
+    pub black: String,
+    pub white: String,
+    pub board_size: u32,
+    pub move_number: usize,
+    pub result: Option<String>, // 进行中为空；中止为 "Void"
+    pub started_at: i64,
+}
+
+/// AI 对 AI 表演赛：服务端轮流向双方引擎要棋并落在权威棋盘上，
+/// 每手通过广播推送给所有观众
+#[derive(Debug)]
+pub struct Exhibition {
+    pub black: Entrant,
+    pub white: Entrant,
+    pub rules: String,
+    pub board_size: u32,
+    pub komi: f32,
+    pub board: GameBoard,
+    pub started_at: i64,
+    pub calibration: bool, // 难度校准的对局：不占表演赛名额
+    outcome: Option<ExhibitionOutcome>,
+    finished_at: Option<i64>,
+    events: broadcast::Sender<ExhibitionEvent>,
+}
+
+impl Exhibition {
+    pub fn new(
+        black: Entrant,
+        white: Entrant,
+        rules: &str,
+        board_size: u32,
+        komi: f32,
+        now: i64,
+    ) -> Self {
+        Self {
+            black,
+            white,
+            rules: rules.to_string(),
+            board_size,
+            komi,
+            board: GameBoard::new(board_size),
+            started_at: now,
+            calibration: false,
+            outcome: None,
+            finished_at: None,
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    /// 终局或中止信息；进行中为空
+    pub fn outcome(&self) -> Option<&ExhibitionOutcome> {
+        self.outcome.as_ref()
+    }
+
+    pub fn finished_at(&self) -> Option<i64> {
+        self.finished_at
+    }
+
+    /// 落子并推送；非法着法不改变棋盘
+    pub fn play(&mut self, color: Color, vertex: Vertex) -> Result<(), IllegalMove> {
+        let number = self.board.moves().len() + 1;
+        let event = MoveEvent::of(number, self.board.play(color, vertex)?);
+        let _ = self.events.send(ExhibitionEvent::Move(event));
+        Ok(())
+    }
+
+    pub fn finish(&mut self, end: GameEnd) {
+        self.finished_at = Some(end.ended_at);
+        self.close(ExhibitionOutcome {
+            end: Some(end),
+            error: None,
+        });
+    }
+
+    /// 引擎出错或给出非法着法时中止，不判胜负
+    pub fn abort(&mut self, error: &str, now: i64) {
+        self.finished_at = Some(now);
+        self.close(ExhibitionOutcome {
+            end: None,
+            error: Some(error.to_string()),
+        });
+    }
+
+    fn close(&mut self, outcome: ExhibitionOutcome) {
+        if self.outcome.is_some() {
+            return;
+        }
+        let _ = self.events.send(ExhibitionEvent::End(outcome.clone()));
+        self.outcome = Some(outcome);
+    }
+
+    /// 当前整盘状态与此后的事件流；在同一把锁内取得，二者之间不会漏掉着法
+    pub fn subscribe(&self, id: &str) -> (ExhibitionView, broadcast::Receiver<ExhibitionEvent>) {
+        (self.view(id), self.events.subscribe())
+    }
+
+    pub fn view(&self, id: &str) -> ExhibitionView {
+        ExhibitionView {
+            exhibition_id: id.to_string(),
+            black: self.black.clone(),
+            white: self.white.clone(),
+            rules: self.rules.clone(),
+            board_size: self.board_size,
+            komi: self.komi,
+            moves: self
+                .board
+                .moves()
+                .iter()
+                .enumerate()
+                .map(|(i, m)| MoveEvent::of(i + 1, m))
+                .collect(),
+            outcome: self.outcome.clone(),
+            started_at: self.started_at,
+        }
+    }
+
+    pub fn summary(&self, id: &str) -> ExhibitionSummary {
+        ExhibitionSummary {
+            exhibition_id: id.to_string(),
+            black: self.black.name.clone(),
+            white: self.white.name.clone(),
+            board_size: self.board_size,
+            move_number: self.board.moves().len(),
+            result: self.result().map(str::to_string),
+            started_at: self.started_at,
+        }
+    }
+
+    fn result(&self) -> Option<&str> {
+        self.outcome.as_ref().map(|o| match &o.end {
+            Some(end) => end.result.as_str(),
+            None => "Void",
+        })
+    }
+
+    pub fn sgf(&self) -> String {
+        write_game(&SgfGame {
+            board_size: self.board_size,
+            komi: self.komi,
+            rules: &self.rules,
+            black: &self.black.name,
+            white: &self.white.name,
+            started_at: self.started_at,
+            result: self.result(),
+            handicap: &[],
+            moves: &self.board.move_list(),
+            comments: None,
+        })
+    }
+}
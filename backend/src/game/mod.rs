@@ -0,0 +1,119 @@
+use crate::engine::protocol::{Color, Score};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+pub mod archive;
+pub mod board;
+pub mod calibration;
+pub mod clock;
+pub mod exhibition;
+pub mod sgf;
+pub mod snapshot;
+
+// 每局事件广播的缓冲；连接落后更多时改发整盘状态
+const GAME_EVENT_BUFFER: usize = 64;
+
+/// 推送给对局 WebSocket 连接的事件；对局双方（人人对局）共用一个广播
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GameEvent {
+    /// 一手棋已落到权威棋盘（人类、引擎或人人对局的任一方）
+    #[serde(rename_all = "camelCase")]
+    Move {
+        color: Color,
+        vertex: String, // GTP 坐标或 "pass"
+        captured: Vec<String>,
+        move_number: usize,
+    },
+    /// 悔棋或整手失败后撤回着法，附带撤回后的盘面
+    #[serde(rename_all = "camelCase")]
+    Undo {
+        move_number: usize,
+        black: Vec<String>,
+        white: Vec<String>,
+    },
+    /// 第 N 手后的形势判断
+    #[serde(rename_all = "camelCase")]
+    Eval {
+        move_number: usize,
+        score: String,
+    },
+    /// 人人对局的另一方凭邀请加入
+    Joined,
+    End {
+        end: GameEnd,
+    },
+}
+
+pub fn event_channel() -> broadcast::Sender<GameEvent> {
+    broadcast::channel(GAME_EVENT_BUFFER).0
+}
+
+/// 对局结束的原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EndReason {
+    Resignation,
+    DoublePass,
+    Timeout,
+    MoveLimit, // AI 对弈达到手数上限后数子
+}
+
+/// 终局记录；结束后的对局拒绝继续落子
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameEnd {
+    pub reason: EndReason,
+    pub result: String, // SGF RE 形式："B+R" / "W+7.5" / "0"；数子失败为 "?"
+    pub winner: Option<Color>, // 和棋或数子失败时为空
+    pub dead: Vec<String>, // 数子时判定的死子（GTP 坐标）
+    pub ended_at: i64,
+}
+
+impl GameEnd {
+    pub fn resignation(loser: Color, ended_at: i64) -> Self {
+        let winner = loser.opponent();
+        Self {
+            reason: EndReason::Resignation,
+            result: format!("{winner}+R"),
+            winner: Some(winner),
+            dead: Vec::new(),
+            ended_at,
+        }
+    }
+
+    pub fn timeout(loser: Color, ended_at: i64) -> Self {
+        let winner = loser.opponent();
+        Self {
+            reason: EndReason::Timeout,
+            result: format!("{winner}+T"),
+            winner: Some(winner),
+            dead: Vec::new(),
+            ended_at,
+        }
+    }
+
+    pub fn scored(score: Option<Score>, dead: Vec<String>, ended_at: i64) -> Self {
+        Self::counted(EndReason::DoublePass, score, dead, ended_at)
+    }
+
+    /// 达到手数上限，按当前局面数子
+    pub fn move_limit(score: Option<Score>, dead: Vec<String>, ended_at: i64) -> Self {
+        Self::counted(EndReason::MoveLimit, score, dead, ended_at)
+    }
+
+    fn counted(reason: EndReason, score: Option<Score>, dead: Vec<String>, ended_at: i64) -> Self {
+        let winner = match score {
+            Some(Score::Black(_)) => Some(Color::Black),
+            Some(Score::White(_)) => Some(Color::White),
+            Some(Score::Draw) | None => None,
+        };
+        Self {
+            reason,
+            result: score.map_or_else(|| "?".to_string(), |s| s.to_string()),
+            winner,
+            dead,
+            ended_at,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::engine::protocol::{Color, Vertex};
+use crate::game::archive::GameRecord;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// 写入 SGF 的一局棋：双方名字、着法与可选的逐手评注
+pub struct SgfGame<'a> {
+    pub board_size: u32,
+    pub komi: f32,
+    pub rules: &'a str,
+    pub black: &'a str,
+    pub white: &'a str,
+    pub started_at: i64,
+    pub result: Option<&'a str>, // 未结束时为空
+    pub handicap: &'a [Vertex],
+    pub moves: &'a [(Color, Vertex)],
+    pub comments: Option<&'a BTreeMap<usize, String>>, // 第 N 手后的形势判断
+}
+
+/// 生成只含主线的 SGF（FF[4]）；文本字段保持 ASCII，便于 review::parser 原样读回。
+/// 未结束的对局不写 RE；comments 为 false 时不附带形势判断评注
+pub fn write_sgf(record: &GameRecord, comments: bool) -> String {
+    let opponent = record.opponent_name();
+    let (black, white) = match record.human_color {
+        Color::Black => ("Human", opponent.as_str()),
+        Color::White => (opponent.as_str(), "Human"),
+    };
+    write_game(&SgfGame {
+        board_size: record.board_size,
+        komi: record.komi,
+        rules: &record.rules,
+        black,
+        white,
+        started_at: record.started_at,
+        result: record.end.as_ref().map(|end| end.result.as_str()),
+        handicap: &record.handicap_vertices(),
+        moves: &record.move_list(),
+        comments: comments.then_some(&record.evals),
+    })
+}
+
+pub fn write_game(game: &SgfGame) -> String {
+    let size = game.board_size;
+    let date = time::OffsetDateTime::from_unix_timestamp(game.started_at)
+        .unwrap_or(time::OffsetDateTime::UNIX_EPOCH)
+        .date();
+    let mut out = String::from("(;FF[4]GM[1]CA[UTF-8]AP[go-backend]");
+    let _ = write!(out, "SZ[{size}]KM[{}]", game.komi);
+    let _ = write!(out, "RU[{}]", escape(&rules_name(game.rules)));
+    let _ = write!(
+        out,
+        "PB[{}]PW[{}]DT[{date}]",
+        escape(game.black),
+        escape(game.white)
+    );
+    if let Some(result) = game.result {
+        let _ = write!(out, "RE[{}]", escape(result));
+    }
+    if !game.handicap.is_empty() {
+        let _ = write!(out, "HA[{}]AB", game.handicap.len());
+        for vertex in game.handicap {
+            let _ = write!(out, "[{}]", vertex.to_sgf(size).unwrap_or_default());
+        }
+        out.push_str("PL[W]");
+    }
+    for (i, (color, vertex)) in game.moves.iter().enumerate() {
+        let color = match color {
+            Color::Black => 'B',
+            Color::White => 'W',
+        };
+        let coord = vertex.to_sgf(size).unwrap_or_default();
+        let _ = write!(out, "\n;{color}[{coord}]");
+        if let Some(score) = game.comments.and_then(|evals| evals.get(&(i + 1))) {
+            let _ = write!(out, "C[{}]", escape(&format!("KataGo estimate: {score}")));
+        }
+    }
+    out.push_str(")\n");
+    out
+}
+
+// SGF 中常见的规则名写法：Chinese / Japanese / Korean / AGA / NZ
+fn rules_name(rules: &str) -> String {
+    match rules.to_ascii_lowercase().as_str() {
+        "aga" => "AGA".to_string(),
+        "nz" | "new-zealand" => "NZ".to_string(),
+        lower => {
+            let mut chars = lower.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameEnd;
+    use crate::game::archive::{Opponent, RecordedMove};
+    use crate::review::StoneColor;
+    use crate::review::parser::parse_sgf;
+
+    #[test]
+    fn exported_game_parses_back() {
+        let moves = [
+            (Color::White, "Q4"),
+            (Color::Black, "D16"),
+            (Color::White, "pass"),
+        ]
+        .map(|(color, vertex)| RecordedMove {
+            color,
+            vertex: vertex.to_string(),
+        })
+        .to_vec();
+        let record = GameRecord {
+            game_id: "g-1".to_string(),
+            sid: "s".to_string(),
+            opponent: Opponent::Engine,
+            profile: "3star".to_string(),
+            level: Some(3),
+            rules: "chinese".to_string(),
+            board_size: 19,
+            komi: 0.5,
+            human_color: Color::Black,
+            handicap: vec!["D4".to_string(), "Q16".to_string()],
+            time_control: None,
+            moves,
+            evals: BTreeMap::from([(2, "B+12.5 [x]".to_string())]),
+            end: Some(GameEnd::resignation(Color::Black, 0)),
+            started_at: 1_792_108_800, // 2026-10-16
+        };
+        let sgf = write_sgf(&record, true);
+        assert!(sgf.contains("DT[2026-10-16]"));
+
+        let parsed = parse_sgf(&sgf).unwrap();
+        assert_eq!(parsed.board_size, 19);
+        assert_eq!(parsed.komi, 0.5);
+        assert_eq!(parsed.meta.rules.as_deref(), Some("Chinese"));
+        assert_eq!(parsed.meta.black.as_deref(), Some("Human"));
+        assert_eq!(parsed.meta.white.as_deref(), Some("KataGo 3-star"));
+        assert_eq!(parsed.meta.result.as_deref(), Some("W+R"));
+        assert_eq!(parsed.initial_setup.black, vec!["dp", "pd"]);
+        assert_eq!(parsed.initial_setup.to_play, Some(StoneColor::White));
+        let moves: Vec<_> = parsed
+            .moves
+            .iter()
+            .map(|m| (m.color, m.coord.clone()))
+            .collect();
+        assert_eq!(
+            moves,
+            vec![
+                (StoneColor::White, Some("pp".to_string())),
+                (StoneColor::Black, Some("dd".to_string())),
+                (StoneColor::White, None),
+            ]
+        );
+        assert_eq!(
+            parsed.moves[1].comment.as_deref(),
+            Some("KataGo estimate: B+12.5 [x]")
+        );
+        assert!(
+            parse_sgf(&write_sgf(&record, false)).unwrap().moves[1]
+                .comment
+                .is_none()
+        );
+    }
+}
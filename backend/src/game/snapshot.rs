@@ -0,0 +1,133 @@
+use crate::game::archive::GameRecord;
+use crate::game::clock::ClockSnapshot;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::{mpsc, oneshot};
+
+/// 进行中对局的快照：服务重启后据此恢复对局（引擎在首次使用时按着法重放）
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameSnapshot {
+    #[serde(flatten)]
+    pub record: GameRecord,
+    pub undo_limit: Option<u32>,
+    #[serde(default)]
+    pub undos_used: u32,
+    #[serde(default)]
+    pub invite_token: Option<String>, // 人人对局的邀请令牌
+    #[serde(default)]
+    pub scoring_engine: bool, // 人人对局是否由引擎数子
+    pub clock: Option<ClockSnapshot>, // 停机期间不计时
+}
+
+enum Op {
+    Save(Box<GameSnapshot>),
+    Remove(String),
+    Flush(oneshot::Sender<()>),
+}
+
+/// 快照目录：每局一个 JSON 文件。写入由单个后台任务按提交顺序完成，
+/// 同一局先后两次快照不会互相覆盖成旧版本
+#[derive(Debug)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    tx: mpsc::UnboundedSender<Op>,
+}
+
+impl SnapshotStore {
+    /// 创建目录并启动写入任务；须在 tokio 运行时内调用
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("create game snapshot dir {}", dir.display()))?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_loop(dir.to_path_buf(), rx));
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            tx,
+        })
+    }
+
+    /// 读取目录中的全部快照；损坏的文件记录日志后跳过
+    pub fn load_all(&self) -> Vec<GameSnapshot> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut snapshots = Vec::new();
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let parsed = std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Ok(serde_json::from_slice::<GameSnapshot>(&bytes)?));
+            match parsed {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(err) => {
+                    tracing::warn!(?err, path = %path.display(), "skipping unreadable game snapshot")
+                }
+            }
+        }
+        snapshots
+    }
+
+    pub fn save(&self, snapshot: GameSnapshot) {
+        let _ = self.tx.send(Op::Save(Box::new(snapshot)));
+    }
+
+    /// 对局释放（关闭或过期）后删除快照
+    pub fn remove(&self, game_id: &str) {
+        let _ = self.tx.send(Op::Remove(game_id.to_string()));
+    }
+
+    /// 等待此前提交的写入全部落盘
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.tx.send(Op::Flush(done)).is_ok() {
+            let _ = wait.await;
+        }
+    }
+}
+
+async fn write_loop(dir: PathBuf, mut rx: mpsc::UnboundedReceiver<Op>) {
+    while let Some(op) = rx.recv().await {
+        match op {
+            Op::Save(snapshot) => {
+                let game_id = snapshot.record.game_id.clone();
+                if let Err(err) = write_snapshot(&dir, &snapshot).await {
+                    tracing::error!(?err, game_id, "failed to write game snapshot");
+                }
+            }
+            Op::Remove(game_id) => {
+                if let Some(path) = path_of(&dir, &game_id)
+                    && let Err(err) = tokio::fs::remove_file(&path).await
+                    && err.kind() != std::io::ErrorKind::NotFound
+                {
+                    tracing::warn!(?err, game_id, "failed to remove game snapshot");
+                }
+            }
+            Op::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+// 先写临时文件再改名，避免崩溃时留下半个文件
+async fn write_snapshot(dir: &Path, snapshot: &GameSnapshot) -> anyhow::Result<()> {
+    let game_id = &snapshot.record.game_id;
+    let path = path_of(dir, game_id).with_context(|| format!("invalid game id {game_id:?}"))?;
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, serde_json::to_vec(snapshot)?).await?;
+    tokio::fs::rename(&tmp, &path).await?;
+    Ok(())
+}
+
+// gameId 只允许字母、数字与 '-'，防止路径穿越
+fn path_of(dir: &Path, game_id: &str) -> Option<PathBuf> {
+    (!game_id.is_empty()
+        && game_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-'))
+    .then(|| dir.join(format!("{game_id}.json")))
+}
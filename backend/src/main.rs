@@ -1,24 +1,42 @@
 mod engine;
+mod game;
 mod review;
+mod session;
 
 use anyhow::{Context, anyhow};
 use axum::{
     Json, Router,
-    extract::{FromRef, FromRequest, Multipart, State},
+    extract::{
+        FromRef, FromRequest, Multipart, Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
     http::{
         HeaderMap, HeaderValue, Method, Request, StatusCode, header::CONTENT_TYPE,
         header::SET_COOKIE,
     },
-    response::{IntoResponse, Response},
-    routing::post,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
 };
+use engine::GoEngine;
+use engine::fake::FakeEngine;
+use engine::native::NativeEngine;
+use engine::protocol::{AnalyzeInfo, Color, GenMove, Score, TimeControl, Vertex};
 use http::Uri;
 use http_body_util::BodyExt;
 use reqwest::Client;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
 use std::path::Path;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::{fs, signal};
 use tower_http::{
     cors::{Any, CorsLayer},
@@ -104,8 +122,36 @@ struct AppState {
     review_store: Arc<dashmap::DashMap<String, review::ReviewState>>, // reviewId -> state
     game_ttl_seconds: i64,
     review_ttl_seconds: i64,
+    #[allow(dead_code)]
     server_start_at: i64,
     sid_locks: Arc<dashmap::DashMap<String, Arc<tokio::sync::Mutex<()>>>>, // 防止同一 sid 并发新建
+    engine_pool: Arc<engine::pool::EnginePool>,
+    // 复盘共用的 analysis 模式引擎，首次分析时启动；进程退出后置空以便重启
+    analysis_engine: Arc<tokio::sync::Mutex<Option<Arc<engine::analysis::AnalysisEngine>>>>,
+    engine_restarts: Arc<std::sync::atomic::AtomicU64>, // 对局引擎崩溃后自动重启次数
+    admin_token: Option<String>,                        // 诊断接口令牌；未配置时诊断接口关闭
+    session_keys: Arc<session::SessionKeys>,            // sid Cookie 的签名密钥
+    engine_backend: EngineBackend,
+    profiles: Arc<engine::profile::ProfileSet>, // 难度档位，启动时加载
+    archive: Arc<game::archive::GameArchive>,   // 已结束对局的磁盘归档
+    snapshots: Arc<game::snapshot::SnapshotStore>, // 进行中对局的快照，重启后恢复
+    exhibitions: Arc<dashmap::DashMap<String, game::exhibition::Exhibition>>, // AI 对 AI 表演赛
+    calibration: Arc<game::calibration::CalibrationStore>, // 难度档位的 Elo 校准结果
+}
+
+/// 对局/复盘引擎来源：配置了 KataGo 时从进程池租借，否则使用进程内轻量引擎
+#[derive(Clone, Debug)]
+enum EngineBackend {
+    KataGo,
+    Native,
+    #[cfg_attr(not(test), allow(dead_code))]
+    Fake {
+        script: Vec<GenMove>,
+    }, // 每个新引擎预置的 genmove 应答（测试用）
+    #[cfg_attr(not(test), allow(dead_code))]
+    Gtp {
+        spec: engine::pool::EngineSpec,
+    }, // 固定命令行的 GTP 进程，经进程池租借（测试用）
 }
 
 impl FromRef<AppState> for Arc<dashmap::DashMap<String, Vec<String>>> {
@@ -128,12 +174,242 @@ impl FromRef<AppState> for Arc<dashmap::DashMap<String, review::ReviewState>> {
 
 #[derive(Clone)]
 struct GameState {
-    sid: String,
+    sid: String, // 开局一方；人人对局的另一方记在 opponent 中
+    created_at: i64,
     last_active_at: i64,
-    engine: Option<std::sync::Arc<engine::gtp::GtpEngine>>, // None 时使用占位行为
-    human_color: String,                                    // "black" or "white"
+    engine: Option<Arc<dyn GoEngine>>, // 进程池租约在对局释放时归还；重启恢复的对局首次使用时才重建
+    profile: String,                   // 难度档位与规则：引擎崩溃后按同一参数重新租借
+    level: Option<usize>,              // 档位序号，用于棋谱与归档中的引擎名
+    rules: String,
+    human_color: String, // 开局一方执子："black" or "white"
+    opponent: game::archive::Opponent,
+    invite_token: Option<String>, // 人人对局的邀请令牌，对方凭此加入
+    scoring_engine: bool,         // 人人对局终局时是否借用引擎数子；人机对局总是数子
     board_size: u32,
     komi: f32,
+    board: game::board::GameBoard, // 权威棋盘：人类着法先经其校验；着法历史用于引擎重启后重放
+    undo_limit: Option<u32>,       // 本局悔棋次数上限（取自难度档位），None 为不限
+    undos_used: u32,
+    end: Option<game::GameEnd>,            // 终局结果；结束后拒绝继续落子
+    clock: Option<game::clock::GameClock>, // 未设置用时规则时为 None
+    evals: BTreeMap<usize, String>, // 第 N 手后的形势判断（score_detail 结果），导出 SGF 时作评注
+    archive: Arc<game::archive::GameArchive>, // 终局时写入归档
+    snapshots: Arc<game::snapshot::SnapshotStore>,
+    events: tokio::sync::broadcast::Sender<game::GameEvent>, // 对局释放时随之关闭，连接据此得知对局已过期
+    turn: Arc<tokio::sync::Mutex<()>>, // 落子、悔棋、提示、数子各自持锁走完整个引擎命令序列
+}
+
+impl GameState {
+    fn human(&self) -> Color {
+        if self.human_color == "black" {
+            Color::Black
+        } else {
+            Color::White
+        }
+    }
+
+    fn is_pvp(&self) -> bool {
+        matches!(self.opponent, game::archive::Opponent::Human { .. })
+    }
+
+    /// 人人对局的邀请尚无人加入：棋钟不走，开局一方关闭时直接释放
+    fn awaiting_guest(&self) -> bool {
+        matches!(
+            self.opponent,
+            game::archive::Opponent::Human { guest_sid: None }
+        )
+    }
+
+    /// 对局玩家的 sid：开局一方，以及人人对局中已加入的另一方
+    fn player_sids(&self) -> Vec<String> {
+        let mut sids = vec![self.sid.clone()];
+        if let game::archive::Opponent::Human {
+            guest_sid: Some(guest),
+        } = &self.opponent
+        {
+            sids.push(guest.clone());
+        }
+        sids
+    }
+
+    /// 返回给某位玩家的对局概况（当前 sid 的对局列表、加入人人对局时使用）
+    fn view(&self, game_id: &str, color: Color) -> serde_json::Value {
+        let record = self.record(game_id);
+        let joined = match &self.opponent {
+            game::archive::Opponent::Engine => None,
+            game::archive::Opponent::Human { guest_sid } => Some(guest_sid.is_some()),
+        };
+        // 邀请令牌只给开局一方，且对方加入后不再返回
+        let invite_token = (color == self.human() && joined == Some(false))
+            .then(|| self.invite_token.clone())
+            .flatten();
+        serde_json::json!({
+            "gameId": game_id,
+            "opponent": if self.is_pvp() { "human" } else { "engine" },
+            "opponentJoined": joined,
+            "inviteToken": invite_token,
+            "profile": record.profile,
+            "boardSize": record.board_size,
+            "komi": record.komi,
+            "humanColor": color,
+            "handicapStones": record.handicap,
+            "moves": record.moves,
+            "toMove": color_name(self.board.to_move()),
+            "moveNumber": self.board.moves().len(),
+            "clock": self.clock_json(),
+            "end": end_json(self.end.as_ref()),
+        })
+    }
+
+    /// sid 在本局中执的颜色；不是对局玩家时为 None
+    fn seat_of(&self, sid: &str) -> Option<Color> {
+        if sid == self.sid {
+            return Some(self.human());
+        }
+        match &self.opponent {
+            game::archive::Opponent::Human {
+                guest_sid: Some(guest),
+            } if guest == sid => Some(self.human().opponent()),
+            _ => None,
+        }
+    }
+
+    /// 记录终局并停表；已结束的对局保留原结果
+    fn finish(&mut self, game_id: &str, end: game::GameEnd) {
+        if self.end.is_some() {
+            return;
+        }
+        tracing::info!(game_id, result = %end.result, reason = ?end.reason, "game finished");
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop(Instant::now());
+        }
+        self.emit(game::GameEvent::End { end: end.clone() });
+        self.end = Some(end);
+        self.persist(game_id);
+        let record = self.record(game_id);
+        let archive = self.archive.clone();
+        tokio::spawn(async move {
+            if let Err(err) = archive.save(&record).await {
+                tracing::error!(?err, game_id = %record.game_id, "failed to archive game");
+            }
+        });
+    }
+
+    /// 推送给该局的 WebSocket 连接；没有连接时直接丢弃
+    fn emit(&self, event: game::GameEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// 撤回着法后推送撤回后的盘面
+    fn emit_undo(&self) {
+        self.emit(game::GameEvent::Undo {
+            move_number: self.board.moves().len(),
+            black: vertex_strings(&self.board.stones(Color::Black)),
+            white: vertex_strings(&self.board.stones(Color::White)),
+        });
+    }
+
+    /// 导出 SGF 与归档用的完整记录
+    fn record(&self, game_id: &str) -> game::archive::GameRecord {
+        game::archive::GameRecord {
+            game_id: game_id.to_string(),
+            sid: self.sid.clone(),
+            opponent: self.opponent.clone(),
+            profile: self.profile.clone(),
+            level: self.level,
+            rules: self.rules.clone(),
+            board_size: self.board_size,
+            komi: self.komi,
+            human_color: self.human(),
+            handicap: vertex_strings(self.board.handicap()),
+            time_control: self.clock.as_ref().map(|c| c.control()),
+            moves: self
+                .board
+                .move_list()
+                .into_iter()
+                .map(|(color, vertex)| game::archive::RecordedMove {
+                    color,
+                    vertex: vertex.to_string(),
+                })
+                .collect(),
+            evals: self.evals.clone(),
+            end: self.end.clone(),
+            started_at: self.created_at,
+        }
+    }
+
+    /// 写入快照；落子、悔棋、终局等改变对局的操作之后调用
+    fn persist(&self, game_id: &str) {
+        self.snapshots.save(game::snapshot::GameSnapshot {
+            record: self.record(game_id),
+            undo_limit: self.undo_limit,
+            undos_used: self.undos_used,
+            invite_token: self.invite_token.clone(),
+            scoring_engine: self.scoring_engine,
+            clock: self.clock.as_ref().map(|c| c.snapshot(Instant::now())),
+        });
+    }
+
+    /// 从快照重建对局：按着法复盘权威棋盘，引擎留待首次使用时重建
+    fn resume(
+        snapshot: game::snapshot::GameSnapshot,
+        state: &AppState,
+        now: i64,
+    ) -> Result<Self, game::board::IllegalMove> {
+        let record = snapshot.record;
+        let mut board =
+            game::board::GameBoard::with_handicap(record.board_size, &record.handicap_vertices());
+        for (color, vertex) in record.move_list() {
+            board.play(color, vertex)?;
+        }
+        Ok(Self {
+            sid: record.sid,
+            created_at: record.started_at,
+            last_active_at: now, // 重启后重新计算闲置时间
+            engine: None,
+            profile: record.profile,
+            level: record.level,
+            rules: record.rules,
+            human_color: color_name(record.human_color).to_string(),
+            opponent: record.opponent,
+            invite_token: snapshot.invite_token,
+            scoring_engine: snapshot.scoring_engine,
+            board_size: record.board_size,
+            komi: record.komi,
+            board,
+            undo_limit: snapshot.undo_limit,
+            undos_used: snapshot.undos_used,
+            end: record.end,
+            clock: snapshot
+                .clock
+                .map(|c| game::clock::GameClock::restore(&c, Instant::now())),
+            evals: record.evals,
+            archive: state.archive.clone(),
+            snapshots: state.snapshots.clone(),
+            events: game::event_channel(),
+            turn: Arc::new(tokio::sync::Mutex::new(())),
+        })
+    }
+
+    /// 正在走的一方已超时则判负，返回是否因此结束
+    fn check_flag(&mut self, game_id: &str) -> bool {
+        let flagged = match (&self.end, &self.clock) {
+            (None, Some(clock)) if !self.awaiting_guest() => clock.flagged(Instant::now()),
+            _ => None,
+        };
+        if let Some(loser) = flagged {
+            self.finish(game_id, game::GameEnd::timeout(loser, now_unix()));
+        }
+        flagged.is_some()
+    }
+
+    /// 响应体中的 clock 字段：剩余用时以服务端为准
+    fn clock_json(&self) -> serde_json::Value {
+        self.clock
+            .as_ref()
+            .and_then(|c| serde_json::to_value(c.snapshot(Instant::now())).ok())
+            .unwrap_or(serde_json::Value::Null)
+    }
 }
 
 #[tokio::main]
@@ -185,6 +461,181 @@ async fn main() {
         .and_then(|s| s.parse().ok())
         .unwrap_or(30);
     let review_ttl_seconds = review_ttl_minutes * 60;
+    let pool_size: usize = std::env::var("ENGINE_POOL_SIZE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(8);
+    let pool_warm: usize = std::env::var("ENGINE_POOL_WARM")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1);
+    let pool_wait_seconds: u64 = std::env::var("ENGINE_POOL_WAIT_SECONDS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(30);
+    let env_seconds = |name: &str, default: Duration| {
+        std::env::var(name)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(default)
+    };
+    let defaults = engine::gtp::CommandTimeouts::default();
+    let gtp_timeouts = engine::gtp::CommandTimeouts {
+        default: env_seconds("GTP_TIMEOUT_SECONDS", defaults.default),
+        genmove: env_seconds("GTP_GENMOVE_TIMEOUT_SECONDS", defaults.genmove),
+        scoring: env_seconds("GTP_SCORE_TIMEOUT_SECONDS", defaults.scoring),
+        load: env_seconds("GTP_LOAD_TIMEOUT_SECONDS", defaults.load),
+        startup: env_seconds("GTP_STARTUP_TIMEOUT_SECONDS", defaults.startup),
+    };
+    let engine_pool = engine::pool::EnginePool::new(
+        pool_size,
+        Duration::from_secs(pool_wait_seconds),
+        gtp_timeouts,
+    );
+    // 难度档位：ENGINE_PROFILES_PATH 指定的 TOML/JSON 文件，未配置时用随程序发布的 engine_profiles.toml
+    let profiles = match std::env::var("ENGINE_PROFILES_PATH")
+        .ok()
+        .filter(|p| !p.is_empty())
+    {
+        Some(path) => match engine::profile::ProfileSet::load(Path::new(&path)) {
+            Ok(profiles) => profiles,
+            Err(err) => {
+                tracing::error!("failed to load engine profiles: {:#}", err);
+                std::process::exit(1);
+            }
+        },
+        None => engine::profile::ProfileSet::bundled(),
+    };
+    let profiles = Arc::new(profiles);
+
+    // 对局归档目录：GAME_ARCHIVE_DIR，默认 backend/data/games
+    let archive_dir = std::env::var("GAME_ARCHIVE_DIR")
+        .ok()
+        .filter(|p| !p.is_empty())
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("data")
+                .join("games")
+        });
+    let archive = match game::archive::GameArchive::open(&archive_dir) {
+        Ok(archive) => Arc::new(archive),
+        Err(err) => {
+            tracing::error!("failed to open game archive: {:#}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // 进行中对局的快照目录：GAME_SNAPSHOT_DIR，默认 backend/data/live
+    let snapshot_dir = std::env::var("GAME_SNAPSHOT_DIR")
+        .ok()
+        .filter(|p| !p.is_empty())
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("data")
+                .join("live")
+        });
+    let snapshots = match game::snapshot::SnapshotStore::open(&snapshot_dir) {
+        Ok(snapshots) => Arc::new(snapshots),
+        Err(err) => {
+            tracing::error!("failed to open game snapshots: {:#}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // 难度校准结果目录：CALIBRATION_DIR，默认 backend/data/calibration
+    let calibration_dir = std::env::var("CALIBRATION_DIR")
+        .ok()
+        .filter(|p| !p.is_empty())
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("data")
+                .join("calibration")
+        });
+    let calibration = match game::calibration::CalibrationStore::open(&calibration_dir) {
+        Ok(calibration) => Arc::new(calibration),
+        Err(err) => {
+            tracing::error!("failed to open calibration dir: {:#}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // sid Cookie 签名：SESSION_SECRET 为当前密钥，SESSION_SECRET_PREVIOUS（逗号分隔）为轮换前仍接受的旧密钥
+    let session_ttl_days: i64 = std::env::var("SESSION_TTL_DAYS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(30);
+    let cookie_secure = std::env::var("COOKIE_SECURE").is_ok_and(|v| v == "1" || v == "true");
+    let previous_secrets: Vec<Vec<u8>> = std::env::var("SESSION_SECRET_PREVIOUS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(|k| k.as_bytes().to_vec())
+        .collect();
+    let session_keys = match std::env::var("SESSION_SECRET")
+        .ok()
+        .filter(|k| !k.is_empty())
+    {
+        Some(secret) => {
+            if secret.len() < 32 {
+                tracing::warn!("SESSION_SECRET is shorter than 32 bytes");
+            }
+            session::SessionKeys::new(
+                secret.into_bytes(),
+                previous_secrets,
+                session_ttl_days * 24 * 3600,
+                cookie_secure,
+            )
+        }
+        // 未配置时沿用 SESSION_KEY_FILE（默认 backend/data/session.key）中生成的密钥
+        None => {
+            let key_file = std::env::var("SESSION_KEY_FILE")
+                .ok()
+                .filter(|p| !p.is_empty())
+                .map(std::path::PathBuf::from)
+                .unwrap_or_else(|| {
+                    Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("data")
+                        .join("session.key")
+                });
+            match session::SessionKeys::load_or_generate(
+                &key_file,
+                previous_secrets,
+                session_ttl_days * 24 * 3600,
+                cookie_secure,
+            ) {
+                Ok(keys) => keys,
+                Err(err) => {
+                    tracing::error!(
+                        ?err,
+                        "SESSION_SECRET not set and session key file unusable, using a random key: \
+                         sid cookies and restored games will not survive a restart"
+                    );
+                    session::SessionKeys::random(session_ttl_days * 24 * 3600, cookie_secure)
+                }
+            }
+        }
+    };
+    let session_keys = Arc::new(session_keys);
+
+    // 后台预热默认难度的引擎，避免首局等待模型加载
+    let engine_backend = if let Some(spec) = katago_spec(profiles.default_profile(), "chinese", &[])
+    {
+        let pool = engine_pool.clone();
+        tokio::spawn(async move {
+            if let Err(err) = pool.warm(&spec, pool_warm).await {
+                tracing::warn!(?err, "failed to warm engine pool");
+            }
+        });
+        EngineBackend::KataGo
+    } else {
+        tracing::warn!("ENGINE_PATH/MODEL_PATH/GTP_CONFIG_PATH not set, using built-in engine");
+        EngineBackend::Native
+    };
 
     let state = Arc::new(AppState {
         concurrency_limit_per_sid,
@@ -195,7 +646,19 @@ async fn main() {
         review_ttl_seconds,
         server_start_at: time::OffsetDateTime::now_utc().unix_timestamp(),
         sid_locks: Arc::new(dashmap::DashMap::new()),
+        engine_pool,
+        analysis_engine: Arc::new(tokio::sync::Mutex::new(None)),
+        engine_restarts: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+        admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+        session_keys,
+        engine_backend,
+        profiles,
+        archive,
+        snapshots,
+        exhibitions: Arc::new(dashmap::DashMap::new()),
+        calibration,
     });
+    restore_games(&state);
     let state_for_cleaner = state.clone();
 
     // CORS（若通过同源静态托管，几乎不会命中，但保留更安全）
@@ -204,16 +667,7 @@ async fn main() {
         .allow_methods([Method::GET, Method::POST])
         .allow_headers(Any);
 
-    let api = Router::new()
-        .route("/api/game/new", post(game_new))
-        .route("/api/game/play", post(game_play))
-        .route("/api/game/heartbeat", post(game_heartbeat))
-        .route("/api/game/close", post(game_close))
-        .route("/api/game/score_detail", post(game_score_detail))
-        .route("/api/game/hint", post(game_hint))
-        .route("/api/review/import", post(review_import))
-        .route("/api/review/analyze", post(review_analyze))
-        .route("/api/exercise/save", post(exercise_save));
+    let api = api_router();
 
     let static_dir = project_root.join("frontend/public");
 
@@ -233,6 +687,18 @@ async fn main() {
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
 
+    // 棋钟巡检：无人落子时也能按时判负
+    let clock_state = state.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            for mut gs in clock_state.game_store.iter_mut() {
+                let game_id = gs.key().clone();
+                gs.check_flag(&game_id);
+            }
+        }
+    });
+
     // 过期清理后台任务
     let cleaner_state = state_for_cleaner;
     tokio::spawn(async move {
@@ -243,20 +709,16 @@ async fn main() {
             cleaner_state.game_store.retain(|game_id, gs| {
                 let expired = now - gs.last_active_at > cleaner_state.game_ttl_seconds;
                 if expired {
-                    // 过期对局：尝试优雅退出其引擎
-                    if let Some(engine) = gs.engine.as_ref() {
-                        let e = engine.clone();
-                        // 在后台异步退出，避免阻塞 retain 闭包
-                        tokio::spawn(async move {
-                            let _ = e.quit().await;
-                        });
+                    // 过期对局：移除后其引擎租约随之归还进程池；人人对局双方的列表都要清理
+                    for sid in gs.player_sids() {
+                        affected_sids.push((sid, game_id.clone()));
                     }
-                    affected_sids.push((gs.sid.clone(), game_id.clone()));
                 }
                 !expired
             });
-            // 从 session_store 移除已过期的 gameId
+            // 从 session_store 移除已过期的 gameId，并删除其快照
             for (sid, gid) in affected_sids {
+                cleaner_state.snapshots.remove(&gid);
                 if let Some(mut v) = cleaner_state.session_store.get_mut(&sid) {
                     v.retain(|g| g != &gid);
                 }
@@ -266,6 +728,11 @@ async fn main() {
             cleaner_state.review_store.retain(|_, review| {
                 now - review.last_active_at <= cleaner_state.review_ttl_seconds
             });
+            // 已结束的表演赛保留一个对局 TTL，供观众下载棋谱
+            cleaner_state.exhibitions.retain(|_, ex| {
+                ex.finished_at()
+                    .is_none_or(|t| now - t <= cleaner_state.game_ttl_seconds)
+            });
         }
     });
 
@@ -274,18 +741,80 @@ async fn main() {
         .await
         .unwrap();
 
-    // 服务退出后，清理剩余引擎子进程（尽力而为）
-    let mut engines: Vec<std::sync::Arc<engine::gtp::GtpEngine>> = Vec::new();
-    for entry in state.game_store.iter() {
-        if let Some(e) = entry.value().engine.clone() {
-            engines.push(e);
+    // 服务退出后，归还所有租约并退出池内引擎子进程（尽力而为）；快照保留，下次启动时恢复
+    state.snapshots.flush().await;
+    state.game_store.clear();
+    state.review_store.clear();
+    state.exhibitions.clear();
+    state.engine_pool.shutdown().await;
+    if let Some(engine) = state.analysis_engine.lock().await.take() {
+        let _ = engine.quit().await;
+    }
+}
+
+/// 启动时按快照恢复进行中的对局及各 sid 的对局列表；引擎延后到首次使用时重建
+fn restore_games(state: &AppState) {
+    let now = now_unix();
+    let mut restored = 0;
+    for snapshot in state.snapshots.load_all() {
+        let game_id = snapshot.record.game_id.clone();
+        match GameState::resume(snapshot, state, now) {
+            Ok(gs) => {
+                for sid in gs.player_sids() {
+                    state
+                        .session_store
+                        .entry(sid)
+                        .or_default()
+                        .push(game_id.clone());
+                }
+                state.game_store.insert(game_id, gs);
+                restored += 1;
+            }
+            Err(err) => {
+                tracing::warn!(?err, game_id, "dropping game snapshot that does not replay");
+                state.snapshots.remove(&game_id);
+            }
         }
     }
-    for e in engines {
-        let _ = e.quit().await;
+    if restored > 0 {
+        tracing::info!(restored, "resumed games from snapshots");
     }
 }
 
+fn api_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/game/new", post(game_new))
+        .route("/api/game/play", post(game_play))
+        .route("/api/game/heartbeat", post(game_heartbeat))
+        .route("/api/game/ws", get(game_ws))
+        .route("/api/game/close", post(game_close))
+        .route("/api/game/join", post(game_join))
+        .route("/api/game/score_detail", post(game_score_detail))
+        .route("/api/game/hint", post(game_hint))
+        .route("/api/game/undo", post(game_undo))
+        .route("/api/game/resign", post(game_resign))
+        .route("/api/game/sgf", get(game_sgf))
+        .route("/api/game/active", get(game_active))
+        .route("/api/game/to_review", post(game_to_review))
+        .route("/api/archive/games", get(archive_list))
+        .route("/api/archive/game", get(archive_get))
+        .route("/api/exhibition/new", post(exhibition_new))
+        .route("/api/exhibition/list", get(exhibition_list))
+        .route("/api/exhibition/stream", get(exhibition_stream))
+        .route("/api/exhibition/sgf", get(exhibition_sgf))
+        .route("/api/engine/pool", get(engine_pool_stats))
+        .route("/api/engine/profiles", get(engine_profiles))
+        .route("/api/engine/stderr", get(engine_stderr))
+        .route(
+            "/api/admin/calibration",
+            get(calibration_status).post(calibration_start),
+        )
+        .route("/api/review/import", post(review_import))
+        .route("/api/review/analyze", post(review_analyze))
+        .route("/api/review/analyze/stream", get(review_analyze_stream))
+        .route("/api/exercise/save", post(exercise_save))
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         signal::ctrl_c()
@@ -312,22 +841,35 @@ async fn shutdown_signal() {
 
 // --- Game routes (stubs) ---
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 struct NewGameResponse {
-    gameId: String,
-    expiresAt: i64,
-    activeGames: u32,
+    game_id: String,
+    expires_at: i64,
+    active_games: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
-    engineMove: Option<String>, // 人类执白时，AI 的首手
+    engine_move: Option<String>, // AI 先行时（人类执白，或让子局 AI 执白）的首手
+    komi: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    handicap_stones: Vec<String>, // 让子位置（黑子），供前端绘制
+    clock: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    invite_token: Option<String>, // 人人对局：交给对方用于加入
 }
 
 #[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct NewGameRequest {
-    boardSize: Option<u32>,
+    board_size: Option<u32>,
     rules: Option<String>,
     komi: Option<f32>,
     handicap: Option<u32>,
-    engineLevel: Option<u8>,
-    playerColor: Option<String>,
+    handicap_placement: Option<String>, // "fixed"（默认）或 "free"（由引擎自选位置）
+    engine_level: Option<u8>,           // 旧接口：第 N 个档位
+    engine_profile: Option<String>,     // 档位名，优先于 engineLevel
+    player_color: Option<String>,
+    time_control: Option<TimeControl>, // 不传则不计时
+    opponent: Option<String>,          // "engine"（默认）或 "human"（凭邀请令牌加入的另一位玩家）
+    scoring_engine: Option<bool>,      // 人人对局终局时是否借用引擎数子
 }
 
 async fn game_new(
@@ -335,7 +877,7 @@ async fn game_new(
     headers: HeaderMap,
     maybe_body: Option<Json<NewGameRequest>>,
 ) -> impl IntoResponse {
-    let (sid, set_cookie) = get_or_create_sid(headers);
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
 
     // per-sid 互斥，防止同时点多次“新开对局”导致重复启动引擎
     let lock = state
@@ -369,61 +911,165 @@ async fn game_new(
     let game_id = format!("g-{}", uuid::Uuid::new_v4());
     let now = time::OffsetDateTime::now_utc().unix_timestamp();
 
-    // 若环境配置齐全则尝试启动引擎，否则置为 None（占位）
-    let engine = match (
-        std::env::var("ENGINE_PATH"),
-        std::env::var("MODEL_PATH"),
-        std::env::var("GTP_CONFIG_PATH"),
+    let req = maybe_body.as_ref().map(|j| &j.0);
+    let profile = match (
+        req.and_then(|r| r.engine_profile.as_deref()),
+        req.and_then(|r| r.engine_level),
     ) {
-        (Ok(engine_path), Ok(model_path), Ok(config_path)) => {
-            let mut args = vec![
-                "gtp".to_string(),
-                "-model".to_string(),
-                model_path,
-                "-config".to_string(),
-                config_path,
-            ];
-            // 难度 → 覆盖配置
-            let req = maybe_body.as_ref().map(|j| &j.0);
-            let level = req.and_then(|r| r.engineLevel).unwrap_or(3);
-            for (k, v) in overrides_for_level(level) {
-                args.push("-override-config".to_string());
-                args.push(format!("{}={}", k, v));
-            }
-            // 规则 → 覆盖配置（默认 chinese）
-            let rule_name = req
-                .and_then(|r| r.rules.clone())
-                .unwrap_or_else(|| "chinese".to_string());
-            args.push("-override-config".to_string());
-            args.push(format!("rules={}", rule_name));
-            match engine::gtp::GtpEngine::start(&engine_path, &args).await {
-                Ok(e) => {
-                    // 初始化棋盘参数
-                    let board_size = req.and_then(|r| r.boardSize).unwrap_or(19);
-                    let komi_req = req.and_then(|r| r.komi).unwrap_or(6.5);
-                    let rule_name = req
-                        .and_then(|r| r.rules.clone())
-                        .unwrap_or_else(|| "chinese".to_string());
-                    // 规则化 komi：Chinese 默认 7.5；其他沿用传入/默认值
-                    let effective_komi: f32 = if rule_name.eq_ignore_ascii_case("chinese") {
-                        7.5
-                    } else {
-                        komi_req
-                    };
-                    // 顺序调整：先清盘，再设棋盘大小，最后设贴目，避免 clear_board 重置贴目导致异常（如出现 W+0.5）
-                    let _ = e.send_command("clear_board").await;
-                    let _ = e.send_command(&format!("boardsize {}", board_size)).await;
-                    let _ = e.send_command(&format!("komi {}", effective_komi)).await;
-                    Some(e)
-                }
+        (Some(name), _) => state.profiles.get(name),
+        (None, Some(level)) => state.profiles.by_level(level),
+        (None, None) => Some(state.profiles.default_profile()),
+    };
+    let Some((profile, undo_limit)) = profile.map(|p| (p.name.clone(), p.undos)) else {
+        return with_cookie(
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error":"UNKNOWN_PROFILE"})),
+            )
+                .into_response(),
+            set_cookie,
+        );
+    };
+    // 规则 → 覆盖配置（默认 chinese）
+    let rule_name = req
+        .and_then(|r| r.rules.clone())
+        .unwrap_or_else(|| "chinese".to_string());
+    let board_size = req.and_then(|r| r.board_size).unwrap_or(19);
+    if !game::board::BOARD_SIZES.contains(&board_size) {
+        return with_cookie(
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error":"INVALID_BOARD_SIZE"})),
+            )
+                .into_response(),
+            set_cookie,
+        );
+    }
+    // 让子：2 子起摆放让子，执白先行；1 子为不贴目的分先
+    let handicap = req.and_then(|r| r.handicap).unwrap_or(0);
+    let free_handicap = req
+        .and_then(|r| r.handicap_placement.as_deref())
+        .is_some_and(|p| p.eq_ignore_ascii_case("free"));
+    if handicap >= 2 && engine::protocol::fixed_handicap_vertices(board_size, handicap).is_none() {
+        return with_cookie(
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error":"INVALID_HANDICAP"})),
+            )
+                .into_response(),
+            set_cookie,
+        );
+    }
+    // 规则化 komi：让子局 0.5（让子补偿由规则在数子时计入）；Chinese 默认 7.5；其他沿用传入/默认值
+    let effective_komi: f32 = if handicap >= 1 {
+        0.5
+    } else if rule_name.eq_ignore_ascii_case("chinese") {
+        7.5
+    } else {
+        req.and_then(|r| r.komi).unwrap_or(6.5)
+    };
+    let player_color = maybe_body
+        .as_ref()
+        .and_then(|j| j.player_color.clone())
+        .unwrap_or_else(|| "black".to_string());
+    let human = if player_color == "white" {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let time_control = req.and_then(|r| r.time_control);
+    if time_control.is_some_and(|tc| !tc.is_valid()) {
+        return with_cookie(
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error":"INVALID_TIME_CONTROL"})),
+            )
+                .into_response(),
+            set_cookie,
+        );
+    }
+    let pvp = req
+        .and_then(|r| r.opponent.as_deref())
+        .is_some_and(|o| o.eq_ignore_ascii_case("human"));
+
+    // 人人对局不占用引擎：让子固定摆在星位，终局数子时才按需借用引擎
+    let (engine, board) = if pvp {
+        let stones = if handicap >= 2 {
+            engine::protocol::fixed_handicap_vertices(board_size, handicap).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        (
+            None,
+            game::board::GameBoard::with_handicap(board_size, &stones),
+        )
+    } else {
+        let handicap_stones_expected = if handicap >= 2 { handicap as usize } else { 0 };
+        let extra_overrides = handicap_overrides(handicap_stones_expected, human);
+
+        // 配置了 KataGo 时从进程池租借（租借时已清盘并设置棋盘/贴目），否则使用进程内轻量引擎
+        let setup = engine::pool::BoardSetup {
+            board_size,
+            komi: effective_komi,
+        };
+        let owner = format!("game:{}", game_id);
+        let engine = match acquire_engine(
+            &state,
+            &profile,
+            &rule_name,
+            &extra_overrides,
+            setup,
+            owner,
+        )
+        .await
+        {
+            Ok(engine) => engine,
+            Err(engine::pool::PoolError::Busy(waited)) => {
+                tracing::warn!(?waited, "engine pool exhausted");
+                let body = serde_json::json!({
+                    "error": "ENGINE_BUSY",
+                    "retryAfterSeconds": 10,
+                });
+                return with_cookie(
+                    (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response(),
+                    set_cookie,
+                );
+            }
+            Err(err) => {
+                tracing::warn!(?err, "failed to start katago, fallback to built-in engine");
+                Arc::new(NativeEngine::new(setup))
+            }
+        };
+
+        let mut board = game::board::GameBoard::new(board_size);
+        if handicap >= 2 {
+            match engine.place_handicap(handicap, free_handicap).await {
+                Ok(stones) => board = game::board::GameBoard::with_handicap(board_size, &stones),
                 Err(err) => {
-                    tracing::warn!(?err, "failed to start katago, fallback to stub engine");
-                    None
+                    tracing::error!(?err, handicap, "failed to place handicap stones");
+                    return with_cookie(engine_error_response(&err).into_response(), set_cookie);
                 }
             }
         }
-        _ => None,
+        if let Some(tc) = &time_control
+            && let Err(err) = engine.time_settings(tc).await
+        {
+            tracing::error!(?err, "failed to send time settings");
+            return with_cookie(engine_error_response(&err).into_response(), set_cookie);
+        }
+        (Some(engine), board)
     };
+    let invite_token = pvp.then(|| uuid::Uuid::new_v4().simple().to_string());
+    let handicap_stones = vertex_strings(board.handicap());
+    let first_to_move = board.to_move();
+    // 人人对局的棋钟等对方加入后才开始
+    let clock = time_control.map(|tc| {
+        let mut clock = game::clock::GameClock::new(tc);
+        if !pvp {
+            clock.start(first_to_move, Instant::now());
+        }
+        clock
+    });
 
     state
         .session_store
@@ -431,49 +1077,87 @@ async fn game_new(
         .and_modify(|v| v.push(game_id.clone()))
         .or_insert_with(|| vec![game_id.clone()]);
 
-    let player_color = maybe_body
-        .as_ref()
-        .and_then(|j| j.playerColor.clone())
-        .unwrap_or_else(|| "black".to_string());
     state.game_store.insert(
         game_id.clone(),
         GameState {
             sid: sid.clone(),
+            created_at: now,
             last_active_at: now,
             engine: engine.clone(),
+            profile: profile.clone(),
+            level: (!pvp).then(|| state.profiles.level_of(&profile)).flatten(),
+            rules: rule_name.clone(),
             human_color: player_color.clone(),
-            board_size: maybe_body.as_ref().and_then(|j| j.boardSize).unwrap_or(19),
-            komi: if maybe_body
-                .as_ref()
-                .and_then(|j| j.rules.clone())
-                .unwrap_or_else(|| "chinese".to_string())
-                .eq_ignore_ascii_case("chinese")
-            {
-                7.5
+            opponent: if pvp {
+                game::archive::Opponent::Human { guest_sid: None }
             } else {
-                maybe_body.as_ref().and_then(|j| j.komi).unwrap_or(6.5)
+                game::archive::Opponent::Engine
             },
+            invite_token: invite_token.clone(),
+            scoring_engine: req.and_then(|r| r.scoring_engine).unwrap_or(false),
+            board_size,
+            komi: effective_komi,
+            board,
+            undo_limit,
+            undos_used: 0,
+            end: None,
+            clock,
+            evals: BTreeMap::new(),
+            archive: state.archive.clone(),
+            snapshots: state.snapshots.clone(),
+            events: game::event_channel(),
+            turn: Arc::new(tokio::sync::Mutex::new(())),
         },
     );
+    if let Some(gs) = state.game_store.get(&game_id) {
+        gs.persist(&game_id);
+    }
 
-    // 若人类执白，AI 需先手（B）
+    // AI 先行：人类执白的分先局，或人类执黑的让子局（白先）
     let mut first_move: Option<String> = None;
-    if player_color == "white" {
-        if let Some(ref e) = engine {
-            if let Ok(resp) = e.send_command("genmove B").await {
-                first_move = Some(parse_gtp_move(&resp));
+    if first_to_move != human && engine.is_some() {
+        let mut e = engine;
+        let time_left = engine_time_left(&state, &game_id, first_to_move);
+        match game_call(&state, &game_id, &mut e, |e| async move {
+            if let Some((seconds, stones)) = time_left {
+                e.time_left(first_to_move, seconds, stones).await?;
             }
-        } else {
-            first_move = Some("Q16".to_string()); // 占位
+            e.genmove(first_to_move).await
+        })
+        .await
+        {
+            Ok(mv) => {
+                if let Err(err) = record_game_move(&state, &game_id, first_to_move, mv) {
+                    tracing::error!(?err, %mv, "engine opening move rejected by board");
+                }
+                first_move = Some(mv.to_string());
+            }
+            Err(err @ engine::gtp::EngineError::Timeout { .. }) => {
+                tracing::warn!(?err, "first genmove timed out");
+                drop(e);
+                if let Ok(turn) = lock_game(&state, &game_id).await {
+                    rebuild_in_background(&state, &game_id, turn);
+                }
+            }
+            Err(err) => tracing::warn!(?err, "first genmove failed"),
         }
     }
 
     let expires = now + state.game_ttl_seconds;
+    let clock = state
+        .game_store
+        .get(&game_id)
+        .map(|gs| gs.clock_json())
+        .unwrap_or_default();
     let res = NewGameResponse {
-        gameId: game_id,
-        expiresAt: expires,
-        activeGames: active + 1,
-        engineMove: first_move,
+        game_id,
+        expires_at: expires,
+        active_games: active + 1,
+        engine_move: first_move,
+        komi: effective_komi,
+        handicap_stones,
+        clock,
+        invite_token,
     };
     let mut resp = (StatusCode::CREATED, Json(res)).into_response();
     if let Some(sc) = set_cookie {
@@ -483,396 +1167,1802 @@ async fn game_new(
 }
 
 #[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GameIdPayload {
-    gameId: String,
+    game_id: String,
 }
 
 #[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct PlayPayload {
-    gameId: String,
-    playerMove: String,
+    game_id: String,
+    player_move: String,
 }
 
 async fn game_heartbeat(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(payload): Json<GameIdPayload>,
-) -> impl IntoResponse {
-    if let Some(mut gs) = state.game_store.get_mut(&payload.gameId) {
-        gs.last_active_at = time::OffsetDateTime::now_utc().unix_timestamp();
-        drop(gs);
-        return StatusCode::NO_CONTENT;
-    }
-    StatusCode::GONE
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    with_cookie(
+        heartbeat(&state, &sid, payload).await.into_response(),
+        set_cookie,
+    )
 }
 
-async fn game_close(
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<GameIdPayload>,
-) -> impl IntoResponse {
-    // 从 game_store 移除
-    if let Some(gs) = state.game_store.remove(&payload.gameId) {
-        if let Some(engine) = gs.1.engine.as_ref() {
-            let _ = engine.quit().await;
-        }
-        let sid = gs.1.sid;
-        if let Some(mut entry) = state.session_store.get_mut(&sid) {
-            entry.retain(|g| g != &payload.gameId);
-        }
-        return StatusCode::NO_CONTENT;
-    }
-    StatusCode::NO_CONTENT
-}
-
-fn get_or_create_sid(headers: HeaderMap) -> (String, Option<HeaderValue>) {
-    // 读取 cookie
-    let mut sid: Option<String> = None;
-    if let Some(cookie_hdr) = headers.get("cookie") {
-        if let Ok(s) = cookie_hdr.to_str() {
-            for part in s.split(';') {
-                let kv = part.trim();
-                if let Some(rest) = kv.strip_prefix("sid=") {
-                    sid = Some(rest.to_string());
-                    break;
-                }
-            }
-        }
-    }
-    if let Some(sid) = sid {
-        return (sid, None);
+async fn heartbeat(
+    state: &AppState,
+    sid: &str,
+    payload: GameIdPayload,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let (mut gs, _) = match owned_game(state, &payload.game_id, sid) {
+        Ok(game) => game,
+        Err(resp) => return resp,
+    };
+    gs.last_active_at = time::OffsetDateTime::now_utc().unix_timestamp();
+    gs.check_flag(&payload.game_id);
+    let mut body = serde_json::json!({
+        "clock": gs.clock_json(),
+        "end": end_json(gs.end.as_ref()),
+        "toMove": color_name(gs.board.to_move()),
+        "moveNumber": gs.board.moves().len(),
+    });
+    // 人人对局靠心跳得知对方的着法与是否已加入
+    if let game::archive::Opponent::Human { guest_sid } = &gs.opponent {
+        body["opponentJoined"] = guest_sid.is_some().into();
+        body["moves"] = serde_json::to_value(gs.record(&payload.game_id).moves).unwrap_or_default();
     }
+    (StatusCode::OK, Json(body))
+}
 
-    // 生成并返回 Set-Cookie
-    let new_sid = uuid::Uuid::new_v4().to_string();
-    let cookie = format!("sid={}; Path=/; HttpOnly; SameSite=Lax", new_sid);
-    let val = HeaderValue::from_str(&cookie).ok();
-    (new_sid, val)
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GameSocketQuery {
+    game_id: String,
 }
 
-async fn game_play(
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<PlayPayload>,
-) -> impl IntoResponse {
-    let now = time::OffsetDateTime::now_utc().unix_timestamp();
-    // 读取必要信息后释放 guard，避免跨 await 持有 DashMap 锁
-    let (engine_opt, human_is_black) =
-        if let Some(mut gs) = state.game_store.get_mut(&payload.gameId) {
-            gs.last_active_at = now;
-            (gs.engine.clone(), gs.human_color == "black")
-        } else {
-            return (
-                StatusCode::GONE,
-                Json(serde_json::json!({"error":"GAME_EXPIRED"})),
-            );
+/// WebSocket 上客户端发来的消息
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum GameSocketRequest {
+    #[serde(rename_all = "camelCase")]
+    Play {
+        player_move: String,
+    },
+    Ping,
+}
+
+// 对局 WebSocket：连接期间即视为活跃（取代心跳），推送着法、引擎应手、棋钟、形势判断与终局；
+// 落子可经此提交，引擎思考期间连接照常收发
+async fn game_ws(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<GameSocketQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    let (view, events) = {
+        let Some(gs) = state.game_store.get(&query.game_id) else {
+            return error_response(StatusCode::GONE, "GAME_EXPIRED", None, set_cookie);
+        };
+        let Some(color) = gs.seat_of(&sid) else {
+            return error_response(StatusCode::FORBIDDEN, "GAME_NOT_OWNED", None, set_cookie);
         };
+        (gs.view(&query.game_id, color), gs.events.subscribe())
+    };
+    let socket = GameSocket {
+        state,
+        game_id: query.game_id,
+        sid,
+    };
+    with_cookie(
+        ws.on_upgrade(move |ws| socket.run(ws, view, events)),
+        set_cookie,
+    )
+}
 
-    if let Some(engine) = engine_opt {
-        let human_color = if human_is_black { 'B' } else { 'W' };
-        let ai_color = if human_is_black { 'W' } else { 'B' };
-        let _ = engine
-            .send_command(&format!("play {} {}", human_color, payload.playerMove))
-            .await;
-        match engine.send_command(&format!("genmove {}", ai_color)).await {
-            Ok(resp) => {
-                let mv = parse_gtp_move(&resp);
-                let body = serde_json::json!({
-                    "engineMove": mv,
-                    "captures": [],
-                    "end": {"finished": false}
-                });
-                return (StatusCode::OK, Json(body));
+struct GameSocket {
+    state: Arc<AppState>,
+    game_id: String,
+    sid: String,
+}
+
+impl GameSocket {
+    async fn run(
+        self,
+        mut ws: WebSocket,
+        view: serde_json::Value,
+        mut events: tokio::sync::broadcast::Receiver<game::GameEvent>,
+    ) {
+        let (replies_tx, mut replies) = tokio::sync::mpsc::channel::<serde_json::Value>(8);
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut outgoing = Some(tagged("state", view));
+        let mut playing = false; // 一次只处理一手：回执前再来的落子回 BUSY
+        loop {
+            if let Some(message) = outgoing.take()
+                && ws.send(Message::Text(message.to_string())).await.is_err()
+            {
+                return;
+            }
+            let expired = tokio::select! {
+                incoming = ws.recv() => match incoming {
+                    Some(Ok(Message::Text(text))) => {
+                        outgoing = self.handle(&text, &replies_tx, &mut playing);
+                        false
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => false,
+                },
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        outgoing = serde_json::to_value(event).ok();
+                        false
+                    }
+                    // 跟不上推送时改发一次整盘状态
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                        outgoing = self.state_message();
+                        outgoing.is_none()
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => true,
+                },
+                Some(reply) = replies.recv() => {
+                    playing = false;
+                    outgoing = Some(reply);
+                    false
+                }
+                _ = tick.tick() => match self.tick() {
+                    Some(clock) => {
+                        outgoing = clock;
+                        false
+                    }
+                    None => true,
+                },
+            };
+            // 对局已释放（关闭或过期）：告知客户端后断开
+            if expired {
+                let message = serde_json::json!({"type": "expired"});
+                let _ = ws.send(Message::Text(message.to_string())).await;
+                let _ = ws.close().await;
+                return;
             }
+        }
+    }
+
+    fn handle(
+        &self,
+        text: &str,
+        replies: &tokio::sync::mpsc::Sender<serde_json::Value>,
+        playing: &mut bool,
+    ) -> Option<serde_json::Value> {
+        let request = match serde_json::from_str::<GameSocketRequest>(text) {
+            Ok(request) => request,
             Err(err) => {
-                tracing::error!(?err, "genmove failed");
+                return Some(serde_json::json!({
+                    "type": "error",
+                    "error": "BAD_MESSAGE",
+                    "detail": err.to_string(),
+                }));
+            }
+        };
+        match request {
+            GameSocketRequest::Ping => Some(serde_json::json!({"type": "pong"})),
+            // 着法与引擎应手经事件推送；此处只在整手处理完后回执，期间连接照常收发
+            GameSocketRequest::Play { .. } if *playing => Some(serde_json::json!({
+                "type": "error",
+                "error": "BUSY",
+                "status": StatusCode::CONFLICT.as_u16(),
+            })),
+            GameSocketRequest::Play { player_move } => {
+                *playing = true;
+                let state = self.state.clone();
+                let sid = self.sid.clone();
+                let payload = PlayPayload {
+                    game_id: self.game_id.clone(),
+                    player_move,
+                };
+                let replies = replies.clone();
+                tokio::spawn(async move {
+                    let (status, Json(body)) = play_move(&state, &sid, payload).await;
+                    let reply = if status.is_success() {
+                        tagged("played", body)
+                    } else {
+                        let mut body = tagged("error", body);
+                        body["status"] = status.as_u16().into();
+                        body
+                    };
+                    let _ = replies.send(reply).await;
+                });
+                None
             }
         }
     }
-    // 占位：无引擎时固定应手
-    let body = serde_json::json!({
-        "engineMove": "Q16",
-        "captures": [],
-        "end": {"finished": false}
-    });
-    (StatusCode::OK, Json(body))
+
+    // 每秒一次：保持对局活跃、巡检超时，并推送棋钟（不计时或已结束时不推送）；对局已释放时为 None
+    fn tick(&self) -> Option<Option<serde_json::Value>> {
+        let mut gs = self.state.game_store.get_mut(&self.game_id)?;
+        gs.last_active_at = now_unix();
+        gs.check_flag(&self.game_id);
+        Some((gs.end.is_none() && gs.clock.is_some()).then(|| {
+            serde_json::json!({
+                "type": "clock",
+                "clock": gs.clock_json(),
+                "toMove": color_name(gs.board.to_move()),
+            })
+        }))
+    }
+
+    fn state_message(&self) -> Option<serde_json::Value> {
+        let gs = self.state.game_store.get(&self.game_id)?;
+        let color = gs.seat_of(&self.sid)?;
+        Some(tagged("state", gs.view(&self.game_id, color)))
+    }
 }
 
-#[derive(serde::Serialize)]
-struct HintResponse {
-    suggestion: String,
+// 给 JSON 对象加上 type 字段
+fn tagged(kind: &str, mut body: serde_json::Value) -> serde_json::Value {
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("type".to_string(), kind.into());
+    }
+    body
 }
 
-// 为当前人类一方给出建议一手（不改变引擎棋局状态），仅返回坐标
-async fn game_hint(
+async fn game_close(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(payload): Json<GameIdPayload>,
-) -> impl IntoResponse {
-    // 读取必要信息
-    let (engine_opt, human_is_black) = if let Some(gs) = state.game_store.get(&payload.gameId) {
-        (gs.engine.clone(), gs.human_color == "black")
-    } else {
-        return (
-            StatusCode::GONE,
-            Json(serde_json::json!({"error":"GAME_EXPIRED"})),
-        );
-    };
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    with_cookie(close_game(&state, &sid, payload).await, set_cookie)
+}
 
-    if let Some(engine) = engine_opt {
-        let human_color = if human_is_black { 'B' } else { 'W' };
-        // 使用 genmove + undo，仅提供坐标
-        match engine
-            .send_command(&format!("genmove {}", human_color))
-            .await
-        {
-            Ok(resp) => {
-                let mv = parse_gtp_move(&resp);
-                let mv_lc = mv.to_ascii_lowercase();
-                if mv_lc != "pass" && mv_lc != "resign" {
-                    let _ = engine.send_command("undo").await;
-                }
-                let body = HintResponse { suggestion: mv };
-                let val = serde_json::to_value(body)
-                    .unwrap_or_else(|_| serde_json::json!({"suggestion":""}));
-                return (StatusCode::OK, Json(val));
-            }
-            Err(err) => {
-                tracing::error!(?err, "genmove for hint failed");
-            }
+async fn close_game(state: &AppState, sid: &str, payload: GameIdPayload) -> Response {
+    let (mut gs, color) = match owned_game(state, &payload.game_id, sid) {
+        Ok(game) => game,
+        // 已释放的对局视为关闭成功
+        Err((StatusCode::GONE, _)) => return StatusCode::NO_CONTENT.into_response(),
+        Err(resp) => return resp.into_response(),
+    };
+    // 人人对局：中途离开即认输；双方都离开后才释放对局。无人加入的邀请直接释放，不记认输
+    let pvp_leaver = (gs.is_pvp() && !gs.awaiting_guest()).then(|| {
+        gs.finish(
+            &payload.game_id,
+            game::GameEnd::resignation(color, now_unix()),
+        );
+        (sid, gs.player_sids())
+    });
+    drop(gs);
+    if let Some((sid, players)) = pvp_leaver {
+        if let Some(mut entry) = state.session_store.get_mut(sid) {
+            entry.retain(|g| g != &payload.game_id);
+        }
+        let still_open = players.iter().any(|p| {
+            state
+                .session_store
+                .get(p)
+                .is_some_and(|games| games.contains(&payload.game_id))
+        });
+        if still_open {
+            return StatusCode::NO_CONTENT.into_response();
         }
     }
-    // 无引擎/失败占位
-    {
-        let body = HintResponse {
-            suggestion: "Q16".to_string(),
-        };
-        let val =
-            serde_json::to_value(body).unwrap_or_else(|_| serde_json::json!({"suggestion":"Q16"}));
-        (StatusCode::OK, Json(val))
+    // 从 game_store 移除；引擎租约随状态释放归还进程池（租约的 quit 为空操作）
+    if let Some((_, gs)) = state.game_store.remove(&payload.game_id) {
+        state.snapshots.remove(&payload.game_id);
+        if let Some(engine) = gs.engine.clone() {
+            tokio::spawn(async move {
+                let _ = engine.quit().await;
+            });
+        }
+        for sid in gs.player_sids() {
+            if let Some(mut entry) = state.session_store.get_mut(&sid) {
+                entry.retain(|g| g != &payload.game_id);
+            }
+        }
+        return StatusCode::NO_CONTENT.into_response();
     }
-}
-
-fn parse_gtp_move(resp: &str) -> String {
-    // 取第一行，去掉开头的"="与空格
-    let line = resp.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
-    let s = line.trim_start_matches('=').trim();
-    s.to_string()
-}
-
-#[derive(serde::Serialize)]
-struct ScoreDetailResponse {
-    result: String,    // e.g. "B+2.5" / "W+7.5" / "—"
-    dead: Vec<String>, // dead stones positions in GTP coords
-    boardSize: u32,
-    komi: f32,
+    StatusCode::NO_CONTENT.into_response()
 }
 
 #[derive(serde::Deserialize)]
-struct ScoreDetailRequest {
-    gameId: String,
+#[serde(rename_all = "camelCase")]
+struct JoinGameRequest {
+    invite_token: String,
 }
 
-// 合并：返回 final_score 结果 + 死子列表 + 棋盘参数（供前端自行计算双方分）
-async fn game_score_detail(
+// 凭邀请令牌加入人人对局，执开局一方的另一色；令牌只能由一个 sid 使用，同一 sid 重复加入返回同一局
+async fn game_join(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<ScoreDetailRequest>,
-) -> impl IntoResponse {
-    let (engine, board_size, komi) = if let Some(gs) = state.game_store.get(&payload.gameId) {
-        (gs.engine.clone(), gs.board_size, gs.komi)
-    } else {
-        return (
-            StatusCode::GONE,
-            Json(serde_json::json!({"error":"GAME_EXPIRED"})),
+    headers: HeaderMap,
+    Json(payload): Json<JoinGameRequest>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    let lock = state
+        .sid_locks
+        .entry(sid.clone())
+        .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(())))
+        .clone();
+    let _guard = lock.lock().await;
+
+    let game_id = state
+        .game_store
+        .iter()
+        .find(|gs| gs.invite_token.as_deref() == Some(payload.invite_token.as_str()))
+        .map(|gs| gs.key().clone());
+    let Some(game_id) = game_id else {
+        return error_response(StatusCode::NOT_FOUND, "INVITE_NOT_FOUND", None, set_cookie);
+    };
+    let active = state
+        .session_store
+        .get(&sid)
+        .map(|v| v.len() as u32)
+        .unwrap_or(0);
+    let Some(mut gs) = state.game_store.get_mut(&game_id) else {
+        return error_response(StatusCode::NOT_FOUND, "INVITE_NOT_FOUND", None, set_cookie);
+    };
+    if sid == gs.sid {
+        return error_response(
+            StatusCode::CONFLICT,
+            "CANNOT_JOIN_OWN_GAME",
+            None,
+            set_cookie,
+        );
+    }
+    if let Some(color) = gs.seat_of(&sid) {
+        let body = gs.view(&game_id, color);
+        return with_cookie((StatusCode::OK, Json(body)).into_response(), set_cookie);
+    }
+    if let game::archive::Opponent::Human { guest_sid: Some(_) } = &gs.opponent {
+        return error_response(StatusCode::CONFLICT, "INVITE_USED", None, set_cookie);
+    }
+    if gs.end.is_some() {
+        return error_response(StatusCode::CONFLICT, "GAME_FINISHED", None, set_cookie);
+    }
+    if active >= state.concurrency_limit_per_sid {
+        let body = serde_json::json!({
+            "error": "CONCURRENCY_LIMIT",
+            "retryAfterSeconds": 10,
+            "activeGames": active,
+        });
+        return with_cookie(
+            (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response(),
+            set_cookie,
         );
+    }
+    gs.opponent = game::archive::Opponent::Human {
+        guest_sid: Some(sid.clone()),
     };
+    gs.last_active_at = now_unix();
+    let to_move = gs.board.to_move();
+    if let Some(clock) = gs.clock.as_mut() {
+        clock.start(to_move, Instant::now());
+    }
+    gs.persist(&game_id);
+    gs.emit(game::GameEvent::Joined);
+    let body = gs.view(&game_id, gs.human().opponent());
+    drop(gs);
+    state
+        .session_store
+        .entry(sid)
+        .or_default()
+        .push(game_id.clone());
+    tracing::info!(game_id, "player joined by invite");
+    with_cookie((StatusCode::OK, Json(body)).into_response(), set_cookie)
+}
 
-    // 无引擎：返回占位信息
-    if engine.is_none() {
-        let body = ScoreDetailResponse {
-            result: "—".to_string(),
-            dead: vec![],
-            boardSize: board_size,
-            komi,
-        };
-        return (StatusCode::OK, Json(serde_json::to_value(body).unwrap()));
+/// 从签名 Cookie 取出 sid；没有、被篡改或已过期时签发新 sid，需要重签时一并返回 Set-Cookie
+fn get_or_create_sid(
+    keys: &session::SessionKeys,
+    headers: HeaderMap,
+) -> (String, Option<HeaderValue>) {
+    let now = now_unix();
+    // 读取 cookie：取第一个校验通过的 sid
+    let session = headers
+        .get_all("cookie")
+        .iter()
+        .filter_map(|hdr| hdr.to_str().ok())
+        .flat_map(|s| s.split(';'))
+        .filter_map(|part| part.trim().strip_prefix("sid="))
+        .find_map(|value| keys.verify(value, now));
+    if let Some(session) = &session
+        && !session.refresh
+    {
+        return (session.sid.clone(), None);
     }
-    let e = engine.unwrap();
 
-    // 1) final_status_list dead
-    let mut dead: Vec<String> = Vec::new();
-    if let Ok(resp) = e.send_command("final_status_list dead").await {
-        let mut acc = String::new();
-        for line in resp.lines() {
-            let t = line.trim();
-            if t.is_empty() {
-                continue;
-            }
-            let t = t.trim_start_matches('=');
-            acc.push(' ');
-            acc.push_str(t);
+    // 生成（或以当前密钥重签）并返回 Set-Cookie
+    let sid = session.map_or_else(|| uuid::Uuid::new_v4().to_string(), |s| s.sid);
+    let val = HeaderValue::from_str(&keys.set_cookie(&sid, now)).ok();
+    (sid, val)
+}
+
+async fn game_play(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<PlayPayload>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    with_cookie(
+        play_move(&state, &sid, payload).await.into_response(),
+        set_cookie,
+    )
+}
+
+/// 人类落子（HTTP 与 WebSocket 共用）：人机对局在引擎应手后返回，人人对局只落这一手
+async fn play_move(
+    state: &AppState,
+    sid: &str,
+    payload: PlayPayload,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let player_move: Vertex = match payload.player_move.parse() {
+        Ok(v) => v,
+        Err(err) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    serde_json::json!({"error":"ILLEGAL_MOVE","reason":"BAD_VERTEX","detail":err.to_string()}),
+                ),
+            );
         }
-        for tok in acc.split_whitespace() {
-            let v = tok.trim();
-            if v.is_empty() {
-                continue;
-            }
-            if v.eq_ignore_ascii_case("pass") {
-                continue;
-            }
-            dead.push(v.to_string());
+    };
+    let turn = match lock_game(state, &payload.game_id).await {
+        Ok(guard) => guard,
+        Err(resp) => return resp,
+    };
+    // 读取必要信息并在棋盘上校验人类着法，之后释放 guard，避免跨 await 持有 DashMap 锁
+    let (engine, human_color) = {
+        let (mut gs, color) = match owned_game(state, &payload.game_id, sid) {
+            Ok(game) => game,
+            Err(resp) => return resp,
+        };
+        gs.last_active_at = now;
+        gs.check_flag(&payload.game_id);
+        if let Some(end) = &gs.end {
+            return game_finished_response(end);
         }
-    }
+        if let Err(err) = gs.board.check(color, player_move) {
+            return illegal_move_response(&err);
+        }
+        if gs.is_pvp() {
+            drop(gs);
+            return play_pvp_move(state, &payload.game_id, color, player_move).await;
+        }
+        (gs.engine.clone(), color)
+    };
+    let ai_color = human_color.opponent();
+    let mut engine = engine;
 
-    // 2) final_score，带回退的兜底
-    let mut result_str = String::new();
-    if let Ok(resp) = e.send_command("final_score").await {
-        let line = resp
-            .lines()
-            .find(|l| !l.trim().is_empty())
-            .unwrap_or("")
-            .trim();
-        let s = line.trim_start_matches('=').trim();
-        if !s.is_empty() {
-            result_str = s.to_string();
+    match game_call(state, &payload.game_id, &mut engine, |e| async move {
+        e.play(human_color, player_move).await
+    })
+    .await
+    {
+        Ok(()) => {}
+        Err(engine::gtp::EngineError::Gtp(msg)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    serde_json::json!({"error":"ILLEGAL_MOVE","reason":"ENGINE_REJECTED","detail":msg}),
+                ),
+            );
+        }
+        Err(err) => {
+            tracing::error!(?err, "play failed");
+            return engine_error_response(&err);
         }
     }
-    if result_str.is_empty() {
-        let mut applied: u32 = 0;
-        for cmd in ["play B pass", "play W pass", "play B pass", "play W pass"].iter() {
-            if applied >= 2 {
-                break;
-            }
-            if e.send_command(cmd).await.is_ok() {
-                applied += 1;
+    // 按钟前的棋钟：整手失败时据此恢复，按钟带来的加秒、读秒复原不留给人类
+    let clock_before_press = state
+        .game_store
+        .get(&payload.game_id)
+        .and_then(|gs| gs.clock.clone())
+        .map(|clock| (clock, Instant::now()));
+    let player_captured = match record_game_move(
+        state,
+        &payload.game_id,
+        human_color,
+        GenMove::Play(player_move),
+    ) {
+        Ok(captured) => captured,
+        // 校验后棋盘被并发请求改变：撤销引擎里的这一手
+        Err(err) => {
+            if let Some(engine) = engine {
+                undo_in_background(engine, 1);
             }
+            return illegal_move_response(&err);
         }
-        if let Ok(resp) = e.send_command("final_score").await {
-            let line = resp
-                .lines()
-                .find(|l| !l.trim().is_empty())
-                .unwrap_or("")
-                .trim();
-            result_str = line.trim_start_matches('=').trim().to_string();
+    };
+
+    // 按钟时已超时：这一手不再由 AI 应对
+    if game_over(state, &payload.game_id) {
+        return play_response(state, &payload.game_id, None, &player_captured, &[]);
+    }
+    // 人类应对 AI 的 pass 也 pass：双方连续 pass，数子终局
+    if player_move == Vertex::Pass && ended_by_passes(state, &payload.game_id) {
+        finish_by_scoring(state, &payload.game_id).await;
+        return play_response(state, &payload.game_id, None, &player_captured, &[]);
+    }
+
+    let time_left = engine_time_left(state, &payload.game_id, ai_color);
+    let mv = match game_call(state, &payload.game_id, &mut engine, |e| async move {
+        if let Some((seconds, stones)) = time_left {
+            e.time_left(ai_color, seconds, stones).await?;
         }
-        for _ in 0..applied {
-            let _ = e.send_command("undo").await;
+        e.genmove(ai_color).await
+    })
+    .await
+    {
+        Ok(mv) => mv,
+        Err(err) => {
+            tracing::error!(?err, "genmove failed");
+            rollback_player_move(state, &payload.game_id, engine, clock_before_press, turn);
+            return engine_error_response(&err);
         }
-        if result_str.is_empty() {
-            result_str = "—".to_string();
+    };
+    let engine_captured = match record_game_move(state, &payload.game_id, ai_color, mv) {
+        Ok(captured) => captured,
+        Err(err) => {
+            tracing::error!(?err, %mv, "engine move rejected by board");
+            rollback_player_move(state, &payload.game_id, engine, clock_before_press, turn);
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(serde_json::json!({"error":"ENGINE_FAILED"})),
+            );
         }
-    }
-
-    let body = ScoreDetailResponse {
-        result: result_str,
-        dead,
-        boardSize: board_size,
-        komi,
     };
-    (StatusCode::OK, Json(serde_json::to_value(body).unwrap()))
-}
 
-fn overrides_for_level(level: u8) -> Vec<(&'static str, String)> {
-    // 更细分的 5 档难度：搜索预算 + 随机性 + 认输策略
-    // 目标：低档更友好/更有趣（更随机、不轻易认输），高档更强/求最优
-    let (
-        visits,
-        time_sec,
-        root_temp,
-        chosen_early,
-        chosen_halflife,
-        allow_resign,
-        resign_threshold,
-    ) = match level {
-        1 => (
-            80,    // 搜索访问数
-            0.35,  // 时间上限（秒）
-            1.6,   // 根温度：更活泼
-            0.95,  // 前期选点温度：更随机
-            30,    // 温度衰减半衰期（手数）更长
-            false, // 不允许认输
-            -0.99, // 阈值占位（无效，因为不允许认输）
+    match mv {
+        GenMove::Resign => finish_game(
+            state,
+            &payload.game_id,
+            game::GameEnd::resignation(ai_color, now_unix()),
         ),
-        2 => (220, 0.55, 1.1, 0.8, 26, false, -0.99),
-        3 => (
-            650, 1.1, 0.6, 0.6, 19, true, -0.97, // 不要太早投降
-        ),
-        4 => (2200, 2.2, 0.25, 0.35, 15, true, -0.93),
-        _ => (
-            // 稳版 5★：在 4★ 基础上小幅提升预算，其他保持一致，优先稳定
-            3000, // maxVisits（4★为 2200）
-            2.5,  // maxTime（4★为 2.2）
-            0.25, // 与 4★ 相同
-            0.35, // 与 4★ 相同
-            15,   // 与 4★ 相同
-            true, -0.93,
-        ),
-    };
-
-    let mut v = vec![
-        ("maxVisits", visits.to_string()),
-        ("maxTime", format!("{:.2}", time_sec)),
-        ("rootPolicyTemperature", format!("{:.2}", root_temp)),
-        ("chosenMoveTemperatureEarly", format!("{:.2}", chosen_early)),
-        ("chosenMoveTemperatureHalflife", chosen_halflife.to_string()),
-        ("allowResignation", allow_resign.to_string()),
-    ];
-    if allow_resign {
-        v.push(("resignThreshold", format!("{:.2}", resign_threshold)));
+        GenMove::Play(Vertex::Pass) if ended_by_passes(state, &payload.game_id) => {
+            finish_by_scoring(state, &payload.game_id).await;
+        }
+        GenMove::Play(_) => {}
     }
-    v
+    play_response(
+        state,
+        &payload.game_id,
+        Some(mv),
+        &player_captured,
+        &engine_captured,
+    )
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct ReviewImportResponse {
-    review_id: String,
-    board_size: u32,
-    komi: f32,
-    meta: review::GameMeta,
-    initial_setup: review::InitialSetup,
-    final_stones: review::BoardStones,
-    moves: Vec<review::MoveNode>,
+/// 人人对局的一手：只经权威棋盘校验，不经引擎；双方连续 pass 时数子终局
+async fn play_pvp_move(
+    state: &AppState,
+    game_id: &str,
+    color: Color,
+    vertex: Vertex,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let captured = match record_game_move(state, game_id, color, GenMove::Play(vertex)) {
+        Ok(captured) => captured,
+        Err(err) => return illegal_move_response(&err),
+    };
+    if vertex == Vertex::Pass && !game_over(state, game_id) && ended_by_passes(state, game_id) {
+        finish_by_scoring(state, game_id).await;
+    }
+    play_response(state, game_id, None, &captured, &[])
 }
 
-#[derive(serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct ReviewImportUrlRequest {
-    source_url: String,
+fn game_not_owned_response() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({"error":"GAME_NOT_OWNED"})),
+    )
 }
 
-#[derive(serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct ReviewAnalyzeRequest {
-    review_id: String,
-    move_index: u32,
-    #[serde(default)]
-    max_visits: Option<u32>,
+fn game_expired_response() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::GONE,
+        Json(serde_json::json!({"error":"GAME_EXPIRED"})),
+    )
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct ReviewAnalyzeResponse {
-    review_id: String,
-    move_index: u32,
-    analysis: review::KataAnalysis,
+type GameRef<'a> = dashmap::mapref::one::RefMut<'a, String, GameState>;
+
+/// 取出 sid 参与的对局及其执子：对局不存在 410 GAME_EXPIRED，不是对局玩家 403 GAME_NOT_OWNED
+fn owned_game<'a>(
+    state: &'a AppState,
+    game_id: &str,
+    sid: &str,
+) -> Result<(GameRef<'a>, Color), (StatusCode, Json<serde_json::Value>)> {
+    let gs = state
+        .game_store
+        .get_mut(game_id)
+        .ok_or_else(game_expired_response)?;
+    match gs.seat_of(sid) {
+        Some(color) => Ok((gs, color)),
+        None => Err(game_not_owned_response()),
+    }
 }
 
-#[derive(serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct ExerciseSaveRequest {
-    review_id: String,
-    move_index: u32,
-    category: String,
-    #[serde(default)]
-    answer: Option<ExerciseAnswerRequest>,
-    #[serde(default)]
-    include_raw_sgf: Option<bool>,
+/// 串行化同一对局的引擎命令序列：并发的落子、悔棋等交错执行会让引擎与棋盘分叉；对局不存在时 410
+async fn lock_game(
+    state: &AppState,
+    game_id: &str,
+) -> Result<tokio::sync::OwnedMutexGuard<()>, (StatusCode, Json<serde_json::Value>)> {
+    let turn = state
+        .game_store
+        .get(game_id)
+        .map(|gs| gs.turn.clone())
+        .ok_or_else(game_expired_response)?;
+    Ok(turn.lock_owned().await)
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct ExerciseSaveResponse {
-    exercise_id: String,
+/// 人人对局不提供依赖引擎对手的操作（悔棋、提示等）
+fn not_supported_in_pvp_response() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::CONFLICT,
+        Json(serde_json::json!({"error":"NOT_SUPPORTED_IN_PVP"})),
+    )
 }
 
-#[derive(Debug, serde::Deserialize)]
+/// 落子响应：双方本手提子、当前局面摘要与终局信息
+fn play_response(
+    state: &AppState,
+    game_id: &str,
+    engine_move: Option<GenMove>,
+    player_captured: &[Vertex],
+    engine_captured: &[Vertex],
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(gs) = state.game_store.get(game_id) else {
+        return (
+            StatusCode::GONE,
+            Json(serde_json::json!({"error":"GAME_EXPIRED"})),
+        );
+    };
+    let body = serde_json::json!({
+        "engineMove": engine_move.map(|mv| mv.to_string()),
+        "captures": {
+            "player": vertex_strings(player_captured),
+            "engine": vertex_strings(engine_captured),
+        },
+        "prisoners": {
+            "black": gs.board.prisoners(Color::Black),
+            "white": gs.board.prisoners(Color::White),
+        },
+        "toMove": color_name(gs.board.to_move()),
+        "ko": gs.board.ko().map(|v| v.to_string()),
+        "moveNumber": gs.board.moves().len(),
+        "end": end_json(gs.end.as_ref()),
+        "clock": gs.clock_json(),
+    });
+    (StatusCode::OK, Json(body))
+}
+
+/// 整手失败时撤销棋盘上的人类着法，由前端重下；引擎里已落几手无从确定（超时的 genmove
+/// 迟到的应手可能是着法、pass 或认输），按撤销后的棋盘重建。
+/// 棋钟回到按钟前（clock_before_press 为按钟前的棋钟与按钟时刻）：人类只扣思考到按钟的用时，
+/// 等引擎的时间不计，从现在起重新计时
+fn rollback_player_move(
+    state: &AppState,
+    game_id: &str,
+    engine: Option<Arc<dyn GoEngine>>,
+    clock_before_press: Option<(game::clock::GameClock, Instant)>,
+    turn: tokio::sync::OwnedMutexGuard<()>,
+) {
+    if let Some(mut gs) = state.game_store.get_mut(game_id) {
+        gs.board.undo();
+        gs.emit_undo();
+        let human = gs.human();
+        if let Some((mut clock, pressed_at)) = clock_before_press {
+            clock.stop(pressed_at);
+            clock.start(human, Instant::now());
+            gs.clock = Some(clock);
+        }
+        gs.persist(game_id);
+    }
+    drop(engine);
+    rebuild_in_background(state, game_id, turn);
+}
+
+fn ended_by_passes(state: &AppState, game_id: &str) -> bool {
+    state
+        .game_store
+        .get(game_id)
+        .is_some_and(|gs| gs.board.ended_by_passes())
+}
+
+fn game_over(state: &AppState, game_id: &str) -> bool {
+    state
+        .game_store
+        .get(game_id)
+        .is_some_and(|gs| gs.end.is_some())
+}
+
+fn finish_game(state: &AppState, game_id: &str, end: game::GameEnd) {
+    if let Some(mut gs) = state.game_store.get_mut(game_id) {
+        gs.finish(game_id, end);
+    }
+}
+
+/// genmove 前告知引擎的剩余用时；不计时的对局为 None
+fn engine_time_left(state: &AppState, game_id: &str, color: Color) -> Option<(u32, u32)> {
+    let gs = state.game_store.get(game_id)?;
+    gs.clock
+        .as_ref()
+        .map(|clock| clock.time_left(color, Instant::now()))
+}
+
+/// 双方连续 pass：按 score_detail 的逻辑数子并记录结果；未挂引擎的人人对局结果记为 "?"
+async fn finish_by_scoring(state: &AppState, game_id: &str) {
+    let scored_by_engine = state
+        .game_store
+        .get(game_id)
+        .is_some_and(|gs| !gs.is_pvp() || gs.scoring_engine);
+    let (score, dead) = if scored_by_engine {
+        score_game(state, game_id).await.unwrap_or_default()
+    } else {
+        (None, Vec::new())
+    };
+    finish_game(
+        state,
+        game_id,
+        game::GameEnd::scored(score, dead, now_unix()),
+    );
+}
+
+fn now_unix() -> i64 {
+    time::OffsetDateTime::now_utc().unix_timestamp()
+}
+
+/// 响应体中的 end 字段：未结束为 { finished: false }，结束时附带结果与原因
+fn end_json(end: Option<&game::GameEnd>) -> serde_json::Value {
+    let mut value = end
+        .and_then(|e| serde_json::to_value(e).ok())
+        .unwrap_or_else(|| serde_json::json!({}));
+    value["finished"] = serde_json::Value::Bool(end.is_some());
+    value
+}
+
+fn game_finished_response(end: &game::GameEnd) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::CONFLICT,
+        Json(serde_json::json!({"error":"GAME_FINISHED","end":end_json(Some(end))})),
+    )
+}
+
+fn illegal_move_response(err: &game::board::IllegalMove) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "error": "ILLEGAL_MOVE",
+            "reason": err.code(),
+            "detail": err.to_string(),
+        })),
+    )
+}
+
+fn vertex_strings(vertices: &[Vertex]) -> Vec<String> {
+    vertices.iter().map(Vertex::to_string).collect()
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::Black => "black",
+        Color::White => "white",
+    }
+}
+
+// 人类认输：记录结果，对局保留到 close 以便查询
+async fn game_resign(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<GameIdPayload>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    with_cookie(
+        resign_game(&state, &sid, payload).await.into_response(),
+        set_cookie,
+    )
+}
+
+async fn resign_game(
+    state: &AppState,
+    sid: &str,
+    payload: GameIdPayload,
+) -> (StatusCode, Json<serde_json::Value>) {
+    // 由请求方认输（人人对局中即请求方所执的一色）
+    let (mut gs, loser) = match owned_game(state, &payload.game_id, sid) {
+        Ok(game) => game,
+        Err(resp) => return resp,
+    };
+    gs.check_flag(&payload.game_id);
+    if let Some(end) = &gs.end {
+        return game_finished_response(end);
+    }
+    let end = game::GameEnd::resignation(loser, now_unix());
+    tracing::info!(game_id = %payload.game_id, "player resigned");
+    gs.finish(&payload.game_id, end);
+    let body = serde_json::json!({"end": end_json(gs.end.as_ref()), "clock": gs.clock_json()});
+    (StatusCode::OK, Json(body))
+}
+
+// 当前 sid 尚未释放的对局（含重启后恢复的），供前端重新接上棋局
+async fn game_active(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    let ids = state
+        .session_store
+        .get(&sid)
+        .map(|v| v.clone())
+        .unwrap_or_default();
+    let games: Vec<serde_json::Value> = ids
+        .iter()
+        .filter_map(|game_id| {
+            let gs = state.game_store.get(game_id)?;
+            let color = gs.seat_of(&sid)?;
+            Some(gs.view(game_id, color))
+        })
+        .collect();
+    let body = serde_json::json!({ "games": games });
+    with_cookie((StatusCode::OK, Json(body)).into_response(), set_cookie)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GameSgfQuery {
+    game_id: String,
+    comments: Option<bool>, // 是否附带形势判断评注，默认附带
+}
+
+// 导出进行中或已结束的对局为 SGF；已关闭的对局从归档中读取（仅限本人）
+async fn game_sgf(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<GameSgfQuery>,
+) -> Response {
+    let live = state
+        .game_store
+        .get(&query.game_id)
+        .map(|gs| gs.record(&query.game_id));
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    let record = match live {
+        Some(record) if !record.players().iter().any(|(p, _)| *p == sid) => {
+            return error_response(StatusCode::FORBIDDEN, "GAME_NOT_OWNED", None, set_cookie);
+        }
+        Some(record) => record,
+        None => match archived_game(&state, &query.game_id, &sid).await {
+            Ok(record) => record,
+            Err(resp) => return with_cookie(resp, set_cookie),
+        },
+    };
+    let sgf = game::sgf::write_sgf(&record, query.comments.unwrap_or(true));
+    let disposition = format!("attachment; filename=\"{}.sgf\"", query.game_id);
+    let mut resp = sgf.into_response();
+    resp.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-go-sgf; charset=utf-8"),
+    );
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        resp.headers_mut()
+            .insert(axum::http::header::CONTENT_DISPOSITION, value);
+    }
+    with_cookie(resp, set_cookie)
+}
+
+// 把进行中或已归档的对局转为当前 sid 的复盘，省去导出再导入 SGF
+async fn game_to_review(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<GameIdPayload>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    let live = state
+        .game_store
+        .get(&payload.game_id)
+        .map(|gs| gs.record(&payload.game_id));
+    let record = match live {
+        Some(record) if !record.players().iter().any(|(p, _)| *p == sid) => {
+            return error_response(StatusCode::FORBIDDEN, "GAME_NOT_OWNED", None, set_cookie);
+        }
+        Some(record) => record,
+        None => match archived_game(&state, &payload.game_id, &sid).await {
+            Ok(record) => record,
+            Err(resp) => return with_cookie(resp, set_cookie),
+        },
+    };
+    let sgf_text = game::sgf::write_sgf(&record, true);
+    let parsed = match review::parser::parse_sgf(&sgf_text) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            tracing::error!(?err, game_id = %payload.game_id, "exported game does not parse");
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "SGF_PARSE_FAILED",
+                None,
+                set_cookie,
+            );
+        }
+    };
+    let source = review::ReviewSource::Game(payload.game_id);
+    let review_state = review::ReviewState::from_parsed(sid, sgf_text, source, parsed);
+    let review_id = format!("r-{}", uuid::Uuid::new_v4());
+    let response_payload = ReviewImportResponse {
+        review_id: review_id.clone(),
+        board_size: review_state.board_size,
+        komi: review_state.komi,
+        meta: review_state.meta.clone(),
+        initial_setup: review_state.initial_setup.clone(),
+        final_stones: review_state.final_stones.clone(),
+        moves: review_state.moves.clone(),
+    };
+    state.review_store.insert(review_id, review_state);
+    with_cookie(
+        (StatusCode::OK, Json(response_payload)).into_response(),
+        set_cookie,
+    )
+}
+
+/// 读取 sid 名下的归档对局；不存在 410（与对局接口一致），属于他人 403
+async fn archived_game(
+    state: &AppState,
+    game_id: &str,
+    sid: &str,
+) -> Result<game::archive::GameRecord, Response> {
+    match state.archive.get(game_id).await {
+        Ok(Some(record)) if record.players().iter().any(|(p, _)| p == sid) => Ok(record),
+        Ok(Some(_)) => Err(error_response(
+            StatusCode::FORBIDDEN,
+            "GAME_NOT_OWNED",
+            None,
+            None,
+        )),
+        Ok(None) => Err(error_response(
+            StatusCode::GONE,
+            "GAME_NOT_FOUND",
+            None,
+            None,
+        )),
+        Err(err) => {
+            tracing::error!(?err, game_id, "failed to read archived game");
+            Err(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "FAILED_TO_READ_ARCHIVE",
+                None,
+                None,
+            ))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveListQuery {
+    result: Option<game::archive::Outcome>, // 以人类一方计：win / loss / draw
+    level: Option<usize>,
+    profile: Option<String>,
+    board_size: Option<u32>,
+    page: Option<usize>,
+    page_size: Option<usize>,
+}
+
+const ARCHIVE_PAGE_SIZE_MAX: usize = 100;
+
+// 当前 sid 的已结束对局，最近的在前
+async fn archive_list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ArchiveListQuery>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    let filter = game::archive::ArchiveFilter {
+        outcome: query.result,
+        level: query.level,
+        profile: query.profile,
+        board_size: query.board_size,
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let page_size = query
+        .page_size
+        .unwrap_or(20)
+        .clamp(1, ARCHIVE_PAGE_SIZE_MAX);
+    let (total, games) = state.archive.list(&sid, &filter, page, page_size);
+    let body = serde_json::json!({
+        "total": total,
+        "page": page,
+        "pageSize": page_size,
+        "games": games,
+    });
+    with_cookie((StatusCode::OK, Json(body)).into_response(), set_cookie)
+}
+
+// 单局归档详情（含全部着法与形势判断），不返回 sid
+async fn archive_get(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<GameIdPayload>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    let record = match archived_game(&state, &query.game_id, &sid).await {
+        Ok(record) => record,
+        Err(resp) => return with_cookie(resp, set_cookie),
+    };
+    let mut body = serde_json::to_value(&record).unwrap_or_default();
+    if let Some(obj) = body.as_object_mut() {
+        obj.remove("sid");
+        if let Some(opponent) = obj.get_mut("opponent").and_then(|o| o.as_object_mut()) {
+            opponent.remove("guestSid");
+        }
+    }
+    with_cookie((StatusCode::OK, Json(body)).into_response(), set_cookie)
+}
+
+// 悔棋：撤回人类最后一手及 AI 的应手（引擎与服务端棋盘同步撤销），受难度档位的次数上限约束
+async fn game_undo(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<GameIdPayload>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    with_cookie(
+        undo_moves(&state, &sid, payload).await.into_response(),
+        set_cookie,
+    )
+}
+
+async fn undo_moves(
+    state: &AppState,
+    sid: &str,
+    payload: GameIdPayload,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let _turn = match lock_game(state, &payload.game_id).await {
+        Ok(guard) => guard,
+        Err(resp) => return resp,
+    };
+    let (engine, undone) = {
+        let (mut gs, human_color) = match owned_game(state, &payload.game_id, sid) {
+            Ok(game) => game,
+            Err(resp) => return resp,
+        };
+        gs.check_flag(&payload.game_id);
+        if let Some(end) = &gs.end {
+            return game_finished_response(end);
+        }
+        if gs.is_pvp() {
+            return not_supported_in_pvp_response();
+        }
+        if let Some(limit) = gs.undo_limit
+            && gs.undos_used >= limit
+        {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error":"UNDO_LIMIT_REACHED","undoLimit":limit})),
+            );
+        }
+        // 从末尾数到人类最后一手（含其后的 AI 应手）
+        let moves = gs.board.moves();
+        let Some(pos) = moves.iter().rposition(|m| m.color == human_color) else {
+            return (
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({"error":"NOTHING_TO_UNDO"})),
+            );
+        };
+        let count = moves.len() - pos;
+        // 先在权威棋盘上悔棋，引擎随后跟上
+        let undone: Vec<String> = (0..count)
+            .filter_map(|_| gs.board.undo())
+            .map(|m| m.vertex.to_string())
+            .collect();
+        gs.undos_used += 1;
+        gs.last_active_at = time::OffsetDateTime::now_utc().unix_timestamp();
+        let move_number = gs.board.moves().len();
+        gs.evals.retain(|&n, _| n <= move_number);
+        gs.emit_undo();
+        gs.persist(&payload.game_id);
+        (gs.engine.clone(), undone)
+    };
+
+    // 引擎逐手撤销；任一步失败（含中途崩溃）都按悔棋后的棋盘重建引擎，重建失败则留待下次使用时再建
+    if let Some(engine) = engine {
+        let mut failed = None;
+        for _ in 0..undone.len() {
+            if let Err(err) = engine.undo().await {
+                failed = Some(err);
+                break;
+            }
+        }
+        if let Some(err) = failed {
+            tracing::warn!(?err, game_id = %payload.game_id, "engine undo failed, rebuilding from board");
+            drop(engine);
+            rebuild_game_engine(state, &payload.game_id).await;
+        }
+    }
+
+    let Some(gs) = state.game_store.get(&payload.game_id) else {
+        return game_expired_response();
+    };
+    let body = serde_json::json!({
+        "undone": undone,
+        "stones": {
+            "black": vertex_strings(&gs.board.stones(Color::Black)),
+            "white": vertex_strings(&gs.board.stones(Color::White)),
+        },
+        "prisoners": {
+            "black": gs.board.prisoners(Color::Black),
+            "white": gs.board.prisoners(Color::White),
+        },
+        "toMove": color_name(gs.board.to_move()),
+        "ko": gs.board.ko().map(|v| v.to_string()),
+        "moveNumber": gs.board.moves().len(),
+        "undosUsed": gs.undos_used,
+        "undosLeft": gs.undo_limit.map(|limit| limit.saturating_sub(gs.undos_used)),
+        "clock": gs.clock_json(),
+    });
+    (StatusCode::OK, Json(body))
+}
+
+#[derive(serde::Serialize)]
+struct HintResponse {
+    suggestion: String,
+    clock: serde_json::Value,
+}
+
+// 为当前人类一方给出建议一手（不改变引擎棋局状态），仅返回坐标
+async fn game_hint(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<GameIdPayload>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    with_cookie(
+        hint_move(&state, &sid, payload).await.into_response(),
+        set_cookie,
+    )
+}
+
+async fn hint_move(
+    state: &AppState,
+    sid: &str,
+    payload: GameIdPayload,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let turn = match lock_game(state, &payload.game_id).await {
+        Ok(guard) => guard,
+        Err(resp) => return resp,
+    };
+    // 读取必要信息
+    let (engine, human_color) = {
+        let (mut gs, human_color) = match owned_game(state, &payload.game_id, sid) {
+            Ok(game) => game,
+            Err(resp) => return resp,
+        };
+        gs.check_flag(&payload.game_id);
+        if let Some(end) = &gs.end {
+            return game_finished_response(end);
+        }
+        if gs.is_pvp() {
+            return not_supported_in_pvp_response();
+        }
+        (gs.engine.clone(), human_color)
+    };
+
+    let mut engine = engine;
+    // 使用 genmove + undo，仅提供坐标（认输不落子，无需撤销）
+    match game_call(state, &payload.game_id, &mut engine, |e| async move {
+        e.genmove(human_color).await
+    })
+    .await
+    {
+        Ok(mv) => {
+            // undo 不走 game_call 的重建重试：重建出的引擎本就没有提示子。撤销失败（含崩溃、
+            // 超时）时提示子是否还在引擎里无从确定，按棋盘重建
+            if let GenMove::Play(_) = mv
+                && let Some(current) = engine.take()
+                && let Err(err) = current.undo().await
+            {
+                tracing::warn!(?err, game_id = %payload.game_id, "undo after hint failed, rebuilding from board");
+                drop(current);
+                rebuild_in_background(state, &payload.game_id, turn);
+            }
+            let body = HintResponse {
+                suggestion: mv.to_string(),
+                clock: state
+                    .game_store
+                    .get(&payload.game_id)
+                    .map(|gs| gs.clock_json())
+                    .unwrap_or_default(),
+            };
+            let val =
+                serde_json::to_value(body).unwrap_or_else(|_| serde_json::json!({"suggestion":""}));
+            (StatusCode::OK, Json(val))
+        }
+        Err(err) => {
+            tracing::error!(?err, "genmove for hint failed");
+            // 超时的 genmove 迟到时可能已在引擎里落子
+            if matches!(err, engine::gtp::EngineError::Timeout { .. }) {
+                drop(engine);
+                rebuild_in_background(state, &payload.game_id, turn);
+            }
+            engine_error_response(&err)
+        }
+    }
+}
+
+/// 引擎命令失败时的响应：超时 504，其余 503
+fn engine_error_response(err: &engine::gtp::EngineError) -> (StatusCode, Json<serde_json::Value>) {
+    match err {
+        engine::gtp::EngineError::Timeout { .. } => (
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(serde_json::json!({"error":"ENGINE_TIMEOUT"})),
+        ),
+        _ => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({"error":"ENGINE_FAILED"})),
+        ),
+    }
+}
+
+/// 无法为对局取得引擎（重建失败）时的响应
+fn engine_unavailable_response() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({"error":"ENGINE_FAILED"})),
+    )
+}
+
+/// 引擎棋局与权威棋盘可能不一致时，后台按棋盘重建引擎；持对局锁进行，之后的命令排在其后。
+/// 旧进程须先读完残留响应（最长一个 genmove 时限）才能复用，不让请求等它
+fn rebuild_in_background(state: &AppState, game_id: &str, turn: tokio::sync::OwnedMutexGuard<()>) {
+    let state = state.clone();
+    let game_id = game_id.to_string();
+    tokio::spawn(async move {
+        rebuild_game_engine(&state, &game_id).await;
+        drop(turn);
+    });
+}
+
+/// 后台撤销引擎里最后 count 手，使引擎棋盘与权威棋盘一致（着法已被引擎接受、棋盘却拒绝时）。
+/// undo 会先等残留响应读完（命令在引擎内串行），之后的命令也排在它之后。
+fn undo_in_background(engine: Arc<dyn GoEngine>, count: usize) {
+    tokio::spawn(async move {
+        for _ in 0..count {
+            if let Err(err) = engine.undo().await {
+                tracing::warn!(?err, "failed to undo engine moves");
+                break;
+            }
+        }
+    });
+}
+
+/// 从引擎来源取得一个已按 setup 清盘的引擎；extra_overrides 为对局相关的额外覆盖配置，
+/// owner 标记进程池引擎的占用者
+async fn acquire_engine(
+    state: &AppState,
+    profile: &str,
+    rules: &str,
+    extra_overrides: &[String],
+    setup: engine::pool::BoardSetup,
+    owner: String,
+) -> Result<Arc<dyn GoEngine>, engine::pool::PoolError> {
+    match &state.engine_backend {
+        EngineBackend::Native => Ok(Arc::new(NativeEngine::new(setup))),
+        EngineBackend::Fake { script } => {
+            Ok(Arc::new(FakeEngine::new(setup).with_script(script.clone())))
+        }
+        EngineBackend::KataGo => {
+            let profile = state
+                .profiles
+                .get(profile)
+                .ok_or_else(|| anyhow!("unknown engine profile {profile}"))?;
+            let spec = katago_spec(profile, rules, extra_overrides)
+                .ok_or_else(|| anyhow!("katago not configured"))?;
+            let lease = state.engine_pool.acquire(&spec, setup).await?;
+            lease.set_owner(Some(owner));
+            Ok(Arc::new(lease))
+        }
+        EngineBackend::Gtp { spec } => {
+            let lease = state.engine_pool.acquire(spec, setup).await?;
+            lease.set_owner(Some(owner));
+            Ok(Arc::new(lease))
+        }
+    }
+}
+
+/// 对局引擎调用。engine 为调用方持有的引擎：为空（重启后恢复的对局）时先按着法重放重建；
+/// 进程退出或管道断开时先放下已死的引擎，重建后重试一次
+async fn game_call<T, F, Fut>(
+    state: &AppState,
+    game_id: &str,
+    engine: &mut Option<Arc<dyn GoEngine>>,
+    op: F,
+) -> Result<T, engine::gtp::EngineError>
+where
+    F: Fn(Arc<dyn GoEngine>) -> Fut,
+    Fut: std::future::Future<Output = Result<T, engine::gtp::EngineError>>,
+{
+    if engine.is_none() {
+        *engine = rebuild_game_engine(state, game_id).await;
+    }
+    let Some(current) = engine.clone() else {
+        return Err(engine_unavailable());
+    };
+    match op(current).await {
+        Err(err) if err.is_fatal() => {
+            tracing::warn!(?err, game_id, "game engine died");
+            // 死引擎的租约仍占着进程池名额：调用方与对局状态都放下后才能租到新引擎
+            *engine = None;
+            *engine = restart_game_engine(state, game_id).await;
+            match engine.clone() {
+                Some(fresh) => op(fresh).await,
+                None => Err(err),
+            }
+        }
+        other => other,
+    }
+}
+
+/// 对局引擎无法重建时按进程已退出处理
+fn engine_unavailable() -> engine::gtp::EngineError {
+    engine::gtp::EngineError::Exited {
+        pid: None,
+        status: "engine unavailable".to_string(),
+    }
+}
+
+/// 引擎崩溃后重建并计数
+async fn restart_game_engine(state: &AppState, game_id: &str) -> Option<Arc<dyn GoEngine>> {
+    let engine = rebuild_game_engine(state, game_id).await?;
+    let restarts = state
+        .engine_restarts
+        .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+        + 1;
+    tracing::warn!(game_id, restarts, "game engine restarted");
+    Some(engine)
+}
+
+/// 按对局的难度与规则重新取得引擎并重放已落着法，替换对局状态中的旧引擎。
+/// 旧引擎先从对局状态取出，调用方也须已放下它，租约才会归还进程池
+async fn rebuild_game_engine(state: &AppState, game_id: &str) -> Option<Arc<dyn GoEngine>> {
+    let (profile, rules, extra_overrides, setup, handicap, time_control, moves) = {
+        let mut gs = state.game_store.get_mut(game_id)?;
+        drop(gs.engine.take());
+        let setup = engine::pool::BoardSetup {
+            board_size: gs.board_size,
+            komi: gs.komi,
+        };
+        let handicap = gs.board.handicap().to_vec();
+        (
+            gs.profile.clone(),
+            gs.rules.clone(),
+            handicap_overrides(handicap.len(), gs.human()),
+            setup,
+            handicap,
+            gs.clock.as_ref().map(|c| c.control()),
+            gs.board.move_list(),
+        )
+    };
+    let owner = format!("game:{}", game_id);
+    let engine = match acquire_engine(state, &profile, &rules, &extra_overrides, setup, owner).await
+    {
+        Ok(engine) => engine,
+        Err(err) => {
+            tracing::error!(?err, game_id, "failed to restart game engine");
+            return None;
+        }
+    };
+    if !handicap.is_empty()
+        && let Err(err) = engine.set_handicap(&handicap).await
+    {
+        tracing::error!(?err, game_id, "failed to restore handicap stones");
+        return None;
+    }
+    if let Some(tc) = &time_control
+        && let Err(err) = engine.time_settings(tc).await
+    {
+        tracing::error!(?err, game_id, "failed to restore time settings");
+        return None;
+    }
+    for (color, vertex) in &moves {
+        if let Err(err) = engine.play(*color, *vertex).await {
+            tracing::error!(
+                ?err,
+                game_id,
+                "failed to replay moves into restarted engine"
+            );
+            return None;
+        }
+    }
+    tracing::info!(game_id, replayed = moves.len(), "game engine rebuilt");
+    if let Some(mut gs) = state.game_store.get_mut(game_id) {
+        gs.engine = Some(engine.clone());
+    }
+    Some(engine)
+}
+
+// 把已被引擎接受的着法落到权威棋盘上，返回提掉的子；认输不是着法
+fn record_game_move(
+    state: &AppState,
+    game_id: &str,
+    color: Color,
+    mv: GenMove,
+) -> Result<Vec<Vertex>, game::board::IllegalMove> {
+    let GenMove::Play(vertex) = mv else {
+        return Ok(Vec::new());
+    };
+    let Some(mut gs) = state.game_store.get_mut(game_id) else {
+        return Ok(Vec::new());
+    };
+    let captured = gs.board.play(color, vertex)?.captured.clone();
+    gs.emit(game::GameEvent::Move {
+        color,
+        vertex: vertex.to_string(),
+        captured: vertex_strings(&captured),
+        move_number: gs.board.moves().len(),
+    });
+    // 落子即按钟；用时已尽则判负（着法仍保留在棋盘上）。对方加入前不计时
+    let awaiting_guest = gs.awaiting_guest();
+    if let Some(clock) = gs.clock.as_mut().filter(|_| !awaiting_guest)
+        && !clock.press(color, Instant::now())
+    {
+        gs.finish(game_id, game::GameEnd::timeout(color, now_unix()));
+    }
+    gs.persist(game_id);
+    Ok(captured)
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScoreDetailResponse {
+    result: String,    // e.g. "B+2.5" / "W+7.5" / "—"
+    dead: Vec<String>, // dead stones positions in GTP coords
+    board_size: u32,
+    komi: f32,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScoreDetailRequest {
+    game_id: String,
+}
+
+// 合并：返回 final_score 结果 + 死子列表 + 棋盘参数（供前端自行计算双方分）
+async fn game_score_detail(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<ScoreDetailRequest>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    with_cookie(
+        score_detail(&state, &sid, payload).await.into_response(),
+        set_cookie,
+    )
+}
+
+async fn score_detail(
+    state: &AppState,
+    sid: &str,
+    payload: ScoreDetailRequest,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let _turn = match lock_game(state, &payload.game_id).await {
+        Ok(guard) => guard,
+        Err(resp) => return resp,
+    };
+    let (board_size, komi) = {
+        let (gs, _) = match owned_game(state, &payload.game_id, sid) {
+            Ok(game) => game,
+            Err(resp) => return resp,
+        };
+        // 人人对局只有挂了引擎才能数子
+        if gs.is_pvp() && !gs.scoring_engine {
+            return not_supported_in_pvp_response();
+        }
+        (gs.board_size, gs.komi)
+    };
+    let Some((score, dead)) = score_game(state, &payload.game_id).await else {
+        return (
+            StatusCode::GONE,
+            Json(serde_json::json!({"error":"GAME_EXPIRED"})),
+        );
+    };
+    if let Some(score) = score
+        && let Some(mut gs) = state.game_store.get_mut(&payload.game_id)
+    {
+        let move_number = gs.board.moves().len();
+        gs.evals.insert(move_number, score.to_string());
+        gs.emit(game::GameEvent::Eval {
+            move_number,
+            score: score.to_string(),
+        });
+        gs.persist(&payload.game_id);
+    }
+    let body = ScoreDetailResponse {
+        result: score.map_or_else(|| "—".to_string(), |s| s.to_string()),
+        dead,
+        board_size,
+        komi,
+    };
+    (StatusCode::OK, Json(serde_json::to_value(body).unwrap()))
+}
+
+/// 数子：死子列表 + final_score（失败时双方补 pass 再试）；对局不存在时返回 None
+async fn score_game(state: &AppState, game_id: &str) -> Option<(Option<Score>, Vec<String>)> {
+    let mut e = state.game_store.get(game_id)?.engine.clone();
+
+    // 1) 死子列表
+    let mut dead: Vec<String> = Vec::new();
+    match game_call(
+        state,
+        game_id,
+        &mut e,
+        |e| async move { e.dead_stones().await },
+    )
+    .await
+    {
+        Ok(vertices) => dead.extend(
+            vertices
+                .into_iter()
+                .filter(|v| *v != Vertex::Pass)
+                .map(|v| v.to_string()),
+        ),
+        Err(err) => tracing::warn!(?err, "failed to list dead stones"),
+    }
+    // 引擎无法重建时不再逐步重试
+    if e.is_none() {
+        return Some((None, dead));
+    }
+
+    // 2) final_score，带回退的兜底
+    let mut score = final_score(state, game_id, &mut e).await;
+    if score.is_none() {
+        let mut applied: u32 = 0;
+        for color in [Color::Black, Color::White, Color::Black, Color::White] {
+            if applied >= 2 {
+                break;
+            }
+            let passed = game_call(state, game_id, &mut e, |e| async move {
+                e.play(color, Vertex::Pass).await
+            })
+            .await;
+            if passed.is_ok() {
+                applied += 1;
+            }
+        }
+        score = final_score(state, game_id, &mut e).await;
+        // 补下的 pass 有几手留在引擎里无从确定（超时迟到、崩溃后按棋盘重放），不盲目 undo，按棋盘重建
+        drop(e);
+        rebuild_game_engine(state, game_id).await;
+    }
+    Some((score, dead))
+}
+
+async fn final_score(
+    state: &AppState,
+    game_id: &str,
+    engine: &mut Option<Arc<dyn GoEngine>>,
+) -> Option<Score> {
+    game_call(
+        state,
+        game_id,
+        engine,
+        |e| async move { e.final_score().await },
+    )
+    .await
+    .ok()
+}
+
+/// 让子局中 KataGo 让子（执白）时按假设自己更强来下，受子（执黑）时下得更稳
+const HANDICAP_PLAYOUT_DOUBLING_ADVANTAGE: f32 = 1.5;
+
+fn handicap_overrides(handicap_stones: usize, human: Color) -> Vec<String> {
+    if handicap_stones == 0 {
+        return Vec::new();
+    }
+    let advantage = match human {
+        Color::Black => HANDICAP_PLAYOUT_DOUBLING_ADVANTAGE,
+        Color::White => -HANDICAP_PLAYOUT_DOUBLING_ADVANTAGE,
+    };
+    vec![format!("playoutDoublingAdvantage={advantage}")]
+}
+
+/// 按难度档位与规则构造 KataGo 启动参数；档位未指定的模型/可执行文件取 MODEL_PATH/ENGINE_PATH，
+/// 缺少任一项或 GTP_CONFIG_PATH 时返回 None
+fn katago_spec(
+    profile: &engine::profile::EngineProfile,
+    rules: &str,
+    extra_overrides: &[String],
+) -> Option<engine::pool::EngineSpec> {
+    let engine_path = profile
+        .engine
+        .clone()
+        .or_else(|| std::env::var("ENGINE_PATH").ok())?;
+    let model_path = profile
+        .model
+        .clone()
+        .or_else(|| std::env::var("MODEL_PATH").ok())?;
+    let config_path = std::env::var("GTP_CONFIG_PATH").ok()?;
+    let mut args = vec![
+        "gtp".to_string(),
+        "-model".to_string(),
+        model_path,
+        "-config".to_string(),
+        config_path,
+    ];
+    // 难度 → 覆盖配置
+    for kv in profile
+        .override_args()
+        .into_iter()
+        .chain(extra_overrides.iter().cloned())
+    {
+        args.push("-override-config".to_string());
+        args.push(kv);
+    }
+    args.push("-override-config".to_string());
+    args.push(format!("rules={}", rules));
+    Some(engine::pool::EngineSpec {
+        program: engine_path,
+        args,
+    })
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EngineStatsResponse {
+    #[serde(flatten)]
+    pool: engine::pool::PoolStats,
+    engine_restarts: u64,
+}
+
+// 进程池状态：容量、存活/空闲/租出数量与排队数，便于调优 ENGINE_POOL_SIZE；附带崩溃重启次数
+async fn engine_pool_stats(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let body = EngineStatsResponse {
+        pool: state.engine_pool.stats(),
+        engine_restarts: state
+            .engine_restarts
+            .load(std::sync::atomic::Ordering::SeqCst),
+    };
+    (StatusCode::OK, Json(body))
+}
+
+// 难度档位列表：前端据此构造难度选择，新开对局以 engineProfile 指定档位名
+async fn engine_profiles(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let body = serde_json::json!({
+        "default": state.profiles.default_profile().name,
+        "profiles": state.profiles.summaries(),
+    });
+    (StatusCode::OK, Json(body))
+}
+
+#[derive(serde::Deserialize)]
+struct EngineStderrQuery {
+    pid: Option<u32>,
+    lines: Option<usize>,
+}
+
+// 管理接口鉴权：请求头 x-admin-token 须与 ADMIN_TOKEN 一致；未配置 ADMIN_TOKEN 时一律拒绝
+fn check_admin(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let Some(expected) = state.admin_token.as_deref() else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({"error":"ADMIN_DISABLED"})),
+        ));
+    };
+    let provided = headers.get("x-admin-token").map(|v| v.as_bytes());
+    if !provided.is_some_and(|provided| admin_token_matches(provided, expected.as_bytes())) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({"error":"ADMIN_TOKEN_REQUIRED"})),
+        ));
+    }
+    Ok(())
+}
+
+// 以两份令牌为密钥对同一标签做 HMAC，再以 verify_slice 常数时间比较，
+// 比较耗时不随令牌前缀相同的长度变化
+fn admin_token_matches(provided: &[u8], expected: &[u8]) -> bool {
+    use hmac::{Hmac, Mac};
+    let mac = |key: &[u8]| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(b"admin-token");
+        mac
+    };
+    mac(provided)
+        .verify_slice(&mac(expected).finalize().into_bytes())
+        .is_ok()
+}
+
+// 诊断：引擎 stderr 最近输出（含最近退出的引擎）；需 x-admin-token 与 ADMIN_TOKEN 一致
+async fn engine_stderr(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<EngineStderrQuery>,
+) -> impl IntoResponse {
+    if let Err(resp) = check_admin(&state, &headers) {
+        return resp;
+    }
+
+    let lines = query.lines.unwrap_or(50).min(200);
+    let mut logs = state.engine_pool.stderr_logs();
+    if let Some(engine) = state.analysis_engine.lock().await.as_ref() {
+        logs.push(engine.stderr().clone());
+    }
+    let snapshots: Vec<_> = logs
+        .iter()
+        .filter(|log| query.pid.is_none() || log.pid() == query.pid)
+        .map(|log| log.snapshot(lines))
+        .collect();
+    if query.pid.is_some() && snapshots.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error":"ENGINE_NOT_FOUND"})),
+        );
+    }
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({"engines": snapshots})),
+    )
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewImportResponse {
+    review_id: String,
+    board_size: u32,
+    komi: f32,
+    meta: review::GameMeta,
+    initial_setup: review::InitialSetup,
+    final_stones: review::BoardStones,
+    moves: Vec<review::MoveNode>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewImportUrlRequest {
+    source_url: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewAnalyzeRequest {
+    review_id: String,
+    move_index: u32,
+    #[serde(default)]
+    max_visits: Option<u32>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewAnalyzeStreamQuery {
+    review_id: String,
+    move_index: u32,
+    #[serde(default)]
+    max_visits: Option<u32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewAnalyzeResponse {
+    review_id: String,
+    move_index: u32,
+    analysis: review::KataAnalysis,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExerciseSaveRequest {
+    review_id: String,
+    move_index: u32,
+    category: String,
+    #[serde(default)]
+    answer: Option<ExerciseAnswerRequest>,
+    #[serde(default)]
+    include_raw_sgf: Option<bool>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExerciseSaveResponse {
+    exercise_id: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct AnswerAlternativeRequest {
     moves: Vec<String>,
@@ -1012,10 +3102,10 @@ const MAX_SGF_BYTES: usize = 1_048_576; // 1 MiB 上限，防止异常大文件
 
 async fn review_import(
     State(state): State<Arc<AppState>>,
-    mut req: Request<axum::body::Body>,
+    req: Request<axum::body::Body>,
 ) -> impl IntoResponse {
     let headers = req.headers().clone();
-    let (sid, set_cookie) = get_or_create_sid(headers.clone());
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers.clone());
 
     enum IncomingSource {
         Local(Vec<u8>),
@@ -1246,14 +3336,17 @@ async fn review_analyze(
     headers: HeaderMap,
     Json(payload): Json<ReviewAnalyzeRequest>,
 ) -> impl IntoResponse {
-    let (sid, set_cookie) = get_or_create_sid(headers);
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
 
     let move_index_usize = payload.move_index as usize;
     let mut cached: Option<review::KataAnalysis> = None;
     let mut analysis_lock_opt = None;
-    let mut raw_sgf = String::new();
-    let mut engine_opt = None;
+    let mut position = Vec::new();
+    let mut board_size = 19;
+    let mut komi = 7.5;
     let mut to_play = review::StoneColor::Black;
+    let visit_limit = payload.max_visits.unwrap_or(400).clamp(50, 5000);
+    let mut query = engine::analysis::AnalysisQuery::default();
 
     {
         let mut review_entry = match state.review_store.get_mut(&payload.review_id) {
@@ -1295,13 +3388,15 @@ async fn review_analyze(
             cached = Some(existing.clone());
         } else {
             analysis_lock_opt = Some(review_entry.analysis_lock.clone());
-            raw_sgf = review_entry.raw_sgf.clone();
-            engine_opt = review_entry.engine.clone();
+            position = review_entry.position_moves(move_index_usize);
+            board_size = review_entry.board_size;
+            komi = review_entry.komi;
             to_play = next_player_to_move(
                 &review_entry.initial_setup,
                 &review_entry.moves,
                 move_index_usize,
             );
+            query = review_entry.analysis_query(payload.move_index, visit_limit);
         }
     }
 
@@ -1328,116 +3423,826 @@ async fn review_analyze(
         }
     };
 
-    let mut engine = engine_opt;
-    if engine.is_none() {
-        match start_review_engine().await {
-            Ok(new_engine) => {
-                engine = Some(new_engine.clone());
-                if let Some(mut entry) = state.review_store.get_mut(&payload.review_id) {
-                    if entry.sid == sid {
-                        entry.engine = Some(new_engine);
-                    }
+    // 优先使用 analysis 模式（JSON 查询，多局面并发）；未配置时回退到对局引擎逐手摆放局面后分析
+    let result = if let Some(spec) = analysis_spec() {
+        analyze_with_analysis_engine(&state, &spec, &query).await
+    } else {
+        let guard = analysis_lock.lock().await;
+        let result = analyze_with_engine(
+            &state,
+            &payload.review_id,
+            &position,
+            engine::pool::BoardSetup { board_size, komi },
+            to_play,
+            visit_limit,
+        )
+        .await;
+        drop(guard);
+        result
+    };
+    let analysis = match result {
+        Ok(a) => a,
+        Err((status, code, detail)) => return error_response(status, code, detail, set_cookie),
+    };
+
+    // 回写缓存
+    if let Some(mut entry) = state.review_store.get_mut(&payload.review_id)
+        && entry.sid == sid
+    {
+        entry
+            .analysis_cache
+            .insert(payload.move_index, analysis.clone());
+        entry.touch();
+    }
+
+    let response = ReviewAnalyzeResponse {
+        review_id: payload.review_id,
+        move_index: payload.move_index,
+        analysis,
+    };
+    with_cookie((StatusCode::OK, Json(response)).into_response(), set_cookie)
+}
+
+// 流式分析单次最长时长
+const ANALYZE_STREAM_MAX_SECONDS: u64 = 60;
+
+// SSE 流式分析：随搜索加深持续推送 analysis 事件；达到访问数上限、超时或客户端断开时
+// 结束分析，最后推送 done 事件并写入分析缓存
+async fn review_analyze_stream(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ReviewAnalyzeStreamQuery>,
+) -> Response {
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
+    let move_index_usize = query.move_index as usize;
+
+    let (position, board_size, komi, to_play) = {
+        let mut review_entry = match state.review_store.get_mut(&query.review_id) {
+            Some(entry) => entry,
+            None => {
+                return error_response(StatusCode::NOT_FOUND, "REVIEW_NOT_FOUND", None, set_cookie);
+            }
+        };
+        if review_entry.sid != sid {
+            return error_response(StatusCode::FORBIDDEN, "REVIEW_NOT_OWNED", None, set_cookie);
+        }
+        if move_index_usize > review_entry.moves.len() {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "MOVE_INDEX_OUT_OF_RANGE",
+                None,
+                set_cookie,
+            );
+        }
+        review_entry.touch();
+        (
+            review_entry.position_moves(move_index_usize),
+            review_entry.board_size,
+            review_entry.komi,
+            next_player_to_move(
+                &review_entry.initial_setup,
+                &review_entry.moves,
+                move_index_usize,
+            ),
+        )
+    };
+
+    let setup = engine::pool::BoardSetup { board_size, komi };
+    let owner = format!("review:{}", query.review_id);
+    let engine = match acquire_engine(
+        &state,
+        &state.profiles.review_profile().name,
+        "chinese",
+        &[],
+        setup,
+        owner,
+    )
+    .await
+    {
+        Ok(engine) => engine,
+        Err(engine::pool::PoolError::Busy(_)) => {
+            return error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "ENGINE_BUSY",
+                None,
+                set_cookie,
+            );
+        }
+        Err(err) => {
+            tracing::warn!(?err, "failed to start review engine");
+            return error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "ENGINE_UNAVAILABLE",
+                None,
+                set_cookie,
+            );
+        }
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Event, Infallible>>(16);
+    let job = AnalyzeStreamJob {
+        state: state.clone(),
+        sid,
+        review_id: query.review_id,
+        position,
+        move_index: query.move_index,
+        to_play,
+        visit_limit: query.max_visits.unwrap_or(5000).clamp(50, 100_000),
+    };
+    tokio::spawn(run_analyze_stream(job, engine, tx));
+
+    let sse =
+        Sse::new(tokio_stream::wrappers::ReceiverStream::new(rx)).keep_alive(KeepAlive::default());
+    with_cookie(sse.into_response(), set_cookie)
+}
+
+struct AnalyzeStreamJob {
+    state: Arc<AppState>,
+    sid: String,
+    review_id: String,
+    position: Vec<(Color, Vertex)>,
+    move_index: u32,
+    to_play: review::StoneColor,
+    visit_limit: u32,
+}
+
+fn sse_json<T: Serialize>(event: &str, data: &T) -> Result<Event, Infallible> {
+    Ok(Event::default()
+        .event(event)
+        .json_data(data)
+        .unwrap_or_else(|_| Event::default().event(event)))
+}
+
+async fn run_analyze_stream(
+    job: AnalyzeStreamJob,
+    engine: Arc<dyn GoEngine>,
+    tx: tokio::sync::mpsc::Sender<Result<Event, Infallible>>,
+) {
+    if let Err(err) = setup_position(engine.as_ref(), &job.position).await {
+        tracing::warn!(?err, "failed to prepare review position");
+        let _ = tx
+            .send(sse_json(
+                "error",
+                &serde_json::json!({"error":"FAILED_TO_PREPARE_POSITION"}),
+            ))
+            .await;
+        return;
+    }
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(ANALYZE_STREAM_MAX_SECONDS);
+    let mut on_update = |infos: &[AnalyzeInfo]| {
+        if let Some(best) = infos.first() {
+            // 客户端消费不及时就丢弃中间结果，只保证最新一条
+            let _ = tx.try_send(sse_json("analysis", &review::KataAnalysis::from_info(best)));
+        }
+        tokio::time::Instant::now() < deadline
+    };
+    // 客户端断开即放弃分析（引擎在下一条命令前自行恢复协议同步）
+    let result = tokio::select! {
+        result = engine.analyze(job.to_play.into(), job.visit_limit, &mut on_update) => result,
+        _ = tx.closed() => return,
+    };
+    drop(engine);
+
+    let infos = match result {
+        Ok(infos) => infos,
+        Err(err) => {
+            tracing::warn!(?err, "streaming analysis failed");
+            let _ = tx
+                .send(sse_json(
+                    "error",
+                    &serde_json::json!({"error":"ENGINE_ANALYZE_FAILED"}),
+                ))
+                .await;
+            return;
+        }
+    };
+    let Some(analysis) = infos.first().map(review::KataAnalysis::from_info) else {
+        return;
+    };
+    if let Some(mut entry) = job.state.review_store.get_mut(&job.review_id)
+        && entry.sid == job.sid
+    {
+        entry
+            .analysis_cache
+            .insert(job.move_index, analysis.clone());
+        entry.touch();
+    }
+    let _ = tx
+        .send(sse_json(
+            "done",
+            &ReviewAnalyzeResponse {
+                review_id: job.review_id,
+                move_index: job.move_index,
+                analysis,
+            },
+        ))
+        .await;
+}
+
+// 同时进行的表演赛上限：每场占用两个引擎
+const MAX_LIVE_EXHIBITIONS: usize = 2;
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NewExhibitionRequest {
+    black_profile: Option<String>, // 档位名，优先于 blackLevel
+    black_level: Option<u8>,
+    white_profile: Option<String>,
+    white_level: Option<u8>,
+    board_size: Option<u32>,
+    rules: Option<String>,
+    komi: Option<f32>,
+    move_interval_ms: Option<u64>, // 每手之间的停顿，便于观看；默认 1000
+    max_moves: Option<usize>,      // 手数上限，达到后按当前局面数子；默认 400
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExhibitionQuery {
+    exhibition_id: String,
+}
+
+// 开一场 AI 对 AI 表演赛：双方各租借一个引擎，由后台任务轮流要棋直到终局
+async fn exhibition_new(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<NewExhibitionRequest>,
+) -> Response {
+    // 每场表演赛占用两个引擎：只有管理员可以开赛，观战不受限
+    if let Err(resp) = check_admin(&state, &headers) {
+        return resp.into_response();
+    }
+    let board_size = req.board_size.unwrap_or(19);
+    if !game::board::BOARD_SIZES.contains(&board_size) {
+        return error_response(StatusCode::BAD_REQUEST, "INVALID_BOARD_SIZE", None, None);
+    }
+    let live = state
+        .exhibitions
+        .iter()
+        .filter(|ex| !ex.is_over() && !ex.calibration)
+        .count();
+    if live >= MAX_LIVE_EXHIBITIONS {
+        return error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            "EXHIBITION_LIMIT",
+            None,
+            None,
+        );
+    }
+    let entrant = |name: Option<&str>, level: Option<u8>| {
+        let profile = match (name, level) {
+            (Some(name), _) => state.profiles.get(name),
+            (None, Some(level)) => state.profiles.by_level(level),
+            (None, None) => Some(state.profiles.default_profile()),
+        }?;
+        Some(game::exhibition::Entrant::new(
+            &profile.name,
+            state.profiles.level_of(&profile.name),
+        ))
+    };
+    let (Some(black), Some(white)) = (
+        entrant(req.black_profile.as_deref(), req.black_level),
+        entrant(req.white_profile.as_deref(), req.white_level),
+    ) else {
+        return error_response(StatusCode::BAD_REQUEST, "UNKNOWN_PROFILE", None, None);
+    };
+    let rules = req.rules.unwrap_or_else(|| "chinese".to_string());
+    let komi = if rules.eq_ignore_ascii_case("chinese") {
+        7.5
+    } else {
+        req.komi.unwrap_or(6.5)
+    };
+
+    let (view, engines) =
+        match open_exhibition(&state, black, white, &rules, board_size, komi, false).await {
+            Ok(opened) => opened,
+            Err(resp) => return resp,
+        };
+    tokio::spawn(run_exhibition(
+        state.clone(),
+        view.exhibition_id.clone(),
+        engines,
+        Duration::from_millis(req.move_interval_ms.unwrap_or(1000).min(10_000)),
+        req.max_moves.unwrap_or(400).max(1),
+    ));
+    (StatusCode::CREATED, Json(view)).into_response()
+}
+
+/// 为表演赛双方各租借一个引擎并登记；返回整盘状态与 [执黑, 执白] 引擎，由调用方驱动对局。
+/// calibration 标记难度校准的对局，不计入表演赛名额
+async fn open_exhibition(
+    state: &AppState,
+    black: game::exhibition::Entrant,
+    white: game::exhibition::Entrant,
+    rules: &str,
+    board_size: u32,
+    komi: f32,
+    calibration: bool,
+) -> Result<(game::exhibition::ExhibitionView, [Arc<dyn GoEngine>; 2]), Response> {
+    let exhibition_id = format!("x-{}", uuid::Uuid::new_v4());
+    let setup = engine::pool::BoardSetup { board_size, komi };
+    let mut engines = Vec::new();
+    for (color, entrant) in [("black", &black), ("white", &white)] {
+        let owner = format!("exhibition:{exhibition_id}:{color}");
+        match acquire_engine(state, &entrant.profile, rules, &[], setup, owner).await {
+            Ok(engine) => engines.push(engine),
+            Err(engine::pool::PoolError::Busy(_)) => {
+                return Err(error_response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "ENGINE_BUSY",
+                    None,
+                    None,
+                ));
+            }
+            Err(err) => {
+                tracing::warn!(?err, "failed to start exhibition engine");
+                return Err(error_response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "ENGINE_UNAVAILABLE",
+                    None,
+                    None,
+                ));
+            }
+        }
+    }
+    let (Some(white_engine), Some(black_engine)) = (engines.pop(), engines.pop()) else {
+        return Err(engine_unavailable_response().into_response());
+    };
+
+    let mut exhibition =
+        game::exhibition::Exhibition::new(black, white, rules, board_size, komi, now_unix());
+    exhibition.calibration = calibration;
+    let view = exhibition.view(&exhibition_id);
+    state.exhibitions.insert(exhibition_id.clone(), exhibition);
+    tracing::info!(
+        exhibition_id,
+        black = %view.black.profile,
+        white = %view.white.profile,
+        "exhibition started"
+    );
+    Ok((view, [black_engine, white_engine]))
+}
+
+/// 表演赛主循环：轮到的一方 genmove，着法落到权威棋盘后再告知另一方引擎；
+/// 双方连续 pass 或达到手数上限时由执黑引擎数子。引擎崩溃时同对局一样按棋盘重建该方引擎
+/// （genmove、数子重试一次）。表演赛被清理后循环随之结束，引擎归还进程池
+async fn run_exhibition(
+    state: Arc<AppState>,
+    id: String,
+    engines: [Arc<dyn GoEngine>; 2],
+    interval: Duration,
+    max_moves: usize,
+) {
+    // [执黑, 执白]；重建时先放下已死的引擎，租约才会归还进程池
+    let mut engines = engines.map(Some);
+    let side = |color: Color| match color {
+        Color::Black => 0,
+        Color::White => 1,
+    };
+    loop {
+        let Some(color) = state
+            .exhibitions
+            .get(&id)
+            .filter(|ex| !ex.is_over())
+            .map(|ex| ex.board.to_move())
+        else {
+            return;
+        };
+        let mover = &mut engines[side(color)];
+        let genmove = exhibition_call(&state, &id, color, mover, |e| async move {
+            e.genmove(color).await
+        });
+        let vertex = match genmove.await {
+            Ok(GenMove::Play(vertex)) => vertex,
+            Ok(GenMove::Resign) => {
+                if let Some(mut ex) = state.exhibitions.get_mut(&id) {
+                    ex.finish(game::GameEnd::resignation(color, now_unix()));
                 }
+                return;
             }
             Err(err) => {
-                tracing::warn!(?err, "failed to start review engine");
-                return with_cookie(
-                    (
-                        StatusCode::SERVICE_UNAVAILABLE,
-                        Json(serde_json::json!({"error":"ENGINE_UNAVAILABLE"})),
-                    )
-                        .into_response(),
-                    set_cookie,
-                );
+                tracing::warn!(?err, exhibition_id = %id, "exhibition genmove failed");
+                abort_exhibition(&state, &id, "ENGINE_FAILED");
+                return;
+            }
+        };
+        let (ended_by_passes, move_count) = {
+            let Some(mut ex) = state.exhibitions.get_mut(&id) else {
+                return;
+            };
+            if let Err(err) = ex.play(color, vertex) {
+                tracing::warn!(?err, %vertex, exhibition_id = %id, "exhibition move rejected by board");
+                drop(ex);
+                abort_exhibition(&state, &id, err.code());
+                return;
+            }
+            (ex.board.ended_by_passes(), ex.board.moves().len())
+        };
+        let other = &mut engines[side(color.opponent())];
+        let told = match other.clone() {
+            Some(engine) => engine.play(color, vertex).await,
+            None => Err(engine_unavailable()),
+        };
+        if let Err(err) = told {
+            tracing::warn!(?err, exhibition_id = %id, "exhibition engine rejected move");
+            // 按棋盘重建的引擎已含这一手，不再重试
+            if err.is_fatal() {
+                *other = None;
+                *other = rebuild_exhibition_engine(&state, &id, color.opponent()).await;
+            }
+            if !err.is_fatal() || other.is_none() {
+                abort_exhibition(&state, &id, "ENGINE_FAILED");
+                return;
             }
         }
+        if ended_by_passes || move_count >= max_moves {
+            let black = &mut engines[side(Color::Black)];
+            let dead = exhibition_call(&state, &id, Color::Black, black, |e| async move {
+                e.dead_stones().await
+            });
+            let dead = match dead.await {
+                Ok(vertices) => vertex_strings(&vertices),
+                Err(err) => {
+                    tracing::warn!(?err, "failed to list dead stones");
+                    Vec::new()
+                }
+            };
+            let score = exhibition_call(&state, &id, Color::Black, black, |e| async move {
+                e.final_score().await
+            })
+            .await
+            .ok();
+            let end = if ended_by_passes {
+                game::GameEnd::scored(score, dead, now_unix())
+            } else {
+                game::GameEnd::move_limit(score, dead, now_unix())
+            };
+            if let Some(mut ex) = state.exhibitions.get_mut(&id) {
+                ex.finish(end);
+            }
+            tracing::info!(exhibition_id = %id, move_count, "exhibition finished");
+            return;
+        }
+        tokio::time::sleep(interval).await;
     }
+}
 
-    let engine = match engine {
-        Some(e) => e,
-        None => {
-            return with_cookie(
-                (
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(serde_json::json!({"error":"ENGINE_UNAVAILABLE"})),
-                )
-                    .into_response(),
-                set_cookie,
+/// 表演赛一方引擎的调用：进程退出或管道断开时按棋盘重建该方引擎后重试一次（同 game_call）
+async fn exhibition_call<T, F, Fut>(
+    state: &AppState,
+    id: &str,
+    color: Color,
+    engine: &mut Option<Arc<dyn GoEngine>>,
+    op: F,
+) -> Result<T, engine::gtp::EngineError>
+where
+    F: Fn(Arc<dyn GoEngine>) -> Fut,
+    Fut: std::future::Future<Output = Result<T, engine::gtp::EngineError>>,
+{
+    let Some(current) = engine.clone() else {
+        return Err(engine_unavailable());
+    };
+    match op(current).await {
+        Err(err) if err.is_fatal() => {
+            tracing::warn!(?err, exhibition_id = id, ?color, "exhibition engine died");
+            *engine = None;
+            *engine = rebuild_exhibition_engine(state, id, color).await;
+            match engine.clone() {
+                Some(fresh) => op(fresh).await,
+                None => Err(err),
+            }
+        }
+        other => other,
+    }
+}
+
+/// 为表演赛 color 一方重新租借引擎并重放棋盘上的着法；表演赛已不存在或重建失败时返回 None
+async fn rebuild_exhibition_engine(
+    state: &AppState,
+    id: &str,
+    color: Color,
+) -> Option<Arc<dyn GoEngine>> {
+    let (profile, rules, setup, moves) = {
+        let ex = state.exhibitions.get(id)?;
+        let entrant = match color {
+            Color::Black => &ex.black,
+            Color::White => &ex.white,
+        };
+        let setup = engine::pool::BoardSetup {
+            board_size: ex.board_size,
+            komi: ex.komi,
+        };
+        (
+            entrant.profile.clone(),
+            ex.rules.clone(),
+            setup,
+            ex.board.move_list(),
+        )
+    };
+    let owner = format!("exhibition:{id}:{}", color_name(color));
+    let engine = match acquire_engine(state, &profile, &rules, &[], setup, owner).await {
+        Ok(engine) => engine,
+        Err(err) => {
+            tracing::error!(
+                ?err,
+                exhibition_id = id,
+                "failed to restart exhibition engine"
             );
+            return None;
         }
     };
+    for (color, vertex) in &moves {
+        if let Err(err) = engine.play(*color, *vertex).await {
+            tracing::error!(
+                ?err,
+                exhibition_id = id,
+                "failed to replay moves into restarted exhibition engine"
+            );
+            return None;
+        }
+    }
+    tracing::info!(
+        exhibition_id = id,
+        replayed = moves.len(),
+        "exhibition engine rebuilt"
+    );
+    Some(engine)
+}
 
-    let visit_limit = payload.max_visits.unwrap_or(400).clamp(50, 5000);
+fn abort_exhibition(state: &AppState, id: &str, error: &str) {
+    if let Some(mut ex) = state.exhibitions.get_mut(id) {
+        ex.abort(error, now_unix());
+    }
+}
 
-    let guard = analysis_lock.lock().await;
-    let prepare_result =
-        load_review_position(&engine, &payload.review_id, &raw_sgf, payload.move_index).await;
-    if let Err(err) = prepare_result {
-        tracing::warn!(?err, "failed to prepare review position");
-        drop(guard);
-        return with_cookie(
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error":"FAILED_TO_PREPARE_POSITION"})),
-            )
-                .into_response(),
-            set_cookie,
+async fn exhibition_list(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut items: Vec<_> = state
+        .exhibitions
+        .iter()
+        .map(|ex| ex.summary(ex.key()))
+        .collect();
+    items.sort_by_key(|item| std::cmp::Reverse(item.started_at));
+    Json(serde_json::json!({ "items": items }))
+}
+
+// SSE 观战：先推送 state（整盘状态），之后逐手推送 move，终局推送 end 后关闭；
+// 无需登录，任何浏览器都可收看
+async fn exhibition_stream(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ExhibitionQuery>,
+) -> Response {
+    let id = query.exhibition_id;
+    let Some((view, mut events)) = state.exhibitions.get(&id).map(|ex| ex.subscribe(&id)) else {
+        return error_response(StatusCode::NOT_FOUND, "EXHIBITION_NOT_FOUND", None, None);
+    };
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Event, Infallible>>(16);
+    tokio::spawn(async move {
+        let mut finished = view.outcome.is_some();
+        if tx.send(sse_json("state", &view)).await.is_err() {
+            return;
+        }
+        while !finished {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = tx.closed() => return,
+            };
+            let sent = match event {
+                Ok(game::exhibition::ExhibitionEvent::Move(mv)) => sse_json("move", &mv),
+                Ok(game::exhibition::ExhibitionEvent::End(outcome)) => {
+                    finished = true;
+                    sse_json("end", &outcome)
+                }
+                // 观众跟不上时丢弃积压的事件，改发一次整盘状态
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                    let Some(view) = state.exhibitions.get(&id).map(|ex| ex.view(&id)) else {
+                        return;
+                    };
+                    finished = view.outcome.is_some();
+                    sse_json("state", &view)
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+            };
+            if tx.send(sent).await.is_err() {
+                return;
+            }
+        }
+    });
+    Sse::new(tokio_stream::wrappers::ReceiverStream::new(rx))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+// 下载表演赛棋谱；进行中的表演赛给出截至当前的着法
+async fn exhibition_sgf(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ExhibitionQuery>,
+) -> Response {
+    let Some(sgf) = state
+        .exhibitions
+        .get(&query.exhibition_id)
+        .map(|ex| ex.sgf())
+    else {
+        return error_response(StatusCode::NOT_FOUND, "EXHIBITION_NOT_FOUND", None, None);
+    };
+    let disposition = format!("attachment; filename=\"{}.sgf\"", query.exhibition_id);
+    let mut resp = sgf.into_response();
+    resp.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-go-sgf; charset=utf-8"),
+    );
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        resp.headers_mut()
+            .insert(axum::http::header::CONTENT_DISPOSITION, value);
+    }
+    resp
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CalibrationRequest {
+    profiles: Option<Vec<String>>, // 参与校准的档位名，默认全部档位
+    games_per_pair: Option<u32>,   // 每对档位的局数（双方轮流执黑），默认 10
+    board_size: Option<u32>,
+    max_moves: Option<usize>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CalibrationQuery {
+    run_id: Option<String>, // 只统计某一轮；默认统计全部历史结果
+}
+
+// 单轮校准的局数上限
+const CALIBRATION_MAX_GAMES: usize = 1000;
+
+// 管理接口：开始一轮难度校准，后台让每对档位对弈若干局，结果逐局写入 CALIBRATION_DIR
+async fn calibration_start(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<CalibrationRequest>,
+) -> Response {
+    if let Err(resp) = check_admin(&state, &headers) {
+        return resp.into_response();
+    }
+    let board_size = req.board_size.unwrap_or(19);
+    if !game::board::BOARD_SIZES.contains(&board_size) {
+        return error_response(StatusCode::BAD_REQUEST, "INVALID_BOARD_SIZE", None, None);
+    }
+    let profiles = req.profiles.unwrap_or_else(|| state.profiles.names());
+    if profiles.iter().any(|p| state.profiles.get(p).is_none()) {
+        return error_response(StatusCode::BAD_REQUEST, "UNKNOWN_PROFILE", None, None);
+    }
+    let games_per_pair = req.games_per_pair.unwrap_or(10).max(1);
+    let pairs = profiles.len() * profiles.len().saturating_sub(1) / 2;
+    let total = pairs * games_per_pair as usize;
+    let distinct: std::collections::HashSet<_> = profiles.iter().collect();
+    if pairs == 0 || distinct.len() != profiles.len() || total > CALIBRATION_MAX_GAMES {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "INVALID_CALIBRATION",
+            Some(format!(
+                "need at least 2 distinct profiles and at most {CALIBRATION_MAX_GAMES} games"
+            )),
+            None,
         );
     }
-
-    let color_char = match to_play {
-        review::StoneColor::Black => 'B',
-        review::StoneColor::White => 'W',
-    };
-    let cmd = format!("kata-analyze {} {}", color_char, visit_limit);
-    let raw = match engine.send_command(&cmd).await {
-        Ok(text) => text,
-        Err(err) => {
-            tracing::warn!(?err, "kata-analyze command failed");
-            drop(guard);
-            return with_cookie(
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(serde_json::json!({"error":"ENGINE_ANALYZE_FAILED"})),
-                )
-                    .into_response(),
-                set_cookie,
-            );
-        }
+    let progress = game::calibration::CalibrationProgress {
+        run_id: format!("c-{}", uuid::Uuid::new_v4()),
+        profiles,
+        games_per_pair,
+        total,
+        played: 0,
+        failed: 0,
+        started_at: now_unix(),
+        finished_at: None,
     };
-    drop(guard);
+    if !state.calibration.try_start(progress.clone()) {
+        return error_response(StatusCode::CONFLICT, "CALIBRATION_RUNNING", None, None);
+    }
+    tracing::info!(run_id = %progress.run_id, total, "calibration started");
+    tokio::spawn(run_calibration(
+        state.clone(),
+        progress.clone(),
+        board_size,
+        req.max_moves.unwrap_or(400).max(1),
+    ));
+    (StatusCode::ACCEPTED, Json(progress)).into_response()
+}
 
-    let analysis = match parse_kata_analyze(&raw) {
-        Some(a) => a,
-        None => {
-            tracing::warn!(raw, "kata-analyze response unparseable");
-            return with_cookie(
-                (
-                    StatusCode::BAD_GATEWAY,
-                    Json(serde_json::json!({"error":"ENGINE_ANALYZE_UNPARSEABLE"})),
+/// 逐局进行校准：对局作为表演赛登记（可在观战页收看），双方交替执黑，不间隔
+async fn run_calibration(
+    state: Arc<AppState>,
+    progress: game::calibration::CalibrationProgress,
+    board_size: u32,
+    max_moves: usize,
+) {
+    let profiles = &progress.profiles;
+    for (i, first) in profiles.iter().enumerate() {
+        for second in &profiles[i + 1..] {
+            for round in 0..progress.games_per_pair {
+                let (black, white) = if round % 2 == 0 {
+                    (first, second)
+                } else {
+                    (second, first)
+                };
+                let entrant = |name: &str| {
+                    game::exhibition::Entrant::new(name, state.profiles.level_of(name))
+                };
+                let opened = open_exhibition(
+                    &state,
+                    entrant(black),
+                    entrant(white),
+                    "chinese",
+                    board_size,
+                    7.5,
+                    true,
                 )
-                    .into_response(),
-                set_cookie,
-            );
-        }
-    };
-
-    // 回写缓存
-    if let Some(mut entry) = state.review_store.get_mut(&payload.review_id) {
-        if entry.sid == sid {
-            entry
-                .analysis_cache
-                .insert(payload.move_index, analysis.clone());
-            entry.touch();
+                .await;
+                let game = match opened {
+                    Ok((view, engines)) => {
+                        let id = view.exhibition_id;
+                        run_exhibition(
+                            state.clone(),
+                            id.clone(),
+                            engines,
+                            Duration::ZERO,
+                            max_moves,
+                        )
+                        .await;
+                        state.exhibitions.get(&id).and_then(|ex| {
+                            let end = ex.outcome()?.end.clone()?;
+                            Some(game::calibration::CalibrationGame {
+                                run_id: progress.run_id.clone(),
+                                black: black.clone(),
+                                white: white.clone(),
+                                board_size,
+                                result: end.result,
+                                winner: end.winner,
+                                reason: Some(end.reason),
+                                moves: ex.board.moves().len(),
+                                ended_at: end.ended_at,
+                            })
+                        })
+                    }
+                    Err(resp) => {
+                        tracing::warn!(status = %resp.status(), "calibration game could not start");
+                        None
+                    }
+                };
+                let recorded = match &game {
+                    Some(game) => match state.calibration.append(game).await {
+                        Ok(()) => true,
+                        Err(err) => {
+                            tracing::error!(?err, "failed to record calibration game");
+                            false
+                        }
+                    },
+                    None => false,
+                };
+                state.calibration.update(|p| {
+                    if recorded {
+                        p.played += 1;
+                    } else {
+                        p.failed += 1;
+                    }
+                });
+            }
         }
     }
+    state
+        .calibration
+        .update(|p| p.finished_at = Some(now_unix()));
+    tracing::info!(run_id = %progress.run_id, "calibration finished");
+}
 
-    let response = ReviewAnalyzeResponse {
-        review_id: payload.review_id,
-        move_index: payload.move_index,
-        analysis,
+// 管理接口：最近一轮的进度，以及按已记录结果估计的各档位 Elo（含 95% 置信区间）
+async fn calibration_status(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<CalibrationQuery>,
+) -> Response {
+    if let Err(resp) = check_admin(&state, &headers) {
+        return resp.into_response();
+    }
+    let games = match state.calibration.load(query.run_id.as_deref()).await {
+        Ok(games) => games,
+        Err(err) => {
+            tracing::error!(?err, "failed to read calibration results");
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "CALIBRATION_READ_FAILED",
+                None,
+                None,
+            );
+        }
     };
-    with_cookie((StatusCode::OK, Json(response)).into_response(), set_cookie)
+    let body = serde_json::json!({
+        "progress": state.calibration.progress(),
+        "games": games.len(),
+        "ratings": game::calibration::elo_ratings(&state.profiles.names(), &games),
+    });
+    (StatusCode::OK, Json(body)).into_response()
 }
 
 async fn exercise_save(
@@ -1445,7 +4250,7 @@ async fn exercise_save(
     headers: HeaderMap,
     Json(payload): Json<ExerciseSaveRequest>,
 ) -> impl IntoResponse {
-    let (sid, set_cookie) = get_or_create_sid(headers);
+    let (sid, set_cookie) = get_or_create_sid(&state.session_keys, headers);
     let include_raw_sgf = payload.include_raw_sgf.unwrap_or(true);
 
     let answer_request = match payload.answer {
@@ -1747,10 +4552,10 @@ fn error_response(
     set_cookie: Option<HeaderValue>,
 ) -> Response {
     let mut body = serde_json::json!({"error": code});
-    if let Some(detail_text) = detail {
-        if let Some(obj) = body.as_object_mut() {
-            obj.insert("detail".to_string(), detail_text.into());
-        }
+    if let Some(detail_text) = detail
+        && let Some(obj) = body.as_object_mut()
+    {
+        obj.insert("detail".to_string(), detail_text.into());
     }
     with_cookie((status, Json(body)).into_response(), set_cookie)
 }
@@ -1807,217 +4612,1862 @@ fn normalise_user_sequence(
             }
         }
     }
-    Ok(result)
-}
+    Ok(result)
+}
+
+fn normalise_user_move(value: &str, board_size: u32) -> Result<String, String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Err("value is empty".to_string());
+    }
+    if trimmed.eq_ignore_ascii_case("pass") {
+        return Ok("pass".to_string());
+    }
+    if trimmed.len() != 2 {
+        return Err(format!(
+            "\"{}\" should be a two-letter SGF coordinate or 'pass'",
+            trimmed
+        ));
+    }
+    let lower = trimmed.to_ascii_lowercase();
+    let bytes = lower.as_bytes();
+    if !bytes.iter().all(|b| b.is_ascii_lowercase()) {
+        return Err(format!("\"{}\" must use letters a-z", trimmed));
+    }
+    let size = board_size as u8;
+    let x = bytes[0];
+    let y = bytes[1];
+    if x < b'a' || y < b'a' || x >= b'a' + size || y >= b'a' + size {
+        return Err(format!(
+            "\"{}\" outside board (size {})",
+            trimmed, board_size
+        ));
+    }
+    Ok(lower)
+}
+
+fn move_node_to_answer_coord(node: &review::MoveNode) -> String {
+    node.coord.clone().unwrap_or_else(|| "pass".to_string())
+}
+
+fn with_cookie(resp: Response, set_cookie: Option<HeaderValue>) -> Response {
+    let mut response = resp;
+    if let Some(val) = set_cookie {
+        response.headers_mut().insert(SET_COOKIE, val);
+    }
+    response
+}
+
+async fn fetch_remote_sgf(url: &str) -> Result<Vec<u8>, String> {
+    let uri: Uri = url.parse().map_err(|_| "INVALID_SOURCE_URL".to_string())?;
+    if uri.scheme_str() != Some("https") {
+        return Err("REMOTE_URL_NOT_HTTPS".to_string());
+    }
+    let host = uri
+        .host()
+        .ok_or_else(|| "REMOTE_URL_HOST_REQUIRED".to_string())?;
+    if !remote_host_allowed(host) {
+        return Err("REMOTE_HOST_NOT_ALLOWED".to_string());
+    }
+
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|err| {
+            tracing::error!(?err, "failed to build reqwest client");
+            "REMOTE_FETCH_FAILED".to_string()
+        })?;
+
+    let resp = client.get(url).send().await.map_err(|err| {
+        tracing::warn!(?err, "failed to download remote sgf");
+        "REMOTE_FETCH_FAILED".to_string()
+    })?;
+
+    if !resp.status().is_success() {
+        tracing::warn!(status=?resp.status(), "remote sgf returned non-200");
+        return Err("REMOTE_FETCH_FAILED".to_string());
+    }
+
+    let bytes = resp.bytes().await.map_err(|err| {
+        tracing::warn!(?err, "failed to read remote sgf body");
+        "REMOTE_FETCH_FAILED".to_string()
+    })?;
+    if bytes.len() > MAX_SGF_BYTES {
+        return Err("SGF_TOO_LARGE".to_string());
+    }
+    Ok(bytes.to_vec())
+}
+
+fn remote_host_allowed(host: &str) -> bool {
+    match std::env::var("REVIEW_IMPORT_HOST_WHITELIST") {
+        Ok(list) => {
+            let trimmed = list.trim();
+            if trimmed.is_empty() {
+                return true;
+            }
+            trimmed
+                .split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        }
+        Err(_) => true,
+    }
+}
+
+/// analysis 模式启动参数：需在 ENGINE_PATH/MODEL_PATH 之外配置 ANALYSIS_CONFIG_PATH
+fn analysis_spec() -> Option<engine::pool::EngineSpec> {
+    let engine_path = std::env::var("ENGINE_PATH").ok()?;
+    let model_path = std::env::var("MODEL_PATH").ok()?;
+    let config_path = std::env::var("ANALYSIS_CONFIG_PATH").ok()?;
+    Some(engine::pool::EngineSpec {
+        program: engine_path,
+        args: vec![
+            "analysis".to_string(),
+            "-model".to_string(),
+            model_path,
+            "-config".to_string(),
+            config_path,
+            // 与 kata-analyze 保持一致：胜率以行棋方视角给出
+            "-override-config".to_string(),
+            "reportAnalysisWinratesAs=SIDETOMOVE".to_string(),
+        ],
+    })
+}
+
+async fn shared_analysis_engine(
+    state: &AppState,
+    spec: &engine::pool::EngineSpec,
+) -> anyhow::Result<Arc<engine::analysis::AnalysisEngine>> {
+    let mut slot = state.analysis_engine.lock().await;
+    if let Some(engine) = slot.as_ref() {
+        return Ok(engine.clone());
+    }
+    let engine = engine::analysis::AnalysisEngine::start(&spec.program, &spec.args).await?;
+    *slot = Some(engine.clone());
+    Ok(engine)
+}
+
+async fn analyze_with_analysis_engine(
+    state: &AppState,
+    spec: &engine::pool::EngineSpec,
+    query: &engine::analysis::AnalysisQuery,
+) -> Result<review::KataAnalysis, SaveError> {
+    let engine = shared_analysis_engine(state, spec).await.map_err(|err| {
+        tracing::warn!(?err, "failed to start analysis engine");
+        (StatusCode::SERVICE_UNAVAILABLE, "ENGINE_UNAVAILABLE", None)
+    })?;
+    match engine.analyze(query).await {
+        Ok(results) => results
+            .last()
+            .map(review::KataAnalysis::from_response)
+            .ok_or((StatusCode::BAD_GATEWAY, "ENGINE_ANALYZE_UNPARSEABLE", None)),
+        Err(engine::analysis::AnalysisError::Rejected(msg)) => {
+            tracing::warn!(msg, "analysis query rejected");
+            Err((StatusCode::BAD_REQUEST, "ENGINE_ANALYZE_FAILED", Some(msg)))
+        }
+        Err(err) => {
+            tracing::warn!(?err, pid = ?engine.pid(), "analysis engine failed");
+            let mut slot = state.analysis_engine.lock().await;
+            if slot.as_ref().is_some_and(|e| Arc::ptr_eq(e, &engine)) {
+                *slot = None;
+            }
+            Err((StatusCode::SERVICE_UNAVAILABLE, "ENGINE_UNAVAILABLE", None))
+        }
+    }
+}
+
+// 每次分析临时取得引擎并逐手摆出局面，分析结束即归还进程池
+async fn analyze_with_engine(
+    state: &AppState,
+    review_id: &str,
+    position: &[(Color, Vertex)],
+    setup: engine::pool::BoardSetup,
+    to_play: review::StoneColor,
+    visit_limit: u32,
+) -> Result<review::KataAnalysis, SaveError> {
+    let owner = format!("review:{}", review_id);
+    let engine = match acquire_engine(
+        state,
+        &state.profiles.review_profile().name,
+        "chinese",
+        &[],
+        setup,
+        owner,
+    )
+    .await
+    {
+        Ok(engine) => engine,
+        Err(engine::pool::PoolError::Busy(_)) => {
+            return Err((StatusCode::SERVICE_UNAVAILABLE, "ENGINE_BUSY", None));
+        }
+        Err(err) => {
+            tracing::warn!(?err, "failed to start review engine");
+            return Err((StatusCode::SERVICE_UNAVAILABLE, "ENGINE_UNAVAILABLE", None));
+        }
+    };
+
+    if let Err(err) = setup_position(engine.as_ref(), position).await {
+        tracing::warn!(?err, "failed to prepare review position");
+        if let engine::gtp::EngineError::Timeout { .. } = err {
+            return Err((StatusCode::GATEWAY_TIMEOUT, "ENGINE_TIMEOUT", None));
+        }
+        return Err((StatusCode::BAD_REQUEST, "FAILED_TO_PREPARE_POSITION", None));
+    }
+
+    let infos = match engine
+        .analyze(to_play.into(), visit_limit, &mut |_| true)
+        .await
+    {
+        Ok(infos) => infos,
+        Err(err @ engine::gtp::EngineError::Timeout { .. }) => {
+            tracing::warn!(?err, "analysis timed out");
+            return Err((StatusCode::GATEWAY_TIMEOUT, "ENGINE_TIMEOUT", None));
+        }
+        Err(err) => {
+            tracing::warn!(?err, "analysis failed");
+            return Err((StatusCode::BAD_REQUEST, "ENGINE_ANALYZE_FAILED", None));
+        }
+    };
+
+    infos.first().map(review::KataAnalysis::from_info).ok_or((
+        StatusCode::BAD_GATEWAY,
+        "ENGINE_ANALYZE_UNPARSEABLE",
+        None,
+    ))
+}
+
+/// 在刚清盘的引擎上依次落下局面中的每一手
+async fn setup_position(
+    engine: &dyn GoEngine,
+    position: &[(Color, Vertex)],
+) -> Result<(), engine::gtp::EngineError> {
+    for (color, vertex) in position {
+        engine.play(*color, *vertex).await?;
+    }
+    Ok(())
+}
+
+fn next_player_to_move(
+    setup: &review::InitialSetup,
+    moves: &[review::MoveNode],
+    move_index: usize,
+) -> review::StoneColor {
+    if move_index == 0 {
+        return setup.to_play.unwrap_or(review::StoneColor::Black);
+    }
+    moves
+        .get(move_index - 1)
+        .map(|m| m.color.opponent())
+        .unwrap_or_else(|| setup.to_play.unwrap_or(review::StoneColor::Black))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    fn test_state(script: Vec<GenMove>) -> Arc<AppState> {
+        let data_dir = std::env::temp_dir().join(format!("game-data-{}", uuid::Uuid::new_v4()));
+        test_state_in(&data_dir, script)
+    }
+
+    // data_dir 下放归档与快照；同一目录再建一次即模拟服务重启
+    fn test_state_in(data_dir: &Path, script: Vec<GenMove>) -> Arc<AppState> {
+        Arc::new(AppState {
+            concurrency_limit_per_sid: 3,
+            session_store: Arc::new(dashmap::DashMap::new()),
+            game_store: Arc::new(dashmap::DashMap::new()),
+            review_store: Arc::new(dashmap::DashMap::new()),
+            game_ttl_seconds: 1800,
+            review_ttl_seconds: 1800,
+            server_start_at: 0,
+            sid_locks: Arc::new(dashmap::DashMap::new()),
+            engine_pool: engine::pool::EnginePool::new(
+                1,
+                Duration::from_secs(1),
+                engine::gtp::CommandTimeouts::default(),
+            ),
+            analysis_engine: Arc::new(tokio::sync::Mutex::new(None)),
+            engine_restarts: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            admin_token: None,
+            session_keys: Arc::new(session::SessionKeys::new(
+                b"test-secret".to_vec(),
+                Vec::new(),
+                3600,
+                false,
+            )),
+            engine_backend: EngineBackend::Fake { script },
+            profiles: Arc::new(engine::profile::ProfileSet::bundled()),
+            archive: Arc::new(game::archive::GameArchive::open(&data_dir.join("games")).unwrap()),
+            snapshots: Arc::new(
+                game::snapshot::SnapshotStore::open(&data_dir.join("live")).unwrap(),
+            ),
+            exhibitions: Arc::new(dashmap::DashMap::new()),
+            calibration: Arc::new(
+                game::calibration::CalibrationStore::open(&data_dir.join("calibration")).unwrap(),
+            ),
+        })
+    }
+
+    // 发送请求并返回状态码与 JSON 响应体（空响应体为 Null）
+    async fn call(
+        state: &Arc<AppState>,
+        method: Method,
+        uri: &str,
+        content_type: &str,
+        body: impl Into<axum::body::Body>,
+    ) -> (StatusCode, serde_json::Value) {
+        call_as(state, "test-sid", method, uri, content_type, body).await
+    }
+
+    // 以指定 sid 签发的 Cookie 头
+    fn sid_cookie(state: &AppState, sid: &str) -> String {
+        format!("sid={}", state.session_keys.sign(sid, now_unix()))
+    }
+
+    // 以指定 sid 发送请求
+    async fn call_as(
+        state: &Arc<AppState>,
+        sid: &str,
+        method: Method,
+        uri: &str,
+        content_type: &str,
+        body: impl Into<axum::body::Body>,
+    ) -> (StatusCode, serde_json::Value) {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(CONTENT_TYPE, content_type)
+            .header("cookie", sid_cookie(state, sid))
+            .body(body.into())
+            .unwrap();
+        let resp = api_router()
+            .with_state(state.clone())
+            .oneshot(req)
+            .await
+            .unwrap();
+        let status = resp.status();
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        let value = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+        (status, value)
+    }
+
+    async fn post_json(
+        state: &Arc<AppState>,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        call(
+            state,
+            Method::POST,
+            uri,
+            "application/json",
+            body.to_string(),
+        )
+        .await
+    }
+
+    // 带 x-admin-token 的 JSON POST（令牌为 "secret"）
+    async fn post_admin(
+        state: &Arc<AppState>,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(CONTENT_TYPE, "application/json")
+            .header("x-admin-token", "secret")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        let resp = api_router()
+            .with_state(state.clone())
+            .oneshot(req)
+            .await
+            .unwrap();
+        let status = resp.status();
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        let value = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+        (status, value)
+    }
+
+    #[tokio::test]
+    async fn game_flow_runs_on_fake_engine() {
+        let state = test_state(Vec::new());
+        let (status, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+
+        // 假引擎取左上起第一个空点
+        let play = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+        let (status, body) = post_json(&state, "/api/game/play", play.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["engineMove"], "A19");
+
+        let (status, body) = post_json(&state, "/api/game/play", play).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "ILLEGAL_MOVE");
+
+        let id = serde_json::json!({"gameId": game_id});
+        let (status, body) = post_json(&state, "/api/game/hint", id.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["suggestion"], "B19");
+
+        // 提示后已撤销：黑白各一子，白贴 7.5
+        let (status, body) = post_json(&state, "/api/game/score_detail", id.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["result"], "W+7.5");
+
+        let (status, _) = post_json(&state, "/api/game/close", id.clone()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = post_json(&state, "/api/game/hint", id).await;
+        assert_eq!(status, StatusCode::GONE);
+    }
+
+    #[tokio::test]
+    async fn scripted_engine_moves_first_for_white_player() {
+        let q16 = Vertex::Point { col: 15, row: 16 };
+        let state = test_state(vec![GenMove::Play(q16), GenMove::Resign]);
+        let (status, body) = post_json(
+            &state,
+            "/api/game/new",
+            serde_json::json!({"playerColor": "white"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["engineMove"], "Q16");
+
+        let play = serde_json::json!({"gameId": body["gameId"], "playerMove": "D4"});
+        let (status, body) = post_json(&state, "/api/game/play", play).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["engineMove"], "resign");
+    }
+
+    #[tokio::test]
+    async fn new_game_rejects_unsupported_board_sizes() {
+        let state = test_state(Vec::new());
+        for size in [0, 4, 26, 50_000, 70_000] {
+            let body = serde_json::json!({"boardSize": size});
+            let (status, body) = post_json(&state, "/api/game/new", body).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{size}");
+            assert_eq!(body["error"], "INVALID_BOARD_SIZE");
+        }
+        assert!(state.game_store.is_empty());
+        let (status, _) =
+            post_json(&state, "/api/game/new", serde_json::json!({"boardSize": 9})).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn handicap_game_places_stones_and_white_starts() {
+        let state = test_state(Vec::new());
+        let (status, body) = post_json(
+            &state,
+            "/api/game/new",
+            serde_json::json!({"handicap": 10, "boardSize": 19}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_HANDICAP");
+
+        let (status, body) = post_json(
+            &state,
+            "/api/game/new",
+            serde_json::json!({"handicap": 2, "komi": 6.5}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["handicapStones"], serde_json::json!(["D4", "Q16"]));
+        assert_eq!(body["komi"], 0.5);
+        assert_eq!(body["engineMove"], "A19");
+
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = |mv: &str| serde_json::json!({"gameId": game_id, "playerMove": mv});
+        let (status, body) = post_json(&state, "/api/game/play", play("D4")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["reason"], "OCCUPIED");
+        let (status, body) = post_json(&state, "/api/game/play", play("D16")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["toMove"], "black");
+        assert_eq!(body["moveNumber"], 3);
+    }
+
+    #[tokio::test]
+    async fn clock_runs_on_server_and_timeout_ends_game() {
+        let state = test_state(Vec::new());
+        let bad = serde_json::json!({"timeControl": {"kind": "byoyomi", "mainTime": 60, "periodTime": 30, "periods": 0}});
+        let (status, body) = post_json(&state, "/api/game/new", bad).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_TIME_CONTROL");
+
+        let tc = serde_json::json!({"timeControl": {"kind": "fischer", "mainTime": 60, "increment": 10}});
+        let (status, body) = post_json(&state, "/api/game/new", tc).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["clock"]["control"]["kind"], "fischer");
+        assert_eq!(body["clock"]["running"], "black");
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+
+        let play = |mv: &str| serde_json::json!({"gameId": game_id, "playerMove": mv});
+        let (status, body) = post_json(&state, "/api/game/play", play("D4")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["clock"]["running"], "black");
+        assert!(body["clock"]["black"]["mainMs"].as_u64().unwrap() > 60_000);
+
+        // 模拟黑方思考超过剩余时间
+        let long_ago = Instant::now()
+            .checked_sub(Duration::from_secs(120))
+            .unwrap();
+        if let Some(clock) = state.game_store.get_mut(&game_id).unwrap().clock.as_mut() {
+            clock.start(Color::Black, long_ago);
+        }
+        let (status, body) = post_json(&state, "/api/game/play", play("D5")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["end"]["reason"], "timeout");
+        assert_eq!(body["end"]["result"], "W+T");
+
+        let id = serde_json::json!({"gameId": game_id});
+        let (status, body) = post_json(&state, "/api/game/heartbeat", id).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["end"]["finished"], true);
+        assert_eq!(body["clock"]["running"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn game_endpoints_reject_other_sessions() {
+        let state = test_state(Vec::new());
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+        post_json(&state, "/api/game/play", play.clone()).await;
+
+        let id = serde_json::json!({"gameId": game_id});
+        let post_as = |sid: &'static str, uri: &'static str, body: serde_json::Value| {
+            let state = state.clone();
+            async move {
+                call_as(
+                    &state,
+                    sid,
+                    Method::POST,
+                    uri,
+                    "application/json",
+                    body.to_string(),
+                )
+                .await
+            }
+        };
+        let intruder_requests = [
+            ("/api/game/play", play.clone()),
+            ("/api/game/hint", id.clone()),
+            ("/api/game/undo", id.clone()),
+            ("/api/game/heartbeat", id.clone()),
+            ("/api/game/score_detail", id.clone()),
+            ("/api/game/resign", id.clone()),
+            ("/api/game/close", id.clone()),
+            ("/api/game/to_review", id.clone()),
+        ];
+        for (uri, body) in intruder_requests {
+            let (status, body) = post_as("intruder", uri, body).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{uri}");
+            assert_eq!(body["error"], "GAME_NOT_OWNED", "{uri}");
+        }
+        let sgf_uri = format!("/api/game/sgf?gameId={game_id}");
+        let (status, body) = call_as(&state, "intruder", Method::GET, &sgf_uri, "", "").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], "GAME_NOT_OWNED");
+
+        // 对局未受影响：仍在进行，棋盘上只有双方各一手
+        let (status, body) = post_json(&state, "/api/game/heartbeat", id.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["end"]["finished"], false);
+        assert_eq!(body["moveNumber"], 2);
+
+        let (status, _) = post_json(&state, "/api/game/close", id.clone()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        // 已释放的对局：关闭仍成功，其余接口 410
+        let (status, _) = post_as("intruder", "/api/game/close", id.clone()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = post_as("intruder", "/api/game/heartbeat", id).await;
+        assert_eq!(status, StatusCode::GONE);
+        assert_eq!(body["error"], "GAME_EXPIRED");
+    }
 
-fn normalise_user_move(value: &str, board_size: u32) -> Result<String, String> {
-    let trimmed = value.trim();
-    if trimmed.is_empty() {
-        return Err("value is empty".to_string());
+    #[tokio::test]
+    async fn unsigned_or_forged_sid_cookies_get_a_fresh_sid() {
+        let state = test_state(Vec::new());
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let id = serde_json::json!({"gameId": body["gameId"]});
+
+        // 裸 sid 或用其他密钥签名的 Cookie 都不能冒充 test-sid
+        let foreign = session::SessionKeys::new(b"guess".to_vec(), Vec::new(), 3600, false);
+        let forged = [
+            "sid=test-sid".to_string(),
+            format!("sid={}", foreign.sign("test-sid", now_unix())),
+        ];
+        for cookie in forged {
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri("/api/game/resign")
+                .header(CONTENT_TYPE, "application/json")
+                .header("cookie", cookie)
+                .body(axum::body::Body::from(id.to_string()))
+                .unwrap();
+            let resp = api_router()
+                .with_state(state.clone())
+                .oneshot(req)
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        }
+
+        // 新签发的 Cookie 带签名与有效期，可以原样带回
+        let req = Request::builder()
+            .uri("/api/game/active")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let resp = api_router()
+            .with_state(state.clone())
+            .oneshot(req)
+            .await
+            .unwrap();
+        let set_cookie = resp.headers()[SET_COOKIE].to_str().unwrap().to_string();
+        assert!(set_cookie.contains("Max-Age=3600; HttpOnly"));
+        let value = set_cookie
+            .strip_prefix("sid=")
+            .and_then(|c| c.split(';').next())
+            .unwrap();
+        let session = state.session_keys.verify(value, now_unix()).unwrap();
+        assert_ne!(session.sid, "test-sid");
+
+        // 过半有效期的 Cookie 在对局操作中续签
+        let stale = format!(
+            "sid={}",
+            state.session_keys.sign("test-sid", now_unix() - 2000)
+        );
+        for uri in ["/api/game/heartbeat", "/api/game/play", "/api/game/close"] {
+            let body = serde_json::json!({"gameId": id["gameId"], "playerMove": "D4"});
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(CONTENT_TYPE, "application/json")
+                .header("cookie", &stale)
+                .body(axum::body::Body::from(body.to_string()))
+                .unwrap();
+            let resp = api_router()
+                .with_state(state.clone())
+                .oneshot(req)
+                .await
+                .unwrap();
+            assert!(resp.status().is_success(), "{uri}: {}", resp.status());
+            let set_cookie = resp.headers()[SET_COOKIE].to_str().unwrap();
+            let value = set_cookie
+                .strip_prefix("sid=")
+                .and_then(|c| c.split(';').next())
+                .unwrap();
+            let session = state.session_keys.verify(value, now_unix()).unwrap();
+            assert_eq!(session.sid, "test-sid");
+            assert!(!session.refresh);
+        }
     }
-    if trimmed.eq_ignore_ascii_case("pass") {
-        return Ok("pass".to_string());
+
+    #[tokio::test]
+    async fn crashed_engine_is_replaced_within_a_full_pool() {
+        // 用 sh 模拟 GTP 引擎并记下收到的命令；第一次 genmove 时进程退出，之后的进程正常应手
+        let dir = std::env::temp_dir().join(format!("crash-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands.log");
+        let script = format!(
+            "while read id cmd rest; do echo \"$cmd $rest\" >> {log}; case \"$cmd\" in \
+             genmove) [ -e {mark} ] || {{ : > {mark}; exit 1; }}; printf '=%s Q16\\n\\n' \"$id\" ;; \
+             quit) printf '=%s\\n\\n' \"$id\"; exit ;; \
+             *) printf '=%s\\n\\n' \"$id\" ;; esac; done",
+            log = log.display(),
+            mark = dir.join("crashed").display(),
+        );
+        let mut app = (*test_state(Vec::new())).clone();
+        app.engine_backend = EngineBackend::Gtp {
+            spec: engine::pool::EngineSpec {
+                program: "sh".to_string(),
+                args: vec!["-c".to_string(), script],
+            },
+        };
+        // 进程池只有一个名额：死引擎的租约不放下就租不到新引擎
+        app.engine_pool = engine::pool::EnginePool::new(
+            1,
+            Duration::from_millis(300),
+            engine::gtp::CommandTimeouts::default(),
+        );
+        let state = Arc::new(app);
+
+        let (status, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let play = serde_json::json!({"gameId": body["gameId"], "playerMove": "D4"});
+        let (status, body) = post_json(&state, "/api/game/play", play).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["engineMove"], "Q16");
+        assert_eq!(
+            state
+                .engine_restarts
+                .load(std::sync::atomic::Ordering::SeqCst),
+            1
+        );
+        assert_eq!(state.engine_pool.stats().live, 1);
+
+        // 新进程先重放人类着法，再应手
+        let commands = std::fs::read_to_string(&log).unwrap();
+        let moves: Vec<&str> = commands
+            .lines()
+            .filter(|l| l.starts_with("play") || l.starts_with("genmove"))
+            .collect();
+        assert_eq!(moves, ["play B D4", "genmove W", "play B D4", "genmove W"]);
     }
-    if trimmed.len() != 2 {
-        return Err(format!(
-            "\"{}\" should be a two-letter SGF coordinate or 'pass'",
-            trimmed
-        ));
+
+    // 对局引擎换成 sh 脚本模拟的 GTP 引擎
+    fn sh_engine_state(script: String) -> Arc<AppState> {
+        let mut app = (*test_state(Vec::new())).clone();
+        app.engine_backend = EngineBackend::Gtp {
+            spec: engine::pool::EngineSpec {
+                program: "sh".to_string(),
+                args: vec!["-c".to_string(), script],
+            },
+        };
+        Arc::new(app)
     }
-    let lower = trimmed.to_ascii_lowercase();
-    let bytes = lower.as_bytes();
-    if !bytes.iter().all(|b| b.is_ascii_lowercase()) {
-        return Err(format!("\"{}\" must use letters a-z", trimmed));
+
+    #[tokio::test]
+    async fn undo_waits_for_the_move_in_flight() {
+        // genmove 较慢的 GTP 引擎：应手尚未返回时请求悔棋
+        let dir = std::env::temp_dir().join(format!("turn-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands.log");
+        let script = format!(
+            "while read id cmd rest; do echo \"$cmd $rest\" >> {log}; case \"$cmd\" in \
+             genmove) sleep 0.3; printf '=%s Q16\\n\\n' \"$id\" ;; \
+             quit) printf '=%s\\n\\n' \"$id\"; exit ;; \
+             *) printf '=%s\\n\\n' \"$id\" ;; esac; done",
+            log = log.display(),
+        );
+        let state = sh_engine_state(script);
+
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = tokio::spawn({
+            let state = state.clone();
+            let body = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+            async move { post_json(&state, "/api/game/play", body).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let (status, body) = post_json(
+            &state,
+            "/api/game/undo",
+            serde_json::json!({"gameId": game_id}),
+        )
+        .await;
+        let (play_status, _) = play.await.unwrap();
+        assert_eq!(play_status, StatusCode::OK);
+
+        // 悔棋排在整手之后：人类着法与 AI 应手一并撤销，引擎与棋盘一致
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["undone"], serde_json::json!(["Q16", "D4"]));
+        assert_eq!(body["moveNumber"], 0);
+        let commands = std::fs::read_to_string(&log).unwrap();
+        let moves: Vec<&str> = commands
+            .lines()
+            .filter(|l| ["play", "genmove", "undo"].iter().any(|c| l.starts_with(c)))
+            .collect();
+        assert_eq!(moves, ["play B D4", "genmove W", "undo ", "undo "]);
     }
-    let size = board_size as u8;
-    let x = bytes[0];
-    let y = bytes[1];
-    if x < b'a' || y < b'a' || x >= b'a' + size || y >= b'a' + size {
-        return Err(format!(
-            "\"{}\" outside board (size {})",
-            trimmed, board_size
-        ));
+
+    #[tokio::test]
+    async fn timed_out_genmove_rebuilds_engine_from_board() {
+        // 第一次 genmove 超时后才迟到地认输：不能靠盲目 undo 对齐引擎
+        let dir = std::env::temp_dir().join(format!("late-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands.log");
+        let script = format!(
+            "while read id cmd rest; do echo \"$cmd $rest\" >> {log}; case \"$cmd\" in \
+             genmove) if [ -e {mark} ]; then printf '=%s Q16\\n\\n' \"$id\"; \
+             else : > {mark}; sleep 0.3; printf '=%s resign\\n\\n' \"$id\"; fi ;; \
+             quit) printf '=%s\\n\\n' \"$id\"; exit ;; \
+             *) printf '=%s\\n\\n' \"$id\" ;; esac; done",
+            log = log.display(),
+            mark = dir.join("slow").display(),
+        );
+        let mut app = (*sh_engine_state(script)).clone();
+        app.engine_pool = engine::pool::EnginePool::new(
+            1,
+            Duration::from_secs(1),
+            engine::gtp::CommandTimeouts {
+                genmove: Duration::from_millis(200),
+                ..Default::default()
+            },
+        );
+        let state = Arc::new(app);
+
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+        let (status, _) = post_json(&state, "/api/game/play", play.clone()).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(
+            state.game_store.get(&game_id).unwrap().board.moves().len(),
+            0
+        );
+
+        // 重下这一手时引擎已按棋盘重建，没有多余的 undo
+        let (status, body) = post_json(&state, "/api/game/play", play).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["engineMove"], "Q16");
+        let commands = std::fs::read_to_string(&log).unwrap();
+        let moves: Vec<&str> = commands
+            .lines()
+            .filter(|l| ["play", "genmove", "undo"].iter().any(|c| l.starts_with(c)))
+            .collect();
+        assert_eq!(moves, ["play B D4", "genmove W", "play B D4", "genmove W"]);
     }
-    Ok(lower)
-}
 
-fn move_node_to_answer_coord(node: &review::MoveNode) -> String {
-    node.coord.clone().unwrap_or_else(|| "pass".to_string())
-}
+    #[tokio::test]
+    async fn failed_genmove_restores_the_clock_before_the_press() {
+        // genmove 失败撤回人类着法：费舍尔加秒、加拿大读秒计手都不留给人类
+        let state = sh_engine_state(
+            "while read id cmd rest; do case \"$cmd\" in \
+             genmove) printf '?%s no move\\n\\n' \"$id\" ;; \
+             quit) printf '=%s\\n\\n' \"$id\"; exit ;; \
+             *) printf '=%s\\n\\n' \"$id\" ;; esac; done"
+                .to_string(),
+        );
+        for tc in [
+            serde_json::json!({"kind": "fischer", "mainTime": 60, "increment": 30}),
+            serde_json::json!({"kind": "canadian", "mainTime": 0, "periodTime": 60, "stones": 5}),
+        ] {
+            let req = serde_json::json!({"timeControl": tc});
+            let (_, body) = post_json(&state, "/api/game/new", req).await;
+            let game_id = body["gameId"].as_str().unwrap().to_string();
+            let before = body["clock"]["black"].clone();
+            let play = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+            let (status, body) = post_json(&state, "/api/game/play", play).await;
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{body}");
+
+            let gs = state.game_store.get(&game_id).unwrap();
+            assert!(gs.board.moves().is_empty());
+            let clock = gs.clock_json();
+            assert_eq!(clock["running"], "black");
+            assert!(clock["black"]["mainMs"].as_u64() <= before["mainMs"].as_u64());
+            assert_eq!(clock["black"]["stones"], before["stones"]);
+        }
+    }
 
-fn with_cookie(resp: Response, set_cookie: Option<HeaderValue>) -> Response {
-    let mut response = resp;
-    if let Some(val) = set_cookie {
-        response.headers_mut().insert(SET_COOKIE, val);
+    #[tokio::test]
+    async fn failed_engine_undo_rebuilds_from_board() {
+        // 引擎拒绝 undo：棋盘照常悔棋，引擎按悔棋后的棋盘重建
+        let dir = std::env::temp_dir().join(format!("undo-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands.log");
+        let script = format!(
+            "while read id cmd rest; do echo \"$cmd $rest\" >> {log}; case \"$cmd\" in \
+             genmove) printf '=%s Q16\\n\\n' \"$id\" ;; \
+             undo) printf '?%s cannot undo\\n\\n' \"$id\" ;; \
+             quit) printf '=%s\\n\\n' \"$id\"; exit ;; \
+             *) printf '=%s\\n\\n' \"$id\" ;; esac; done",
+            log = log.display(),
+        );
+        let state = sh_engine_state(script);
+
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+        post_json(&state, "/api/game/play", play.clone()).await;
+
+        let id = serde_json::json!({"gameId": game_id});
+        let (status, body) = post_json(&state, "/api/game/undo", id).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["undone"], serde_json::json!(["Q16", "D4"]));
+        assert_eq!(body["moveNumber"], 0);
+
+        // 重建后的引擎从空棋盘开始，同一手可以再下
+        let (status, body) = post_json(&state, "/api/game/play", play).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let commands = std::fs::read_to_string(&log).unwrap();
+        let after_undo: Vec<&str> = commands
+            .lines()
+            .skip_while(|l| !l.starts_with("undo"))
+            .filter(|l| {
+                ["undo", "clear_board", "play", "genmove"]
+                    .iter()
+                    .any(|c| l.starts_with(c))
+            })
+            .collect();
+        assert_eq!(
+            after_undo,
+            ["undo ", "clear_board ", "play B D4", "genmove W"]
+        );
     }
-    response
-}
 
-async fn fetch_remote_sgf(url: &str) -> Result<Vec<u8>, String> {
-    let uri: Uri = url.parse().map_err(|_| "INVALID_SOURCE_URL".to_string())?;
-    if uri.scheme_str() != Some("https") {
-        return Err("REMOTE_URL_NOT_HTTPS".to_string());
+    #[tokio::test]
+    async fn failed_hint_undo_rebuilds_from_board() {
+        // 提示后的 undo 失败：引擎里可能还留着提示子，按棋盘重建而不是重试 undo
+        let dir = std::env::temp_dir().join(format!("hint-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands.log");
+        let script = format!(
+            "while read id cmd rest; do echo \"$cmd $rest\" >> {log}; case \"$cmd\" in \
+             genmove) printf '=%s Q16\\n\\n' \"$id\" ;; \
+             undo) printf '?%s cannot undo\\n\\n' \"$id\" ;; \
+             quit) printf '=%s\\n\\n' \"$id\"; exit ;; \
+             *) printf '=%s\\n\\n' \"$id\" ;; esac; done",
+            log = log.display(),
+        );
+        let state = sh_engine_state(script);
+
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let id = serde_json::json!({"gameId": game_id});
+        let (status, body) = post_json(&state, "/api/game/hint", id).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["suggestion"], "Q16");
+
+        // 落子排在后台重建之后，引擎从空棋盘开始
+        let play = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+        let (status, body) = post_json(&state, "/api/game/play", play).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let commands = std::fs::read_to_string(&log).unwrap();
+        let after_hint: Vec<&str> = commands
+            .lines()
+            .skip_while(|l| !l.starts_with("genmove"))
+            .filter(|l| {
+                ["undo", "clear_board", "play", "genmove"]
+                    .iter()
+                    .any(|c| l.starts_with(c))
+            })
+            .collect();
+        assert_eq!(
+            after_hint,
+            [
+                "genmove B",
+                "undo ",
+                "clear_board ",
+                "play B D4",
+                "genmove W"
+            ]
+        );
     }
-    let host = uri
-        .host()
-        .ok_or_else(|| "REMOTE_URL_HOST_REQUIRED".to_string())?;
-    if !remote_host_allowed(host) {
-        return Err("REMOTE_HOST_NOT_ALLOWED".to_string());
+
+    #[tokio::test]
+    async fn fallback_scoring_rebuilds_instead_of_undoing_passes() {
+        // 首次 final_score 失败：补 pass 后数子，再按棋盘重建引擎而不是 undo
+        let dir = std::env::temp_dir().join(format!("score-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands.log");
+        let script = format!(
+            "n=0; while read id cmd rest; do echo \"$cmd $rest\" >> {log}; case \"$cmd\" in \
+             genmove) printf '=%s Q16\\n\\n' \"$id\" ;; \
+             final_score) n=$((n+1)); if [ $n -eq 1 ]; then printf '?%s cannot score\\n\\n' \"$id\"; \
+             else printf '=%s B+1.5\\n\\n' \"$id\"; fi ;; \
+             quit) printf '=%s\\n\\n' \"$id\"; exit ;; \
+             *) printf '=%s\\n\\n' \"$id\" ;; esac; done",
+            log = log.display(),
+        );
+        let state = sh_engine_state(script);
+
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+        post_json(&state, "/api/game/play", play).await;
+        let id = serde_json::json!({"gameId": game_id});
+        let (status, body) = post_json(&state, "/api/game/score_detail", id).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["result"], "B+1.5");
+
+        let commands = std::fs::read_to_string(&log).unwrap();
+        let scoring: Vec<&str> = commands
+            .lines()
+            .skip_while(|l| !l.starts_with("final_score"))
+            .filter(|l| {
+                ["final_score", "undo", "clear_board", "play"]
+                    .iter()
+                    .any(|c| l.starts_with(c))
+            })
+            .collect();
+        assert_eq!(
+            scoring,
+            [
+                "final_score ",
+                "play B pass",
+                "play W pass",
+                "final_score ",
+                "clear_board ",
+                "play B D4",
+                "play W Q16",
+            ]
+        );
     }
 
-    let client = Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()
-        .map_err(|err| {
-            tracing::error!(?err, "failed to build reqwest client");
-            "REMOTE_FETCH_FAILED".to_string()
-        })?;
+    #[tokio::test]
+    async fn live_game_exports_sgf() {
+        let state = test_state(vec![GenMove::Play("Q16".parse().unwrap())]);
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+        post_json(&state, "/api/game/play", play).await;
+        let id = serde_json::json!({"gameId": game_id});
+        let (_, body) = post_json(&state, "/api/game/score_detail", id).await;
+        assert_eq!(body["result"], "W+7.5");
+
+        let req = Request::builder()
+            .uri(format!("/api/game/sgf?gameId={game_id}"))
+            .header("cookie", sid_cookie(&state, "test-sid"))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let resp = api_router()
+            .with_state(state.clone())
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(
+            resp.headers()[CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("application/x-go-sgf")
+        );
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        let parsed = review::parser::parse_sgf(std::str::from_utf8(&bytes).unwrap()).unwrap();
+        assert_eq!(parsed.meta.black.as_deref(), Some("Human"));
+        assert_eq!(parsed.meta.white.as_deref(), Some("KataGo 3-star"));
+        assert_eq!(parsed.meta.result, None);
+        assert_eq!(parsed.komi, 7.5);
+        assert_eq!(parsed.moves.len(), 2);
+        assert_eq!(parsed.moves[1].coord.as_deref(), Some("pd"));
+        assert_eq!(
+            parsed.moves[1].comment.as_deref(),
+            Some("KataGo estimate: W+7.5")
+        );
 
-    let resp = client.get(url).send().await.map_err(|err| {
-        tracing::warn!(?err, "failed to download remote sgf");
-        "REMOTE_FETCH_FAILED".to_string()
-    })?;
+        let (status, _) = call(
+            &state,
+            Method::GET,
+            "/api/game/sgf?gameId=g-missing",
+            "text/plain",
+            "",
+        )
+        .await;
+        assert_eq!(status, StatusCode::GONE);
+    }
 
-    if !resp.status().is_success() {
-        tracing::warn!(status=?resp.status(), "remote sgf returned non-200");
-        return Err("REMOTE_FETCH_FAILED".to_string());
+    #[tokio::test]
+    async fn finished_games_are_archived_per_sid() {
+        let state = test_state(Vec::new());
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let id = serde_json::json!({"gameId": game_id});
+        post_json(&state, "/api/game/resign", id.clone()).await;
+
+        // 归档在后台写入
+        let mut listed = serde_json::Value::Null;
+        for _ in 0..50 {
+            let (_, body) = call(&state, Method::GET, "/api/archive/games", "", "").await;
+            if body["total"] == 1 {
+                listed = body;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(listed["games"][0]["gameId"], game_id.as_str());
+        assert_eq!(listed["games"][0]["outcome"], "loss");
+        let (_, body) = call(&state, Method::GET, "/api/archive/games?result=win", "", "").await;
+        assert_eq!(body["total"], 0);
+
+        post_json(&state, "/api/game/close", id).await;
+        let uri = format!("/api/archive/game?gameId={game_id}");
+        let (status, body) = call(&state, Method::GET, &uri, "", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["end"]["result"], "W+R");
+        assert_eq!(body["sid"], serde_json::Value::Null);
+        let uri = format!("/api/game/sgf?gameId={game_id}");
+        let (status, _) = call(&state, Method::GET, &uri, "", "").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let req = Request::builder()
+            .uri(format!("/api/archive/game?gameId={game_id}"))
+            .header("cookie", sid_cookie(&state, "other-sid"))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let resp = api_router()
+            .with_state(state.clone())
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
     }
 
-    let bytes = resp.bytes().await.map_err(|err| {
-        tracing::warn!(?err, "failed to read remote sgf body");
-        "REMOTE_FETCH_FAILED".to_string()
-    })?;
-    if bytes.len() > MAX_SGF_BYTES {
-        return Err("SGF_TOO_LARGE".to_string());
+    #[tokio::test]
+    async fn games_resume_from_snapshots_after_restart() {
+        let data_dir = std::env::temp_dir().join(format!("game-data-{}", uuid::Uuid::new_v4()));
+        let state = test_state_in(&data_dir, Vec::new());
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = |mv: &str| serde_json::json!({"gameId": game_id, "playerMove": mv});
+        post_json(&state, "/api/game/play", play("D4")).await;
+        state.snapshots.flush().await;
+
+        // 同一数据目录重新启动：对局与 sid 的对局列表按快照恢复
+        let restarted = test_state_in(&data_dir, Vec::new());
+        restore_games(&restarted);
+        let (status, body) = call(&restarted, Method::GET, "/api/game/active", "", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["games"][0]["gameId"], game_id.as_str());
+        assert_eq!(body["games"][0]["moveNumber"], 2);
+        assert_eq!(body["games"][0]["moves"][1]["vertex"], "A19");
+
+        // 引擎在首次落子时重放着法重建，不计入崩溃重启
+        let (status, body) = post_json(&restarted, "/api/game/play", play("A19")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["reason"], "OCCUPIED");
+        let (status, body) = post_json(&restarted, "/api/game/play", play("D5")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["engineMove"], "B19");
+        assert_eq!(body["moveNumber"], 4);
+        assert_eq!(
+            restarted
+                .engine_restarts
+                .load(std::sync::atomic::Ordering::SeqCst),
+            0
+        );
+
+        let id = serde_json::json!({"gameId": game_id});
+        post_json(&restarted, "/api/game/close", id).await;
+        restarted.snapshots.flush().await;
+        assert!(restarted.snapshots.load_all().is_empty());
+        let _ = std::fs::remove_dir_all(&data_dir);
     }
-    Ok(bytes.to_vec())
-}
 
-fn remote_host_allowed(host: &str) -> bool {
-    match std::env::var("REVIEW_IMPORT_HOST_WHITELIST") {
-        Ok(list) => {
-            let trimmed = list.trim();
-            if trimmed.is_empty() {
-                return true;
+    #[tokio::test]
+    async fn games_convert_to_reviews_for_their_owner() {
+        let state = test_state(Vec::new());
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = serde_json::json!({"gameId": game_id, "playerMove": "D4"});
+        post_json(&state, "/api/game/play", play).await;
+
+        let id = serde_json::json!({"gameId": game_id});
+        let (status, body) = post_json(&state, "/api/game/to_review", id.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["moves"].as_array().unwrap().len(), 2);
+        assert_eq!(body["meta"]["black"], "Human");
+        let review_id = body["reviewId"].as_str().unwrap();
+        assert_eq!(state.review_store.get(review_id).unwrap().sid, "test-sid");
+        let analyze = serde_json::json!({"reviewId": review_id, "moveIndex": 2, "maxVisits": 10});
+        let (status, _) = post_json(&state, "/api/review/analyze", analyze).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/api/game/to_review")
+            .header(CONTENT_TYPE, "application/json")
+            .header("cookie", sid_cookie(&state, "other-sid"))
+            .body(axum::body::Body::from(id.to_string()))
+            .unwrap();
+        let resp = api_router()
+            .with_state(state.clone())
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let missing = serde_json::json!({"gameId": "g-missing"});
+        let (status, _) = post_json(&state, "/api/game/to_review", missing).await;
+        assert_eq!(status, StatusCode::GONE);
+    }
+
+    #[tokio::test]
+    async fn invited_player_joins_and_players_alternate() {
+        let state = test_state(Vec::new());
+        let post_as = |sid: &'static str, uri: &'static str, body: serde_json::Value| {
+            let state = state.clone();
+            async move {
+                call_as(
+                    &state,
+                    sid,
+                    Method::POST,
+                    uri,
+                    "application/json",
+                    body.to_string(),
+                )
+                .await
             }
-            trimmed
-                .split(',')
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty())
-                .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        };
+        let new = serde_json::json!({"opponent": "human", "playerColor": "black"});
+        let (status, body) = post_as("host", "/api/game/new", new).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["engineMove"], serde_json::Value::Null);
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let join = serde_json::json!({"inviteToken": body["inviteToken"]});
+
+        let (status, _) = post_as("host", "/api/game/join", join.clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, body) = post_as("guest", "/api/game/join", join.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["humanColor"], "white");
+        assert_eq!(body["opponentJoined"], true);
+        let (status, body) = post_as("other", "/api/game/join", join).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"], "INVITE_USED");
+
+        let play = |mv: &str| serde_json::json!({"gameId": game_id, "playerMove": mv});
+        let (status, body) = post_as("guest", "/api/game/play", play("Q16")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["reason"], "WRONG_TURN");
+        let (status, body) = post_as("host", "/api/game/play", play("D4")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["engineMove"], serde_json::Value::Null);
+        assert_eq!(body["toMove"], "white");
+        let (status, _) = post_as("other", "/api/game/play", play("Q16")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = post_as("guest", "/api/game/play", play("Q16")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let id = serde_json::json!({"gameId": game_id});
+        let (status, _) = post_as("host", "/api/game/undo", id.clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (_, body) = post_as("host", "/api/game/heartbeat", id.clone()).await;
+        assert_eq!(body["moves"][1]["vertex"], "Q16");
+
+        // 未挂引擎：双方连续 pass 后结束，结果待定
+        post_as("host", "/api/game/play", play("pass")).await;
+        let (_, body) = post_as("guest", "/api/game/play", play("pass")).await;
+        assert_eq!(body["end"]["reason"], "doublePass");
+        assert_eq!(body["end"]["result"], "?");
+
+        // 一方离开后对局仍为另一方保留
+        post_as("guest", "/api/game/close", id.clone()).await;
+        assert!(state.game_store.contains_key(&game_id));
+        post_as("host", "/api/game/close", id).await;
+        assert!(!state.game_store.contains_key(&game_id));
+        // 归档在后台写入，双方都能看到
+        let mut total = serde_json::Value::Null;
+        for _ in 0..50 {
+            let (_, body) =
+                call_as(&state, "guest", Method::GET, "/api/archive/games", "", "").await;
+            total = body["total"].clone();
+            if total == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
-        Err(_) => true,
+        assert_eq!(total, 1);
     }
-}
 
-async fn start_review_engine() -> anyhow::Result<std::sync::Arc<engine::gtp::GtpEngine>> {
-    let engine_path = std::env::var("ENGINE_PATH").context("ENGINE_PATH not set")?;
-    let model_path = std::env::var("MODEL_PATH").context("MODEL_PATH not set")?;
-    let config_path = std::env::var("GTP_CONFIG_PATH").context("GTP_CONFIG_PATH not set")?;
+    #[tokio::test]
+    async fn pvp_clock_waits_for_the_guest() {
+        let state = test_state(Vec::new());
+        let new = serde_json::json!({
+            "opponent": "human",
+            "timeControl": {"kind": "fischer", "mainTime": 60, "increment": 0},
+        });
+        let (_, body) = post_json(&state, "/api/game/new", new.clone()).await;
+        assert_eq!(body["clock"]["running"], serde_json::Value::Null);
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+
+        // 等人期间棋钟不走，巡检也不判负
+        let long_ago = Instant::now() - Duration::from_secs(3600);
+        if let Some(clock) = state.game_store.get_mut(&game_id).unwrap().clock.as_mut() {
+            clock.start(Color::Black, long_ago);
+        }
+        assert!(
+            !state
+                .game_store
+                .get_mut(&game_id)
+                .unwrap()
+                .check_flag(&game_id)
+        );
+        let join = serde_json::json!({"inviteToken": body["inviteToken"]});
+        let (_, body) = call_as(
+            &state,
+            "guest",
+            Method::POST,
+            "/api/game/join",
+            "application/json",
+            join.to_string(),
+        )
+        .await;
+        assert_eq!(body["clock"]["running"], "black");
+        assert!(body["clock"]["black"]["mainMs"].as_u64().unwrap() > 59_000);
+
+        // 无人加入的邀请关闭后直接释放，不记认输、不归档
+        let (_, body) = post_json(&state, "/api/game/new", new).await;
+        let id = serde_json::json!({"gameId": body["gameId"]});
+        let (status, _) = post_json(&state, "/api/game/close", id).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(state.game_store.len(), 1);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let (_, body) = call(&state, Method::GET, "/api/archive/games", "", "").await;
+        assert_eq!(body["total"], 0);
+    }
 
-    let mut args = vec![
-        "gtp".to_string(),
-        "-model".to_string(),
-        model_path,
-        "-config".to_string(),
-        config_path,
-    ];
-    for (k, v) in overrides_for_level(5) {
-        args.push("-override-config".to_string());
-        args.push(format!("{}={}", k, v));
+    #[tokio::test]
+    async fn human_game_can_borrow_an_engine_for_scoring() {
+        let state = test_state(Vec::new());
+        let new = serde_json::json!({"opponent": "human", "scoringEngine": true});
+        let (_, body) = post_json(&state, "/api/game/new", new).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let join = serde_json::json!({"inviteToken": body["inviteToken"]});
+        let (status, _) = call_as(
+            &state,
+            "guest",
+            Method::POST,
+            "/api/game/join",
+            "application/json",
+            join.to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let pass = serde_json::json!({"gameId": game_id, "playerMove": "pass"});
+        post_json(&state, "/api/game/play", pass.clone()).await;
+        let (_, body) = call_as(
+            &state,
+            "guest",
+            Method::POST,
+            "/api/game/play",
+            "application/json",
+            pass.to_string(),
+        )
+        .await;
+        assert_eq!(body["end"]["result"], "W+7.5");
     }
-    args.push("-override-config".to_string());
-    args.push("rules=chinese".to_string());
 
-    let engine = engine::gtp::GtpEngine::start(&engine_path, &args)
-        .await
-        .context("failed to spawn katago")?;
-    Ok(engine)
-}
+    #[tokio::test]
+    async fn exhibition_streams_moves_and_exports_sgf() {
+        let state = test_state(Vec::new());
+        let req = serde_json::json!({
+            "blackLevel": 1,
+            "whiteProfile": "5star",
+            "boardSize": 9,
+            "moveIntervalMs": 0,
+            "maxMoves": 4,
+        });
+        let (status, body) = post_json(&state, "/api/exhibition/new", req.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], "ADMIN_DISABLED");
+        let mut app = (*state).clone();
+        app.admin_token = Some("secret".to_string());
+        let state = Arc::new(app);
+        let (status, _) = post_json(&state, "/api/exhibition/new", req.clone()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let oversized = serde_json::json!({"boardSize": 40});
+        let (status, body) = post_admin(&state, "/api/exhibition/new", oversized).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_BOARD_SIZE");
+
+        // 进行中的校准对局不占表演赛名额
+        for i in 0..MAX_LIVE_EXHIBITIONS {
+            let entrant = || game::exhibition::Entrant::new("1star", Some(1));
+            let mut ex =
+                game::exhibition::Exhibition::new(entrant(), entrant(), "chinese", 9, 7.5, 0);
+            ex.calibration = true;
+            state.exhibitions.insert(format!("x-calibration-{i}"), ex);
+        }
+        let (status, body) = post_admin(&state, "/api/exhibition/new", req).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["black"]["name"], "KataGo 1-star");
+        let id = body["exhibitionId"].as_str().unwrap().to_string();
+
+        // 终局后流随之关闭，因此可以整段读完
+        let req = Request::builder()
+            .uri(format!("/api/exhibition/stream?exhibitionId={id}"))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let resp = api_router()
+            .with_state(state.clone())
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        let text = std::str::from_utf8(&bytes).unwrap();
+        assert!(text.starts_with("event: state"));
+        assert!(text.contains("\"result\":\"W+7.5\""));
+        assert!(text.contains("\"reason\":\"moveLimit\""));
+
+        let (_, body) = call(
+            &state,
+            Method::GET,
+            "/api/exhibition/list",
+            "text/plain",
+            "",
+        )
+        .await;
+        let item = body["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["exhibitionId"] == id.as_str())
+            .unwrap();
+        assert_eq!(item["result"], "W+7.5");
+        assert_eq!(item["moveNumber"], 4);
+
+        let req = Request::builder()
+            .uri(format!("/api/exhibition/sgf?exhibitionId={id}"))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let resp = api_router()
+            .with_state(state.clone())
+            .oneshot(req)
+            .await
+            .unwrap();
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        let parsed = review::parser::parse_sgf(std::str::from_utf8(&bytes).unwrap()).unwrap();
+        assert_eq!(parsed.meta.black.as_deref(), Some("KataGo 1-star"));
+        assert_eq!(parsed.meta.white.as_deref(), Some("KataGo 5-star"));
+        assert_eq!(parsed.meta.result.as_deref(), Some("W+7.5"));
+        assert_eq!(parsed.moves.len(), 4);
+
+        let (status, body) = call(
+            &state,
+            Method::GET,
+            "/api/exhibition/stream?exhibitionId=x-missing",
+            "text/plain",
+            "",
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "EXHIBITION_NOT_FOUND");
+    }
 
-async fn load_review_position(
-    engine: &std::sync::Arc<engine::gtp::GtpEngine>,
-    review_id: &str,
-    raw_sgf: &str,
-    move_index: u32,
-) -> anyhow::Result<()> {
-    let temp_dir = std::env::temp_dir().join("katago_review_cache");
-    fs::create_dir_all(&temp_dir)
-        .await
-        .context("failed to create temp dir for sgf cache")?;
-    let file_path = temp_dir.join(format!("{}.sgf", review_id));
-    fs::write(&file_path, raw_sgf.as_bytes())
-        .await
-        .with_context(|| format!("failed to write sgf cache: {}", file_path.display()))?;
-    let path_text = file_path
-        .to_str()
-        .ok_or_else(|| anyhow!("temporary SGF path not valid UTF-8"))?;
-    let cmd = if move_index == 0 {
-        format!("loadsgf {}", path_text)
-    } else {
-        format!("loadsgf {} {}", path_text, move_index)
-    };
-    engine
-        .send_command(&cmd)
-        .await
-        .context("loadsgf command failed")?;
-    Ok(())
-}
+    #[test]
+    fn admin_token_must_match_exactly() {
+        assert!(admin_token_matches(b"secret", b"secret"));
+        for wrong in [&b""[..], b"secre", b"secrets", b"Secret"] {
+            assert!(!admin_token_matches(wrong, b"secret"), "{wrong:?}");
+        }
+    }
 
-fn parse_kata_analyze(raw: &str) -> Option<review::KataAnalysis> {
-    let line = raw
-        .lines()
-        .find(|l| l.trim_start().starts_with("info"))?
-        .trim();
-    let mut winrate = None;
-    let mut score_lead = None;
-    let mut visits = None;
-    let mut pv: Vec<String> = Vec::new();
-
-    let mut tokens = line.split_whitespace().peekable();
-    while let Some(token) = tokens.next() {
-        match token {
-            "winrate" => {
-                if let Some(value) = tokens.next() {
-                    winrate = value.parse::<f32>().ok();
-                }
+    #[tokio::test]
+    async fn exhibition_rebuilds_a_crashed_engine_from_the_board() {
+        // 执黑先下 D4；执白的第一次 genmove 时进程退出，重建后双方 pass 终局
+        let dir = std::env::temp_dir().join(format!("exhibition-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands.log");
+        let script = format!(
+            "while read id cmd rest; do echo \"$cmd $rest\" >> {log}; case \"$cmd\" in \
+             genmove) if [ ! -e {first} ]; then : > {first}; printf '=%s D4\\n\\n' \"$id\"; \
+             elif [ ! -e {crash} ]; then : > {crash}; exit 1; \
+             else printf '=%s pass\\n\\n' \"$id\"; fi ;; \
+             quit) printf '=%s\\n\\n' \"$id\"; exit ;; \
+             *) printf '=%s\\n\\n' \"$id\" ;; esac; done",
+            log = log.display(),
+            first = dir.join("first").display(),
+            crash = dir.join("crash").display(),
+        );
+        let mut app = (*sh_engine_state(script)).clone();
+        app.engine_pool = engine::pool::EnginePool::new(
+            2,
+            Duration::from_secs(1),
+            engine::gtp::CommandTimeouts::default(),
+        );
+        let state = Arc::new(app);
+        let entrant = || game::exhibition::Entrant::new("1star", Some(1));
+        let (view, engines) =
+            open_exhibition(&state, entrant(), entrant(), "chinese", 9, 7.5, false)
+                .await
+                .unwrap();
+        let id = view.exhibition_id;
+        run_exhibition(state.clone(), id.clone(), engines, Duration::ZERO, 10).await;
+
+        let ex = state.exhibitions.get(&id).unwrap();
+        let outcome = ex.outcome().unwrap();
+        assert_eq!(outcome.error, None);
+        assert!(outcome.end.is_some());
+        assert_eq!(ex.board.moves().len(), 3);
+        // 执白的新引擎重放了 D4，之后 genmove 重试
+        let commands = std::fs::read_to_string(&log).unwrap();
+        let moves: Vec<&str> = commands
+            .lines()
+            .filter(|l| l.starts_with("play") || l.starts_with("genmove"))
+            .collect();
+        assert_eq!(
+            moves,
+            [
+                "genmove B",
+                "play B D4",
+                "genmove W",
+                "play B D4",
+                "genmove W",
+                "play W pass",
+                "genmove B",
+                "play B pass",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn calibration_plays_every_pair_and_rates_levels() {
+        let mut state = (*test_state(Vec::new())).clone();
+        state.admin_token = Some("secret".to_string());
+        let state = Arc::new(state);
+        let admin = |method: Method, uri: &str, body: serde_json::Value| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(CONTENT_TYPE, "application/json")
+                .header("x-admin-token", "secret")
+                .body(axum::body::Body::from(body.to_string()))
+                .unwrap()
+        };
+        let send = |req: Request<axum::body::Body>| {
+            let state = state.clone();
+            async move {
+                let resp = api_router().with_state(state).oneshot(req).await.unwrap();
+                let status = resp.status();
+                let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                (status, body)
             }
-            "scoreLead" | "scorelead" => {
-                if let Some(value) = tokens.next() {
-                    score_lead = value.parse::<f32>().ok();
-                }
+        };
+
+        let (status, _) = post_json(&state, "/api/admin/calibration", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let bad = serde_json::json!({"profiles": ["1star", "nope"]});
+        let (status, body) = send(admin(Method::POST, "/api/admin/calibration", bad)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "UNKNOWN_PROFILE");
+        let bad = serde_json::json!({"profiles": ["1star", "2star"], "boardSize": 1});
+        let (status, body) = send(admin(Method::POST, "/api/admin/calibration", bad)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_BOARD_SIZE");
+
+        // 假引擎下 4 手后数子，执白以贴目获胜；双方各执一次白，实力相当
+        let req = serde_json::json!({
+            "profiles": ["1star", "2star"],
+            "gamesPerPair": 2,
+            "boardSize": 9,
+            "maxMoves": 4,
+        });
+        let (status, body) = send(admin(Method::POST, "/api/admin/calibration", req)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["total"], 2);
+        let run_id = body["runId"].as_str().unwrap().to_string();
+
+        let uri = format!("/api/admin/calibration?runId={run_id}");
+        let mut body = serde_json::Value::Null;
+        for _ in 0..100 {
+            let (_, status) = send(admin(Method::GET, &uri, serde_json::Value::Null)).await;
+            body = status;
+            if !body["progress"]["finishedAt"].is_null() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(body["progress"]["played"], 2);
+        assert_eq!(body["games"], 2);
+        let games = state.calibration.load(Some(&run_id)).await.unwrap();
+        assert!(
+            games
+                .iter()
+                .all(|g| g.reason == Some(game::EndReason::MoveLimit))
+        );
+        let ratings = body["ratings"].as_array().unwrap();
+        assert_eq!(ratings.len(), 2);
+        assert_eq!(ratings[1]["profile"], "2star");
+        assert!(ratings[1]["elo"].as_f64().unwrap().abs() < 1e-6);
+        assert!(ratings[1]["low"].as_f64().unwrap() < 0.0);
+    }
+
+    type ClientSocket = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    // 连接对局 WebSocket；握手被拒时返回状态码
+    async fn connect_game_ws(
+        state: &AppState,
+        addr: SocketAddr,
+        game_id: &str,
+        sid: &str,
+    ) -> Result<ClientSocket, StatusCode> {
+        use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+        let mut req = format!("ws://{addr}/api/game/ws?gameId={game_id}")
+            .into_client_request()
+            .unwrap();
+        req.headers_mut()
+            .insert("cookie", sid_cookie(state, sid).parse().unwrap());
+        match tokio_tungstenite::connect_async(req).await {
+            Ok((ws, _)) => Ok(ws),
+            Err(tungstenite::Error::Http(resp)) => Err(resp.status()),
+            Err(err) => panic!("websocket connect failed: {err}"),
+        }
+    }
+
+    async fn next_json(ws: &mut ClientSocket) -> serde_json::Value {
+        use futures_util::StreamExt;
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), ws.next())
+                .await
+                .expect("websocket message")
+                .unwrap()
+                .unwrap();
+            if let tokio_tungstenite::tungstenite::Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
             }
-            "visits" => {
-                if let Some(value) = tokens.next() {
-                    visits = value.parse::<u32>().ok();
+        }
+    }
+
+    #[tokio::test]
+    async fn websocket_pushes_moves_and_keeps_game_alive() {
+        use futures_util::SinkExt;
+        let state = test_state(vec![GenMove::Play("Q16".parse().unwrap())]);
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = api_router().with_state(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        assert_eq!(
+            connect_game_ws(&state, addr, &game_id, "intruder")
+                .await
+                .err(),
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            connect_game_ws(&state, addr, "g-missing", "test-sid")
+                .await
+                .err(),
+            Some(StatusCode::GONE)
+        );
+
+        let mut ws = connect_game_ws(&state, addr, &game_id, "test-sid")
+            .await
+            .unwrap();
+        let first = next_json(&mut ws).await;
+        assert_eq!(first["type"], "state");
+        assert_eq!(first["moveNumber"], 0);
+        state.game_store.get_mut(&game_id).unwrap().last_active_at = 0;
+
+        let play = serde_json::json!({"type": "play", "playerMove": "D4"});
+        ws.send(tokio_tungstenite::tungstenite::Message::Text(
+            play.to_string(),
+        ))
+        .await
+        .unwrap();
+        // 两手着法经事件推送，整手的回执另行送达（先后不定）
+        let mut moves = Vec::new();
+        let mut played = serde_json::Value::Null;
+        while moves.len() < 2 || played.is_null() {
+            let message = next_json(&mut ws).await;
+            match message["type"].as_str() {
+                Some("move") => {
+                    moves.push((message["vertex"].clone(), message["moveNumber"].clone()))
                 }
+                Some("played") => played = message,
+                _ => {}
             }
-            "pv" => {
-                pv = tokens.map(|t| t.to_string()).collect();
-                break;
+        }
+        assert_eq!(
+            moves,
+            vec![
+                (serde_json::json!("D4"), serde_json::json!(1)),
+                (serde_json::json!("Q16"), serde_json::json!(2)),
+            ]
+        );
+        assert_eq!(played["engineMove"], "Q16");
+
+        ws.send(tokio_tungstenite::tungstenite::Message::Text(
+            serde_json::json!({"type": "ping"}).to_string(),
+        ))
+        .await
+        .unwrap();
+        while next_json(&mut ws).await["type"] != "pong" {}
+        // 连接本身即是心跳
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(state.game_store.get(&game_id).unwrap().last_active_at > 0);
+
+        // 上一手尚未回执时再落子回 BUSY，不另起处理
+        let turn = state.game_store.get(&game_id).unwrap().turn.clone();
+        let held = turn.lock().await;
+        for vertex in ["D5", "D6"] {
+            let play = serde_json::json!({"type": "play", "playerMove": vertex});
+            ws.send(tokio_tungstenite::tungstenite::Message::Text(
+                play.to_string(),
+            ))
+            .await
+            .unwrap();
+        }
+        let busy = loop {
+            let message = next_json(&mut ws).await;
+            if message["type"] == "error" {
+                break message;
             }
-            _ => {}
+        };
+        assert_eq!(busy["error"], "BUSY");
+        drop(held);
+        while next_json(&mut ws).await["type"] != "played" {}
+        assert_eq!(
+            state.game_store.get(&game_id).unwrap().board.moves().len(),
+            4
+        );
+
+        // 对局被清理后连接收到 expired
+        state.game_store.remove(&game_id);
+        while next_json(&mut ws).await["type"] != "expired" {}
+    }
+
+    #[tokio::test]
+    async fn play_reports_captures_from_server_board() {
+        let script = ["E5", "A19", "A18", "A17"]
+            .map(|m| GenMove::Play(m.parse().unwrap()))
+            .to_vec();
+        let state = test_state(script);
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].as_str().unwrap().to_string();
+        let play = |mv: &str| serde_json::json!({"gameId": game_id, "playerMove": mv});
+
+        for mv in ["D5", "F5", "E4"] {
+            let (status, body) = post_json(&state, "/api/game/play", play(mv)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body["captures"]["player"], serde_json::json!([]));
         }
+        let (status, body) = post_json(&state, "/api/game/play", play("E6")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["captures"]["player"], serde_json::json!(["E5"]));
+        assert_eq!(body["prisoners"]["black"], 1);
+        assert_eq!(body["toMove"], "black");
+        assert_eq!(body["moveNumber"], 8);
+
+        let (status, body) = post_json(&state, "/api/game/play", play("A17")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["reason"], "OCCUPIED");
     }
 
-    Some(review::KataAnalysis {
-        winrate: winrate.unwrap_or(0.5),
-        score_lead: score_lead.unwrap_or(0.0),
-        pv,
-        visits: visits.unwrap_or(0),
-    })
-}
+    #[tokio::test]
+    async fn undo_reverts_both_moves_within_allowance() {
+        let state = test_state(Vec::new());
+        let (_, body) = post_json(
+            &state,
+            "/api/game/new",
+            serde_json::json!({"engineProfile": "4star"}),
+        )
+        .await;
+        let id = serde_json::json!({"gameId": body["gameId"]});
+        let (status, body) = post_json(&state, "/api/game/undo", id.clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"], "NOTHING_TO_UNDO");
+
+        let play = serde_json::json!({"gameId": id["gameId"], "playerMove": "D4"});
+        post_json(&state, "/api/game/play", play.clone()).await;
+        let (status, body) = post_json(&state, "/api/game/undo", id.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["undone"], serde_json::json!(["A19", "D4"]));
+        assert_eq!(body["stones"]["black"], serde_json::json!([]));
+        assert_eq!(body["toMove"], "black");
+        assert_eq!(body["undosLeft"], 0);
+
+        // 引擎棋盘也已撤回：同一点可以重下
+        let (status, _) = post_json(&state, "/api/game/play", play).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = post_json(&state, "/api/game/undo", id).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], "UNDO_LIMIT_REACHED");
+    }
 
-fn next_player_to_move(
-    setup: &review::InitialSetup,
-    moves: &[review::MoveNode],
-    move_index: usize,
-) -> review::StoneColor {
-    if move_index == 0 {
-        return setup.to_play.unwrap_or(review::StoneColor::Black);
+    #[tokio::test]
+    async fn game_ends_on_resignation_and_double_pass() {
+        let state = test_state(vec![GenMove::Resign]);
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let play = serde_json::json!({"gameId": body["gameId"], "playerMove": "D4"});
+        let (status, body) = post_json(&state, "/api/game/play", play.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["engineMove"], "resign");
+        assert_eq!(body["end"]["finished"], true);
+        assert_eq!(body["end"]["reason"], "resignation");
+        assert_eq!(body["end"]["result"], "B+R");
+        let (status, body) = post_json(&state, "/api/game/play", play).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"], "GAME_FINISHED");
+
+        let state = test_state(vec![GenMove::Play(Vertex::Pass)]);
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let game_id = body["gameId"].clone();
+        let play = |mv: &str| serde_json::json!({"gameId": game_id, "playerMove": mv});
+        let (_, body) = post_json(&state, "/api/game/play", play("D4")).await;
+        assert_eq!(body["engineMove"], "pass");
+        assert_eq!(body["end"]["finished"], false);
+        let (status, body) = post_json(&state, "/api/game/play", play("pass")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["engineMove"], serde_json::Value::Null);
+        assert_eq!(body["end"]["reason"], "doublePass");
+        assert_eq!(body["end"]["result"], "W+6.5");
+        assert_eq!(body["end"]["winner"], "white");
+    }
+
+    #[tokio::test]
+    async fn player_can_resign_once() {
+        let state = test_state(Vec::new());
+        let (_, body) = post_json(&state, "/api/game/new", serde_json::json!({})).await;
+        let id = serde_json::json!({"gameId": body["gameId"]});
+        let (status, body) = post_json(&state, "/api/game/resign", id.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["end"]["result"], "W+R");
+        let (status, _) = post_json(&state, "/api/game/resign", id.clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = post_json(&state, "/api/game/undo", id).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn profiles_are_listed_and_selectable() {
+        let state = test_state(Vec::new());
+        let (status, body) = call(&state, Method::GET, "/api/engine/profiles", "", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["default"], "3star");
+        assert_eq!(body["profiles"][0]["name"], "1star");
+
+        let (status, body) = post_json(
+            &state,
+            "/api/game/new",
+            serde_json::json!({"engineProfile": "nope"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "UNKNOWN_PROFILE");
+
+        let (status, body) = post_json(
+            &state,
+            "/api/game/new",
+            serde_json::json!({"engineProfile": "5star"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let game_id = body["gameId"].as_str().unwrap();
+        assert_eq!(state.game_store.get(game_id).unwrap().profile, "5star");
+    }
+
+    #[tokio::test]
+    async fn review_analysis_runs_on_fake_engine() {
+        let state = test_state(Vec::new());
+        let boundary = "test-boundary";
+        let form = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"sgf_file\"; filename=\"a.sgf\"\r\n\r\n\
+             (;GM[1]SZ[19]KM[7.5];B[pd];W[dp])\r\n--{boundary}--\r\n"
+        );
+        let (status, body) = call(
+            &state,
+            Method::POST,
+            "/api/review/import",
+            &format!("multipart/form-data; boundary={boundary}"),
+            form,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let review_id = body["reviewId"].as_str().unwrap().to_string();
+
+        let (status, body) = post_json(
+            &state,
+            "/api/review/analyze",
+            serde_json::json!({"reviewId": review_id, "moveIndex": 2, "maxVisits": 100}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["analysis"]["visits"], 100);
+        assert_eq!(body["analysis"]["pv"][0], "A19");
     }
-    moves
-        .get(move_index - 1)
-        .map(|m| m.color.opponent())
-        .unwrap_or_else(|| setup.to_play.unwrap_or(review::StoneColor::Black))
 }
@@ -1,4 +1,5 @@
-use crate::engine::gtp::GtpEngine;
+use crate::engine::analysis::{AnalysisQuery, AnalysisResponse};
+use crate::engine::protocol::{AnalyzeInfo, Color, Vertex, sgf_to_gtp};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -20,7 +21,6 @@ pub struct ReviewState {
     pub source: ReviewSource,
     pub raw_sgf: String,
     pub analysis_cache: HashMap<u32, KataAnalysis>,
-    pub engine: Option<Arc<GtpEngine>>,
     pub analysis_lock: Arc<tokio::sync::Mutex<()>>,
 }
 
@@ -60,6 +60,24 @@ pub enum StoneColor {
     White,
 }
 
+impl From<StoneColor> for Color {
+    fn from(color: StoneColor) -> Self {
+        match color {
+            StoneColor::Black => Color::Black,
+            StoneColor::White => Color::White,
+        }
+    }
+}
+
+impl From<Color> for StoneColor {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::Black => StoneColor::Black,
+            Color::White => StoneColor::White,
+        }
+    }
+}
+
 #[allow(dead_code)]
 impl StoneColor {
     pub fn opponent(self) -> Self {
@@ -113,12 +131,35 @@ pub struct KataAnalysis {
     pub visits: u32,
 }
 
+impl KataAnalysis {
+    /// 取 kata-analyze 候选点的评估与变化
+    pub fn from_info(info: &AnalyzeInfo) -> Self {
+        Self {
+            winrate: info.winrate,
+            score_lead: info.score_lead,
+            pv: info.pv.iter().map(|v| v.to_string()).collect(),
+            visits: info.visits,
+        }
+    }
+
+    /// 取 analysis 引擎结果的根节点评估与首选变化
+    pub fn from_response(resp: &AnalysisResponse) -> Self {
+        Self {
+            winrate: resp.root_info.winrate,
+            score_lead: resp.root_info.score_lead,
+            pv: resp.best_move().map(|m| m.pv.clone()).unwrap_or_default(),
+            visits: resp.root_info.visits,
+        }
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type", content = "value")]
 pub enum ReviewSource {
     LocalUpload,
     RemoteUrl(String),
+    Game(String), // 由本站对局转入，值为 gameId
 }
 
 #[allow(dead_code)]
@@ -143,12 +184,94 @@ impl ReviewState {
             source,
             raw_sgf,
             analysis_cache: HashMap::new(),
-            engine: None,
             analysis_lock: Arc::new(tokio::sync::Mutex::new(())),
         }
     }
 
+    /// 第 move_index 手后的局面，按落子顺序展开：初始布局（逐子摆放）+ 主线前 move_index 手
+    pub fn position_moves(&self, move_index: usize) -> Vec<(Color, Vertex)> {
+        let size = self.board_size;
+        let setup = |color: Color, coords: &[String]| -> Vec<(Color, Vertex)> {
+            coords
+                .iter()
+                .filter_map(|c| Vertex::from_sgf(c, size))
+                .filter(|v| *v != Vertex::Pass)
+                .map(|v| (color, v))
+                .collect()
+        };
+        let mut position = setup(Color::Black, &self.initial_setup.black);
+        position.extend(setup(Color::White, &self.initial_setup.white));
+        position.extend(self.moves.iter().take(move_index).map(|m| {
+            let vertex = m
+                .coord
+                .as_deref()
+                .and_then(|c| Vertex::from_sgf(c, size))
+                .unwrap_or(Vertex::Pass);
+            (Color::from(m.color), vertex)
+        }));
+        position
+    }
+
+    /// 构造 analysis 引擎查询：初始布局 + 主线全部着法，只分析第 move_index 手后的局面
+    pub fn analysis_query(&self, move_index: u32, max_visits: u32) -> AnalysisQuery {
+        let size = self.board_size;
+        let stones = |color: &str, coords: &[String]| -> Vec<(String, String)> {
+            coords
+                .iter()
+                .filter_map(|c| sgf_to_gtp(c, size))
+                .map(|v| (color.to_string(), v))
+                .collect()
+        };
+        let mut initial_stones = stones("B", &self.initial_setup.black);
+        initial_stones.extend(stones("W", &self.initial_setup.white));
+        let moves = self
+            .moves
+            .iter()
+            .map(|m| {
+                let color = match m.color {
+                    StoneColor::Black => "B",
+                    StoneColor::White => "W",
+                };
+                let vertex = m
+                    .coord
+                    .as_deref()
+                    .and_then(|c| sgf_to_gtp(c, size))
+                    .unwrap_or_else(|| "pass".to_string());
+                (color.to_string(), vertex)
+            })
+            .collect();
+        AnalysisQuery {
+            moves,
+            initial_stones,
+            initial_player: self.initial_setup.to_play.map(|c| match c {
+                StoneColor::Black => "B".to_string(),
+                StoneColor::White => "W".to_string(),
+            }),
+            rules: katago_rules_name(self.meta.rules.as_deref()),
+            komi: self.komi,
+            board_x_size: size,
+            board_y_size: size,
+            analyze_turns: Some(vec![move_index]),
+            max_visits: Some(max_visits),
+            ..Default::default()
+        }
+    }
+
     pub fn touch(&mut self) {
         self.last_active_at = time::OffsetDateTime::now_utc().unix_timestamp();
     }
 }
+
+/// SGF 的 RU 属性 → KataGo 规则名；无法识别时按 chinese 处理
+fn katago_rules_name(ru: Option<&str>) -> String {
+    let normalized = ru.unwrap_or("").trim().to_ascii_lowercase();
+    match normalized.as_str() {
+        "japanese" | "jp" => "japanese",
+        "korean" => "korean",
+        "aga" => "aga",
+        "nz" | "new zealand" | "new-zealand" => "new-zealand",
+        "tromp-taylor" | "tromp taylor" => "tromp-taylor",
+        _ => "chinese",
+    }
+    .to_string()
+}
@@ -16,64 +16,47 @@ pub fn parse_sgf(input: &str) -> Result<ParsedReview> {
     let root = &nodes[0];
 
     let mut board_size: u32 = 19;
-    if let Some(sz_val) = root.single_value("SZ") {
-        if let Ok(sz) = sz_val.parse::<u32>() {
-            if (5..=25).contains(&sz) {
-                board_size = sz;
-            }
-        }
+    if let Some(sz_val) = root.single_value("SZ")
+        && let Ok(sz) = sz_val.parse::<u32>()
+        && (5..=25).contains(&sz)
+    {
+        board_size = sz;
     }
 
     let mut komi = 0.0_f32;
-    if let Some(km_val) = root.single_value("KM") {
-        if let Ok(km) = km_val.parse::<f32>() {
-            komi = km;
-        }
-    }
-
-    let mut meta = GameMeta::default();
-    meta.black = root
-        .single_value("PB")
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty());
-    meta.white = root
-        .single_value("PW")
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty());
-    meta.result = root
-        .single_value("RE")
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty());
-    meta.rules = root
-        .single_value("RU")
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty());
-    if komi != 0.0 {
-        meta.komi = Some(komi);
-    }
-    let comment = root
-        .single_value("C")
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty());
-    meta.comment = comment;
+    if let Some(km_val) = root.single_value("KM")
+        && let Ok(km) = km_val.parse::<f32>()
+    {
+        komi = km;
+    }
+
+    let text_prop = |key: &str| {
+        root.single_value(key)
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+    let meta = GameMeta {
+        black: text_prop("PB"),
+        white: text_prop("PW"),
+        result: text_prop("RE"),
+        rules: text_prop("RU"),
+        komi: if komi != 0.0 { Some(komi) } else { None },
+        comment: text_prop("C"),
+    };
 
     let size_usize = board_size as usize;
-    let mut initial_setup = InitialSetup::default();
-    initial_setup.black = root
-        .values("AB")
-        .into_iter()
-        .filter_map(|raw| normalise_coord(&raw))
-        .collect();
-    initial_setup.white = root
-        .values("AW")
-        .into_iter()
-        .filter_map(|raw| normalise_coord(&raw))
-        .collect();
-    initial_setup.empty = root
-        .values("AE")
-        .into_iter()
-        .filter_map(|raw| normalise_coord(&raw))
-        .collect();
+    let coord_prop = |key: &str| -> Vec<String> {
+        root.values(key)
+            .into_iter()
+            .filter_map(|raw| normalise_coord(&raw))
+            .collect()
+    };
+    let mut initial_setup = InitialSetup {
+        black: coord_prop("AB"),
+        white: coord_prop("AW"),
+        empty: coord_prop("AE"),
+        to_play: None,
+    };
     if let Some(pl) = root.single_value("PL") {
         let s = pl.trim();
         if s.eq_ignore_ascii_case("B") {
@@ -86,10 +69,10 @@ pub fn parse_sgf(input: &str) -> Result<ParsedReview> {
     let mut moves: Vec<MoveNode> = Vec::new();
     let mut move_index: u32 = 0;
     for node in nodes.iter().skip(1) {
-        if let Some(mn) = node.single_value("MN") {
-            if let Ok(idx) = mn.parse::<u32>() {
-                move_index = idx.saturating_sub(1);
-            }
+        if let Some(mn) = node.single_value("MN")
+            && let Ok(idx) = mn.parse::<u32>()
+        {
+            move_index = idx.saturating_sub(1);
         }
         if let Some(value) = node.single_value("B") {
             move_index += 1;
@@ -192,7 +175,7 @@ fn board_vector_after(
     Ok(board)
 }
 
-fn apply_move(
+pub(crate) fn apply_move(
     board: &mut [Option<StoneColor>],
     size: usize,
     x: usize,
@@ -228,7 +211,7 @@ fn apply_move(
     }
 }
 
-fn collect_group(
+pub(crate) fn collect_group(
     board: &[Option<StoneColor>],
     size: usize,
     x: usize,
@@ -261,7 +244,7 @@ fn collect_group(
     (visited.into_iter().collect(), liberties.len())
 }
 
-fn neighbors(x: usize, y: usize, size: usize) -> Vec<(usize, usize)> {
+pub(crate) fn neighbors(x: usize, y: usize, size: usize) -> Vec<(usize, usize)> {
     let mut result = Vec::with_capacity(4);
     if x > 0 {
         result.push((x - 1, y));
@@ -278,7 +261,7 @@ fn neighbors(x: usize, y: usize, size: usize) -> Vec<(usize, usize)> {
     result
 }
 
-fn index(size: usize, x: usize, y: usize) -> usize {
+pub(crate) fn index(size: usize, x: usize, y: usize) -> usize {
     y * size + x
 }
 
@@ -390,7 +373,7 @@ impl<'a> SgfParser<'a> {
                 None => break,
             };
             let values = self.parse_values()?;
-            let entry = props.entry(name).or_insert_with(Vec::new);
+            let entry = props.entry(name).or_default();
             entry.extend(values);
         }
         Ok(Node { props })
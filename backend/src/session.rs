@@ -0,0 +1,224 @@
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::path::Path;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// sid Cookie 的签发与校验。Cookie 值为 `<sid>.<签发时间>.<签名>`，签名为以服务端密钥
+/// 对前两段做的 HMAC-SHA256（十六进制）；客户端无法自造或改写 sid
+#[derive(Debug)]
+pub struct SessionKeys {
+    current: Vec<u8>,
+    previous: Vec<Vec<u8>>, // 轮换下来的旧密钥：仍可校验，通过后改用当前密钥重签
+    ttl_seconds: i64,
+    secure: bool, // Set-Cookie 是否带 Secure（仅 HTTPS 下回传）
+}
+
+/// 校验通过的 Cookie
+#[derive(Debug, PartialEq, Eq)]
+pub struct Session {
+    pub sid: String,
+    pub refresh: bool, // 由旧密钥签发或已过半有效期，应重签
+}
+
+impl SessionKeys {
+    pub fn new(current: Vec<u8>, previous: Vec<Vec<u8>>, ttl_seconds: i64, secure: bool) -> Self {
+        Self {
+            current,
+            previous,
+            ttl_seconds,
+            secure,
+        }
+    }
+
+    /// 未配置密钥时使用随机密钥：重启后此前签发的 Cookie 全部失效
+    pub fn random(ttl_seconds: i64, secure: bool) -> Self {
+        let key: [u8; 32] = rand::random();
+        Self::new(key.to_vec(), Vec::new(), ttl_seconds, secure)
+    }
+
+    /// 未配置密钥时沿用 path 中保存的随机密钥（十六进制），没有则生成并写入：
+    /// 重启后已签发的 Cookie 与恢复的对局仍归原 sid 所有
+    pub fn load_or_generate(
+        path: &Path,
+        previous: Vec<Vec<u8>>,
+        ttl_seconds: i64,
+        secure: bool,
+    ) -> anyhow::Result<Self> {
+        let key = match std::fs::read_to_string(path) {
+            Ok(text) => decode_hex(text.trim())
+                .filter(|key| !key.is_empty())
+                .with_context(|| format!("malformed session key in {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let key: [u8; 32] = rand::random();
+                write_key(path, &key)
+                    .with_context(|| format!("write session key {}", path.display()))?;
+                key.to_vec()
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("read session key {}", path.display()));
+            }
+        };
+        Ok(Self::new(key, previous, ttl_seconds, secure))
+    }
+
+    /// 用当前密钥签发 Cookie 值
+    pub fn sign(&self, sid: &str, issued_at: i64) -> String {
+        let payload = format!("{sid}.{issued_at}");
+        let tag = mac(&self.current, &payload).finalize().into_bytes();
+        let mut value = payload;
+        value.push('.');
+        for byte in tag.iter() {
+            value.push_str(&format!("{:02x}", byte));
+        }
+        value
+    }
+
+    /// 校验 Cookie 值；签名不符（篡改或密钥已撤下）、已过期或格式不符时为 None
+    pub fn verify(&self, value: &str, now: i64) -> Option<Session> {
+        let (payload, tag) = value.rsplit_once('.')?;
+        let (sid, issued_at) = payload.rsplit_once('.')?;
+        let issued_at: i64 = issued_at.parse().ok()?;
+        if sid.is_empty() || now - issued_at > self.ttl_seconds {
+            return None;
+        }
+        let tag = decode_hex(tag)?;
+        let current = mac(&self.current, payload).verify_slice(&tag).is_ok();
+        if !current
+            && !self
+                .previous
+                .iter()
+                .any(|key| mac(key, payload).verify_slice(&tag).is_ok())
+        {
+            return None;
+        }
+        Some(Session {
+            sid: sid.to_string(),
+            refresh: !current || now - issued_at > self.ttl_seconds / 2,
+        })
+    }
+
+    /// 签发（或重签）sid 的 Set-Cookie 头
+    pub fn set_cookie(&self, sid: &str, now: i64) -> String {
+        let mut cookie = format!(
+            "sid={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax",
+            self.sign(sid, now),
+            self.ttl_seconds
+        );
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+}
+
+fn mac(key: &[u8], payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+// 密钥文件只允许本用户读写
+fn write_key(path: &Path, key: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    options.open(path)?.write_all(hex.as_bytes())
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 3600;
+
+    fn keys(current: &str, previous: &[&str]) -> SessionKeys {
+        SessionKeys::new(
+            current.as_bytes().to_vec(),
+            previous.iter().map(|k| k.as_bytes().to_vec()).collect(),
+            30 * DAY,
+            false,
+        )
+    }
+
+    #[test]
+    fn signed_sid_round_trips_and_rejects_tampering() {
+        let keys = keys("secret", &[]);
+        let value = keys.sign("abc", 1000);
+        let session = keys.verify(&value, 1000 + DAY).unwrap();
+        assert_eq!(session.sid, "abc");
+        assert!(!session.refresh);
+
+        // 改 sid、改签发时间、改签名、裸 sid 都不被接受
+        assert_eq!(keys.verify(&value.replacen("abc", "abd", 1), 1000), None);
+        assert_eq!(
+            keys.verify(&value.replacen(".1000.", ".9000.", 1), 1000),
+            None
+        );
+        let mut forged = value.clone();
+        let last = if forged.ends_with('0') { "1" } else { "0" };
+        forged.replace_range(forged.len() - 1.., last);
+        assert_eq!(keys.verify(&forged, 1000), None);
+        assert_eq!(keys.verify("abc", 1000), None);
+        // 其他密钥签发的不被接受
+        assert_eq!(self::keys("other", &[]).verify(&value, 1000), None);
+    }
+
+    #[test]
+    fn generated_key_is_reused_after_restart() {
+        let dir = std::env::temp_dir().join(format!("session-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("session.key");
+        let first = SessionKeys::load_or_generate(&path, Vec::new(), DAY, false).unwrap();
+        let value = first.sign("abc", 0);
+        let again = SessionKeys::load_or_generate(&path, Vec::new(), DAY, false).unwrap();
+        assert_eq!(again.verify(&value, 0).unwrap().sid, "abc");
+
+        std::fs::write(&path, "not hex").unwrap();
+        assert!(SessionKeys::load_or_generate(&path, Vec::new(), DAY, false).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn old_cookies_expire_and_rotated_keys_still_verify() {
+        let old = keys("old", &[]);
+        let value = old.sign("abc", 0);
+        // 过半有效期后要求重签，过期后拒绝
+        assert!(old.verify(&value, 20 * DAY).unwrap().refresh);
+        assert_eq!(old.verify(&value, 31 * DAY), None);
+
+        // 轮换：旧密钥签发的仍可用，但要求以新密钥重签
+        let rotated = keys("new", &["old"]);
+        let session = rotated.verify(&value, DAY).unwrap();
+        assert_eq!(session.sid, "abc");
+        assert!(session.refresh);
+        assert!(
+            !rotated
+                .verify(&rotated.sign("abc", DAY), DAY)
+                .unwrap()
+                .refresh
+        );
+        assert!(!rotated.set_cookie("abc", DAY).contains("Secure"));
+        assert!(
+            SessionKeys::new(b"k".to_vec(), Vec::new(), DAY, true)
+                .set_cookie("abc", 0)
+                .ends_with("; Secure")
+        );
+    }
+}